@@ -0,0 +1,49 @@
+use std::time::Instant;
+
+use flynes::cartridges;
+
+const NESTEST_START_PC: u16 = 0xC000;
+const NUMBER_OF_INSTRUCTIONS: usize = 50_000_000;
+
+fn main() {
+    pretty_env_logger::init();
+
+    let (
+        mut cpu_unit,
+        mut bus_unit
+    ) = match cartridges::read_nes_file("./../../roms/nestest.nes".into()) {
+        Ok(modules) => modules,
+        Err(err) => {
+            println!("Error occured, see log");
+            println!("Error: {err}");
+            return
+        }
+    };
+
+    cpu_unit.set_pc(NESTEST_START_PC);
+
+    let mut restarts: usize = 0;
+    let mut executed_cycles: usize = 0;
+
+    let t = Instant::now();
+
+    for _ in 0..NUMBER_OF_INSTRUCTIONS {
+        match cpu_unit.execute_cpu_iteration(&mut bus_unit) {
+            Ok(cycles) => executed_cycles += cycles,
+            Err(_) => {
+                // nestest in automation mode ends with unofficial opcodes and RTS to nowhere, restart it
+                cpu_unit.set_pc(NESTEST_START_PC);
+                restarts += 1;
+            }
+        }
+    }
+
+    let elapsed = t.elapsed();
+    let mips = NUMBER_OF_INSTRUCTIONS as f64 / elapsed.as_secs_f64() / 1_000_000.0;
+    let emulated_mhz = executed_cycles as f64 / elapsed.as_secs_f64() / 1_000_000.0;
+
+    println!("Instructions: {NUMBER_OF_INSTRUCTIONS} (nestest restarts: {restarts})");
+    println!("Elapsed: {elapsed:?}");
+    println!("MIPS: {mips:.2}");
+    println!("Emulated CPU clock: {emulated_mhz:.2} MHz");
+}
@@ -0,0 +1,332 @@
+use crate::common::is_bit_set;
+use crate::region::Region;
+use pulse::{Pulse, PulseChannel};
+use triangle::Triangle;
+use noise::Noise;
+use dmc::{Dmc, DMA_STALL_CYCLES};
+use frame_counter::{FrameCounter, FrameClock};
+
+mod pulse;
+mod triangle;
+mod noise;
+mod dmc;
+mod frame_counter;
+mod units;
+
+pub const APU_STATUS_ADDRESS: usize = 0x4015;
+const PULSE_1_START: usize = 0x4000;
+const PULSE_2_END: usize = 0x4007;
+const TRIANGLE_START: usize = 0x4008;
+const TRIANGLE_END: usize = 0x400B;
+const NOISE_START: usize = 0x400C;
+const NOISE_END: usize = 0x400F;
+const DMC_START: usize = 0x4010;
+const DMC_END: usize = 0x4013;
+pub const FRAME_COUNTER_ADDRESS: usize = 0x4017;
+
+const STATUS_PULSE_1: u8 = 0b0000_0001;
+const STATUS_PULSE_2: u8 = 0b0000_0010;
+const STATUS_TRIANGLE: u8 = 0b0000_0100;
+const STATUS_NOISE: u8 = 0b0000_1000;
+const STATUS_DMC: u8 = 0b0001_0000;
+const STATUS_FRAME_IRQ: u8 = 0b0100_0000;
+const STATUS_DMC_IRQ: u8 = 0b1000_0000;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// 2A03 audio unit. Channels are clocked by CPU cycles and mixed into
+/// samples at the output sample rate while sample output is enabled
+#[derive(Debug, Clone)]
+pub struct Apu {
+    pulses: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    region: Region,
+    /// CPU cycles since power on, pulse timers are clocked on every second one
+    cycles: u64,
+    /// CPU cycles stolen by DMC sample fetches
+    dma_stall_cycles: usize,
+    sample_rate: u32,
+    /// Sample is produced when it exceeds CPU clock, incremented by sample rate every cycle
+    sample_clock: u64,
+    sample_sum: f32,
+    sample_cycles: u32,
+    /// Samples are only kept when somebody takes them, otherwise they would pile up
+    sample_output: bool,
+    samples: Vec<f32>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self {
+            pulses: [Pulse::new(PulseChannel::First), Pulse::new(PulseChannel::Second)],
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_counter: FrameCounter::default(),
+            region: Region::default(),
+            cycles: 0,
+            dma_stall_cycles: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_clock: 0,
+            sample_sum: 0.0,
+            sample_cycles: 0,
+            sample_output: false,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl Apu {
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Disabling drops samples which were not taken yet
+    pub fn set_sample_output(&mut self, enabled: bool) {
+        self.sample_output = enabled;
+        if !enabled {
+            self.samples = Vec::new();
+        }
+    }
+
+    /// Reset silences all channels like a write of 0 to $4015 and restarts frame counter
+    pub fn reset(&mut self) {
+        self.write_register(APU_STATUS_ADDRESS, 0);
+        self.frame_counter.reset(self.is_odd_cycle());
+    }
+
+    fn is_odd_cycle(&self) -> bool {
+        !self.cycles.is_multiple_of(2)
+    }
+
+    pub fn write_register(&mut self, address: usize, value: u8) {
+        match address {
+            PULSE_1_START..=PULSE_2_END => {
+                let offset = address - PULSE_1_START;
+                self.pulses[offset / 4].write_register(offset % 4, value);
+            },
+            TRIANGLE_START..=TRIANGLE_END => self.triangle.write_register(address - TRIANGLE_START, value),
+            NOISE_START..=NOISE_END => self.noise.write_register(address - NOISE_START, value, self.region),
+            DMC_START..=DMC_END => self.dmc.write_register(address - DMC_START, value, self.region),
+            APU_STATUS_ADDRESS => {
+                self.pulses[0].set_enabled(is_bit_set(value, STATUS_PULSE_1));
+                self.pulses[1].set_enabled(is_bit_set(value, STATUS_PULSE_2));
+                self.triangle.set_enabled(is_bit_set(value, STATUS_TRIANGLE));
+                self.noise.set_enabled(is_bit_set(value, STATUS_NOISE));
+                self.dmc.set_enabled(is_bit_set(value, STATUS_DMC));
+            },
+            FRAME_COUNTER_ADDRESS => self.frame_counter.write(value, self.is_odd_cycle()),
+            _ => {},
+        }
+    }
+
+    /// $4015 read, bits are set for channels with non-zero length counter
+    /// and DMC with sample bytes left. Clears frame interrupt flag
+    pub fn read_status(&mut self) -> u8 {
+        let status_bits = [
+            (self.pulses[0].is_active(), STATUS_PULSE_1),
+            (self.pulses[1].is_active(), STATUS_PULSE_2),
+            (self.triangle.is_active(), STATUS_TRIANGLE),
+            (self.noise.is_active(), STATUS_NOISE),
+            (self.dmc.is_active(), STATUS_DMC),
+            (self.frame_counter.irq_flag(), STATUS_FRAME_IRQ),
+            (self.dmc.irq_flag(), STATUS_DMC_IRQ),
+        ];
+        self.frame_counter.clear_irq_flag();
+
+        status_bits.iter()
+            .filter(|(is_set, _)| *is_set)
+            .fold(0, |status, (_, bit)| status | bit)
+    }
+
+    /// Level of the APU output to CPU IRQ line
+    pub fn irq_asserted(&self) -> bool {
+        self.frame_counter.irq_flag() || self.dmc.irq_flag()
+    }
+
+    /// CPU cycles stolen by DMC DMA since the last call
+    pub fn take_dma_stall_cycles(&mut self) -> usize {
+        std::mem::take(&mut self.dma_stall_cycles)
+    }
+
+    /// DMC sample bytes are fetched with `dma_read` from CPU address space
+    pub fn execute_cycles(&mut self, cycles_num: usize, dma_read: &mut impl FnMut(u16) -> u8) {
+        for _ in 0..cycles_num {
+            self.execute_cycle(dma_read);
+        }
+    }
+
+    fn execute_cycle(&mut self, dma_read: &mut impl FnMut(u16) -> u8) {
+        if self.is_odd_cycle() {
+            self.pulses.iter_mut().for_each(Pulse::clock_timer);
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if let Some(address) = self.dmc.dma_request() {
+            self.dmc.complete_dma(dma_read(address));
+            self.dma_stall_cycles += DMA_STALL_CYCLES;
+        }
+
+        match self.frame_counter.clock(self.region) {
+            FrameClock::None => {},
+            FrameClock::Quarter => self.clock_quarter_frame(),
+            FrameClock::Half => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            },
+        }
+        self.cycles += 1;
+
+        if !self.sample_output {
+            return
+        }
+        self.sample_sum += self.mix();
+        self.sample_cycles += 1;
+        self.sample_clock += self.sample_rate as u64;
+        let cpu_clock_hz = self.region.cpu_clock_hz() as u64;
+        if self.sample_clock >= cpu_clock_hz {
+            self.sample_clock -= cpu_clock_hz;
+            self.samples.push(self.sample_sum / self.sample_cycles as f32);
+            self.sample_sum = 0.0;
+            self.sample_cycles = 0;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulses.iter_mut().for_each(Pulse::clock_quarter_frame);
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulses.iter_mut().for_each(Pulse::clock_half_frame);
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// Nonlinear mixer approximation from 2A03 DAC, output is in 0.0-1.0 range
+    fn mix(&self) -> f32 {
+        let pulse_sum = (self.pulses[0].output() + self.pulses[1].output()) as f32;
+        let pulse_out = if pulse_sum == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse_sum + 100.0)
+        };
+
+        let tnd_sum = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd_sum == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd_sum + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+
+    /// Mixed output since the last take
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+#[test]
+fn test_apu_status() {
+    let mut apu = Apu::default();
+    // Length is loaded only for enabled channels
+    apu.write_register(0x4003, 0b0000_1000);
+    apu.write_register(0x4007, 0b0000_1000);
+    assert_eq!(apu.read_status(), 0);
+
+    apu.write_register(APU_STATUS_ADDRESS, STATUS_PULSE_1 | STATUS_PULSE_2);
+    apu.write_register(0x4000, 0b0011_1111);
+    apu.write_register(0x4003, 0b0000_1000);
+    apu.write_register(0x4007, 0b0001_1000);
+    assert_eq!(apu.read_status(), STATUS_PULSE_1 | STATUS_PULSE_2);
+
+    // Pulse 2 length 2 ends after 2 half frames, halted pulse 1 keeps playing
+    apu.execute_cycles(29829 - 2, &mut |_| 0);
+    assert_eq!(apu.read_status(), STATUS_PULSE_1 | STATUS_PULSE_2);
+    apu.execute_cycles(2, &mut |_| 0);
+    assert_eq!(apu.read_status(), STATUS_PULSE_1 | STATUS_FRAME_IRQ);
+    assert!(!apu.irq_asserted());
+
+    apu.write_register(APU_STATUS_ADDRESS, STATUS_PULSE_2 | STATUS_TRIANGLE | STATUS_NOISE);
+    apu.write_register(0x400B, 0b0000_1000);
+    apu.write_register(0x400F, 0b0000_1000);
+    assert_eq!(apu.read_status(), STATUS_TRIANGLE | STATUS_NOISE);
+}
+
+#[test]
+fn test_frame_irq() {
+    let mut apu = Apu::default();
+    apu.execute_cycles(1, &mut |_| 0);
+    // Write on odd cycle restarts the sequence 4 cycles later
+    apu.write_register(FRAME_COUNTER_ADDRESS, 0x00);
+    apu.execute_cycles(4 + 29828 - 1, &mut |_| 0);
+    assert!(!apu.irq_asserted());
+    apu.execute_cycles(1, &mut |_| 0);
+    assert!(apu.irq_asserted());
+
+    // Flag is set again on the next 2 cycles even after $4015 read
+    assert_eq!(apu.read_status() & STATUS_FRAME_IRQ, STATUS_FRAME_IRQ);
+    apu.execute_cycles(1, &mut |_| 0);
+    assert!(apu.irq_asserted());
+    apu.execute_cycles(1, &mut |_| 0);
+    apu.read_status();
+    apu.execute_cycles(1, &mut |_| 0);
+    assert!(!apu.irq_asserted());
+
+    // IRQ inhibit acknowledges the interrupt, 5-step mode doesn't raise it
+    apu.execute_cycles(29830, &mut |_| 0);
+    assert!(apu.irq_asserted());
+    apu.write_register(FRAME_COUNTER_ADDRESS, 0b0100_0000);
+    assert!(!apu.irq_asserted());
+    apu.write_register(FRAME_COUNTER_ADDRESS, 0b1000_0000);
+    apu.execute_cycles(3 * 37282, &mut |_| 0);
+    assert!(!apu.irq_asserted());
+
+    // Reset keeps the mode
+    apu.reset();
+    apu.execute_cycles(3 * 37282, &mut |_| 0);
+    assert!(!apu.irq_asserted());
+}
+
+#[test]
+fn test_apu_output() {
+    let mut apu = Apu::default();
+    apu.execute_cycles(1000, &mut |_| 0);
+    assert!(apu.take_samples().is_empty());
+
+    apu.set_sample_output(true);
+    apu.execute_cycles(Region::Ntsc.cpu_clock_hz() as usize / 10, &mut |_| 0);
+    let silence = apu.take_samples();
+    assert!((4409..=4410).contains(&silence.len()));
+    // Stopped triangle holds its level, so silence is a constant offset
+    assert!(silence.iter().all(|&sample| sample == silence[0]));
+
+    // 50% duty with constant volume 15 at period $FD (about 440 Hz)
+    apu.write_register(APU_STATUS_ADDRESS, STATUS_PULSE_1);
+    apu.write_register(0x4000, 0b1011_1111);
+    apu.write_register(0x4002, 0xFD);
+    apu.write_register(0x4003, 0b1111_1000);
+    apu.execute_cycles(Region::Ntsc.cpu_clock_hz() as usize / 10, &mut |_| 0);
+    let tone = apu.take_samples();
+    let peak = tone.iter().cloned().fold(0.0, f32::max);
+    assert!((peak - silence[0] - 95.88 / (8128.0 / 15.0 + 100.0)).abs() < 1e-6);
+
+    let middle = (peak + silence[0]) / 2.0;
+    let rising_edges = tone.windows(2).filter(|pair| pair[0] < middle && pair[1] >= middle).count();
+    assert!((43..=45).contains(&rising_edges), "{rising_edges} periods in 100 ms");
+
+    assert!(apu.take_samples().is_empty());
+}
@@ -0,0 +1,237 @@
+use crate::common::is_bit_set;
+use crate::region::Region;
+
+/// Output timer periods in CPU cycles selected by the lower 4 bits of $4010
+const NTSC_RATE_TABLE: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const PAL_RATE_TABLE: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+const FLAGS_IRQ_ENABLED: u8 = 0b1000_0000;
+const FLAGS_LOOP: u8 = 0b0100_0000;
+const FLAGS_RATE_MASK: u8 = 0b0000_1111;
+const DIRECT_LOAD_MASK: u8 = 0b0111_1111;
+
+const SAMPLE_ADDRESS_START: u16 = 0xC000;
+const SAMPLE_ADDRESS_STEP: u16 = 64;
+const SAMPLE_LENGTH_STEP: u16 = 16;
+/// Sample address wraps from $FFFF to $8000
+const SAMPLE_ADDRESS_WRAP: u16 = 0x8000;
+
+/// CPU is halted for 4 cycles on every sample fetch, shorter stalls next to writes and OAM DMA are not emulated
+pub(super) const DMA_STALL_CYCLES: usize = 4;
+
+const MAX_OUTPUT_LEVEL: u8 = 127;
+
+/// Delta modulation channel at $4010-$4013, plays 1-bit samples fetched from $8000-$FFFF
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Dmc {
+    irq_enabled: bool,
+    looped: bool,
+    irq_flag: bool,
+    timer_period: u16,
+    timer: u16,
+    /// 7-bit output level
+    output_level: u8,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    sample_buffer: Option<u8>,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            irq_enabled: false,
+            looped: false,
+            irq_flag: false,
+            timer_period: NTSC_RATE_TABLE[0] - 1,
+            timer: 0,
+            output_level: 0,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            sample_buffer: None,
+            sample_address: SAMPLE_ADDRESS_START,
+            sample_length: 1,
+            current_address: SAMPLE_ADDRESS_START,
+            bytes_remaining: 0,
+        }
+    }
+}
+
+impl Dmc {
+    /// Register index is the address offset from $4010
+    pub(super) fn write_register(&mut self, register: usize, value: u8, region: Region) {
+        match register {
+            0 => {
+                self.irq_enabled = is_bit_set(value, FLAGS_IRQ_ENABLED);
+                if !self.irq_enabled {
+                    self.irq_flag = false;
+                }
+                self.looped = is_bit_set(value, FLAGS_LOOP);
+                let rate_table = match region {
+                    Region::Pal => PAL_RATE_TABLE,
+                    Region::Ntsc | Region::Dendy => NTSC_RATE_TABLE,
+                };
+                self.timer_period = rate_table[(value & FLAGS_RATE_MASK) as usize] - 1;
+            },
+            1 => self.output_level = value & DIRECT_LOAD_MASK,
+            2 => self.sample_address = SAMPLE_ADDRESS_START + value as u16 * SAMPLE_ADDRESS_STEP,
+            3 => self.sample_length = value as u16 * SAMPLE_LENGTH_STEP + 1,
+            _ => unreachable!("DMC has only 4 registers"),
+        }
+    }
+
+    /// Enabling restarts the sample only if the previous one has ended, disabling stops it
+    /// after the buffered byte. Any $4015 write acknowledges DMC interrupt
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart_sample();
+        }
+    }
+
+    fn restart_sample(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Sample bytes are still to be fetched
+    pub(super) fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub(super) fn irq_flag(&self) -> bool {
+        self.irq_flag
+    }
+
+    /// Address of the next sample byte if empty buffer waits for DMA
+    pub(super) fn dma_request(&self) -> Option<u16> {
+        (self.sample_buffer.is_none() && self.bytes_remaining > 0).then_some(self.current_address)
+    }
+
+    /// Stores byte fetched from address given by `dma_request`
+    pub(super) fn complete_dma(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(SAMPLE_ADDRESS_WRAP);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looped {
+                self.restart_sample();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle, output level moves by 2 per sample bit
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return
+        }
+        self.timer = self.timer_period;
+
+        if !self.silence {
+            if is_bit_set(self.shift_register, 1) {
+                if self.output_level <= MAX_OUTPUT_LEVEL - 2 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                },
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// Level 0-127
+    pub(super) fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+#[test]
+fn test_dmc() {
+    let mut dmc = Dmc::default();
+    // Fastest rate, sample at $C040 of 17 bytes, IRQ at the end
+    for (register, value) in [(0, FLAGS_IRQ_ENABLED | 0x0F), (1, 0x40), (2, 0x01), (3, 0x01)] {
+        dmc.write_register(register, value, Region::Ntsc);
+    }
+    assert_eq!(dmc.timer_period, 53);
+    assert_eq!(dmc.output(), 0x40);
+    assert_eq!(dmc.dma_request(), None);
+
+    dmc.set_enabled(true);
+    assert!(dmc.is_active());
+    assert_eq!(dmc.dma_request(), Some(0xC040));
+    dmc.complete_dma(0b0000_0111);
+    assert_eq!(dmc.dma_request(), None);
+
+    // Buffer goes to the shift register after the first 8 silent bits, then it's refilled
+    let mut levels = Vec::new();
+    for now_bit in 0..16 {
+        (0..54).for_each(|_| dmc.clock_timer());
+        levels.push(dmc.output());
+        if now_bit == 7 {
+            assert_eq!(dmc.dma_request(), Some(0xC041));
+        }
+    }
+    assert!(levels[..8].iter().all(|&level| level == 0x40));
+    assert_eq!(levels[8..], [0x42, 0x44, 0x46, 0x44, 0x42, 0x40, 0x3E, 0x3C]);
+
+    // The last fetch raises IRQ, clearing IRQ enable acknowledges it
+    for _ in 0..16 {
+        dmc.complete_dma(0x00);
+        dmc.sample_buffer = None;
+    }
+    assert!(!dmc.is_active());
+    assert!(dmc.irq_flag());
+    dmc.write_register(0, 0x0F, Region::Ntsc);
+    assert!(!dmc.irq_flag());
+
+    dmc.set_enabled(true);
+    dmc.set_enabled(false);
+    assert!(!dmc.is_active());
+
+    // Looped sample restarts from its address without IRQ, fetch address wraps to $8000
+    dmc.write_register(0, FLAGS_LOOP, Region::Pal);
+    dmc.write_register(2, 0xFF, Region::Pal);
+    dmc.write_register(3, 0x04, Region::Pal);
+    assert_eq!(dmc.timer_period, 397);
+    dmc.set_enabled(true);
+    let mut fetched = Vec::new();
+    for _ in 0..66 {
+        fetched.push(dmc.dma_request().unwrap());
+        dmc.complete_dma(0x00);
+        dmc.sample_buffer = None;
+    }
+    assert_eq!(fetched[..2], [0xFFC0, 0xFFC1]);
+    assert_eq!(fetched[63..], [0xFFFF, 0x8000, 0xFFC0]);
+    assert!(!dmc.irq_flag());
+
+    // Output level saturates at 0 and 127
+    dmc.write_register(1, 0x7F, Region::Pal);
+    dmc.silence = false;
+    dmc.shift_register = 0xFF;
+    dmc.timer = 0;
+    dmc.clock_timer();
+    assert_eq!(dmc.output(), 0x7F);
+}
@@ -0,0 +1,160 @@
+use crate::common::is_bit_set;
+use crate::region::Region;
+
+const MODE_FIVE_STEP: u8 = 0b1000_0000;
+const IRQ_INHIBIT: u8 = 0b0100_0000;
+
+/// CPU cycles after the sequence restart of 4 quarter frame clocks in 4-step mode,
+/// 5-step mode skips the 4th one and clocks the 5th
+const NTSC_FRAME_STEPS: [usize; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [usize; 5] = [8313, 16627, 24939, 33253, 41565];
+
+/// New mode takes effect 3 CPU cycles after the write on even cycle and 4 after the write on odd one
+const WRITE_DELAY_EVEN: u8 = 3;
+const WRITE_DELAY_ODD: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FrameClock {
+    None,
+    /// Envelopes and triangle linear counter
+    Quarter,
+    /// Quarter frame units plus length counters and sweeps
+    Half,
+}
+
+/// $4017 frame sequencer which clocks channel units and raises frame IRQ in 4-step mode
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    irq_flag: bool,
+    /// CPU cycles since the sequence restart
+    cycle: usize,
+    /// Mode written to $4017 and CPU cycles until the sequence restarts with it
+    pending_write: Option<(bool, u8)>,
+}
+
+impl FrameCounter {
+    /// IRQ inhibit acts immediately, mode and sequence restart are delayed
+    pub(super) fn write(&mut self, value: u8, odd_cycle: bool) {
+        self.irq_inhibit = is_bit_set(value, IRQ_INHIBIT);
+        if self.irq_inhibit {
+            self.irq_flag = false;
+        }
+
+        let delay = if odd_cycle { WRITE_DELAY_ODD } else { WRITE_DELAY_EVEN };
+        self.pending_write = Some((is_bit_set(value, MODE_FIVE_STEP), delay));
+    }
+
+    /// Reset restarts the sequence with the last written mode
+    pub(super) fn reset(&mut self, odd_cycle: bool) {
+        self.irq_flag = false;
+        let mode = if self.five_step { MODE_FIVE_STEP } else { 0 };
+        let inhibit = if self.irq_inhibit { IRQ_INHIBIT } else { 0 };
+        self.write(mode | inhibit, odd_cycle);
+    }
+
+    pub(super) fn irq_flag(&self) -> bool {
+        self.irq_flag
+    }
+
+    /// Done by $4015 read
+    pub(super) fn clear_irq_flag(&mut self) {
+        self.irq_flag = false;
+    }
+
+    /// Runs one CPU cycle and returns units to clock on it
+    pub(super) fn clock(&mut self, region: Region) -> FrameClock {
+        let steps = match region {
+            Region::Pal => PAL_FRAME_STEPS,
+            Region::Ntsc | Region::Dendy => NTSC_FRAME_STEPS,
+        };
+
+        self.cycle += 1;
+        let mut frame_clock = match self.cycle {
+            cycle if cycle == steps[0] || cycle == steps[2] => FrameClock::Quarter,
+            cycle if cycle == steps[1] => FrameClock::Half,
+            cycle if cycle == steps[3] && !self.five_step => FrameClock::Half,
+            cycle if cycle == steps[4] && self.five_step => FrameClock::Half,
+            _ => FrameClock::None,
+        };
+
+        // 4-step mode raises IRQ on 3 cycles around the last step, the last one starts the next sequence
+        if !self.five_step && (steps[3] - 1..=steps[3] + 1).contains(&self.cycle) && !self.irq_inhibit {
+            self.irq_flag = true;
+        }
+        let sequence_end = if self.five_step { steps[4] + 1 } else { steps[3] + 1 };
+        if self.cycle == sequence_end {
+            self.cycle = 0;
+        }
+
+        if let Some((five_step, delay)) = self.pending_write {
+            if delay > 1 {
+                self.pending_write = Some((five_step, delay - 1));
+            } else {
+                self.pending_write = None;
+                self.five_step = five_step;
+                self.cycle = 0;
+                // Switch to 5-step mode clocks all units immediately
+                if five_step {
+                    frame_clock = FrameClock::Half;
+                }
+            }
+        }
+
+        frame_clock
+    }
+}
+
+#[test]
+fn test_frame_counter() {
+    use FrameClock::{Quarter, Half};
+
+    // Cycles of the events in 2 sequences after the write on even cycle
+    let sequence_events = |value: u8, region: Region| {
+        let mut frame_counter = FrameCounter::default();
+        frame_counter.write(value, false);
+        let mut events = Vec::new();
+        for now_cycle in 1..=2 * 37_400 {
+            let frame_clock = frame_counter.clock(region);
+            if frame_clock != FrameClock::None {
+                events.push((now_cycle, frame_clock));
+            }
+            if frame_counter.irq_flag() {
+                events.push((now_cycle, FrameClock::None));
+                frame_counter.clear_irq_flag();
+            }
+        }
+        events
+    };
+
+    let irq = FrameClock::None;
+    let four_step = sequence_events(0, Region::Ntsc);
+    assert_eq!(four_step[..9], [
+        (3 + 7457, Quarter), (3 + 14913, Half), (3 + 22371, Quarter),
+        (3 + 29828, irq), (3 + 29829, Half), (3 + 29829, irq), (3 + 29830, irq),
+        (3 + 29830 + 7457, Quarter), (3 + 29830 + 14913, Half),
+    ]);
+
+    // 5-step mode clocks units right after the write and never raises IRQ
+    let five_step = sequence_events(MODE_FIVE_STEP, Region::Ntsc);
+    assert_eq!(five_step[..6], [
+        (3, Half), (3 + 7457, Quarter), (3 + 14913, Half), (3 + 22371, Quarter),
+        (3 + 37281, Half), (3 + 37282 + 7457, Quarter),
+    ]);
+    assert!(!five_step.contains(&(3 + 29829, irq)));
+
+    let inhibited = sequence_events(IRQ_INHIBIT, Region::Pal);
+    assert_eq!(inhibited[..4], [
+        (3 + 8313, Quarter), (3 + 16627, Half), (3 + 24939, Quarter), (3 + 33253, Half),
+    ]);
+    assert!(!inhibited.iter().any(|&(_, frame_clock)| frame_clock == irq));
+
+    // Write on odd cycle takes effect one cycle later, inhibit clears pending IRQ at once
+    let mut frame_counter = FrameCounter::default();
+    frame_counter.write(0, true);
+    let cycles_to_irq = (1..).find(|_| { frame_counter.clock(Region::Ntsc); frame_counter.irq_flag() });
+    assert_eq!(cycles_to_irq, Some(4 + 29828));
+    frame_counter.write(IRQ_INHIBIT, false);
+    assert!(!frame_counter.irq_flag());
+}
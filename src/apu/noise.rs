@@ -0,0 +1,147 @@
+use crate::common::is_bit_set;
+use crate::region::Region;
+use super::units::{Envelope, LengthCounter};
+
+/// Timer periods in CPU cycles selected by the lower 4 bits of $400E
+const NTSC_PERIOD_TABLE: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const PAL_PERIOD_TABLE: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+
+const CONTROL_LENGTH_HALT: u8 = 0b0010_0000;
+/// Short mode takes the feedback from bit 6 instead of bit 1, which gives 93-step sequences
+const PERIOD_MODE: u8 = 0b1000_0000;
+const PERIOD_INDEX_MASK: u8 = 0b0000_1111;
+const LENGTH_INDEX_SHIFT: u8 = 3;
+
+const SHORT_MODE_TAP: u8 = 6;
+const LONG_MODE_TAP: u8 = 1;
+const FEEDBACK_SHIFT: u8 = 14;
+
+/// Pseudo-random noise channel at $400C-$400F
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Noise {
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    /// 15-bit linear feedback shift register
+    shift_register: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            short_mode: false,
+            timer_period: NTSC_PERIOD_TABLE[0] - 1,
+            timer: 0,
+            shift_register: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    /// Register index is the address offset from $400C, $400D is unused
+    pub(super) fn write_register(&mut self, register: usize, value: u8, region: Region) {
+        match register {
+            0 => {
+                self.length.set_halted(is_bit_set(value, CONTROL_LENGTH_HALT));
+                self.envelope.write_control(value);
+            },
+            1 => {},
+            2 => {
+                self.short_mode = is_bit_set(value, PERIOD_MODE);
+                let period_table = match region {
+                    Region::Pal => PAL_PERIOD_TABLE,
+                    Region::Ntsc | Region::Dendy => NTSC_PERIOD_TABLE,
+                };
+                self.timer_period = period_table[(value & PERIOD_INDEX_MASK) as usize] - 1;
+            },
+            3 => {
+                self.length.load(value >> LENGTH_INDEX_SHIFT);
+                self.envelope.restart();
+            },
+            _ => unreachable!("Noise channel has only 4 registers"),
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.length.is_active()
+    }
+
+    /// Clocked every CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            let tap = if self.short_mode { SHORT_MODE_TAP } else { LONG_MODE_TAP };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << FEEDBACK_SHIFT);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    /// Volume 0-15, silent while bit 0 of shift register is set
+    pub(super) fn output(&self) -> u8 {
+        if is_bit_set(self.shift_register, 1) || !self.length.is_active() {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[test]
+fn test_noise() {
+    // Number of timer reloads until shift register comes back to the initial state
+    let sequence_length = |period_value: u8| {
+        let mut noise = Noise::default();
+        noise.write_register(2, period_value, Region::Ntsc);
+        let mut reloads = 0;
+        loop {
+            (0..4).for_each(|_| noise.clock_timer());
+            reloads += 1;
+            if noise.shift_register == 1 {
+                return reloads
+            }
+        }
+    };
+    assert_eq!(sequence_length(0x00), 32767);
+    assert_eq!(sequence_length(PERIOD_MODE), 93);
+
+    let mut noise = Noise::default();
+    noise.set_enabled(true);
+    // Constant volume 6, period index 2, length index 1
+    for (register, value) in [(0, 0b0011_0110), (2, 0x02), (3, 0b0000_1000)] {
+        noise.write_register(register, value, Region::Ntsc);
+    }
+    assert_eq!(noise.timer_period, 15);
+    assert_eq!(noise.output(), 0);
+
+    // Long mode register after the first shifts: 1 -> $4000 -> $2000 -> $1000
+    let mut outputs = Vec::new();
+    for _ in 0..4 {
+        (0..16).for_each(|_| noise.clock_timer());
+        outputs.push((noise.shift_register, noise.output()));
+    }
+    assert_eq!(outputs, [(0x4000, 6), (0x2000, 6), (0x1000, 6), (0x0800, 6)]);
+
+    noise.write_register(2, 0x02, Region::Pal);
+    assert_eq!(noise.timer_period, 13);
+
+    noise.set_enabled(false);
+    assert_eq!(noise.output(), 0);
+}
@@ -0,0 +1,225 @@
+use crate::common::is_bit_set;
+use super::units::{Envelope, LengthCounter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const CONTROL_DUTY_SHIFT: u8 = 6;
+const CONTROL_LENGTH_HALT: u8 = 0b0010_0000;
+const SWEEP_ENABLED: u8 = 0b1000_0000;
+const SWEEP_PERIOD_SHIFT: u8 = 4;
+const SWEEP_NEGATE: u8 = 0b0000_1000;
+const SWEEP_SHIFT: u8 = 0b0000_0111;
+const TIMER_HIGH_MASK: u8 = 0b0000_0111;
+const LENGTH_INDEX_SHIFT: u8 = 3;
+
+/// Channel is silenced when the current period is too short or the sweep target overflows
+const MIN_PERIOD: u16 = 8;
+const MAX_TARGET_PERIOD: u16 = 0x07FF;
+
+/// Pulse channel 1 negates the sweep change with ones' complement, channel 2 with two's complement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PulseChannel {
+    First,
+    Second,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+/// Square wave channel at $4000-$4003 or $4004-$4007
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Pulse {
+    channel: PulseChannel,
+    duty: u8,
+    sequencer_step: usize,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    sweep: Sweep,
+    length: LengthCounter,
+}
+
+impl Pulse {
+    pub(super) fn new(channel: PulseChannel) -> Self {
+        Self {
+            channel,
+            duty: 0,
+            sequencer_step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            sweep: Sweep::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    /// Register index is the address offset from the channel start
+    pub(super) fn write_register(&mut self, register: usize, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> CONTROL_DUTY_SHIFT;
+                self.length.set_halted(is_bit_set(value, CONTROL_LENGTH_HALT));
+                self.envelope.write_control(value);
+            },
+            1 => {
+                self.sweep.enabled = is_bit_set(value, SWEEP_ENABLED);
+                self.sweep.period = (value >> SWEEP_PERIOD_SHIFT) & 0b0111;
+                self.sweep.negate = is_bit_set(value, SWEEP_NEGATE);
+                self.sweep.shift = value & SWEEP_SHIFT;
+                self.sweep.reload = true;
+            },
+            2 => self.timer_period = (self.timer_period & 0xFF00) | value as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((value & TIMER_HIGH_MASK) as u16) << 8);
+                self.length.load(value >> LENGTH_INDEX_SHIFT);
+                self.sequencer_step = 0;
+                self.envelope.restart();
+            },
+            _ => unreachable!("Pulse channel has only 4 registers"),
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.length.is_active()
+    }
+
+    /// Clocked every second CPU cycle, sequencer steps backwards through the duty table
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequencer_step = (self.sequencer_step + 7) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.is_sweep_muting() {
+            self.timer_period = self.sweep_target_period();
+        }
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    /// Target is computed continuously, even when sweep is disabled
+    fn sweep_target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if !self.sweep.negate {
+            self.timer_period + change
+        } else {
+            match self.channel {
+                PulseChannel::First => self.timer_period.saturating_sub(change + 1),
+                PulseChannel::Second => self.timer_period.saturating_sub(change),
+            }
+        }
+    }
+
+    fn is_sweep_muting(&self) -> bool {
+        self.timer_period < MIN_PERIOD || self.sweep_target_period() > MAX_TARGET_PERIOD
+    }
+
+    /// Volume 0-15
+    pub(super) fn output(&self) -> u8 {
+        if DUTY_TABLE[self.duty as usize][self.sequencer_step] == 0
+            || !self.length.is_active()
+            || self.is_sweep_muting()
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[test]
+fn test_pulse_sequencer() {
+    let mut pulse = Pulse::new(PulseChannel::First);
+    pulse.set_enabled(true);
+    // 25% duty, constant volume 9, period 8, length index 1
+    for (register, value) in [(0, 0b0101_1001), (2, 0x08), (3, 0b0000_1000)] {
+        pulse.write_register(register, value);
+    }
+
+    let mut waveform = Vec::new();
+    for _ in 0..8 {
+        waveform.push(pulse.output());
+        (0..=8).for_each(|_| pulse.clock_timer());
+    }
+    assert_eq!(waveform, [0, 0, 0, 0, 0, 0, 9, 9]);
+
+    // Period below 8 silences the channel
+    pulse.write_register(2, 0x07);
+    assert_eq!((0..16).map(|_| { pulse.clock_timer(); pulse.output() }).max(), Some(0));
+
+    // Disabled channel ignores length load
+    pulse.set_enabled(false);
+    pulse.write_register(3, 0b1111_1000);
+    assert!(!pulse.is_active());
+}
+
+#[test]
+fn test_pulse_sweep() {
+    let sweep_target = |channel, sweep_value| {
+        let mut pulse = Pulse::new(channel);
+        pulse.set_enabled(true);
+        pulse.write_register(2, 0x00);
+        pulse.write_register(3, 0b0000_1001);
+        pulse.write_register(1, sweep_value);
+        pulse.clock_half_frame();
+        pulse.timer_period
+    };
+
+    // Period $100 with shift 1 and sweep period 0 changes on the first half frame
+    assert_eq!(sweep_target(PulseChannel::First, 0b1000_0001), 0x180);
+    assert_eq!(sweep_target(PulseChannel::Second, 0b1000_0001), 0x180);
+    assert_eq!(sweep_target(PulseChannel::First, 0b1000_1001), 0x7F);
+    assert_eq!(sweep_target(PulseChannel::Second, 0b1000_1001), 0x80);
+    // Disabled sweep or zero shift keep the period
+    assert_eq!(sweep_target(PulseChannel::First, 0b0000_0001), 0x100);
+    assert_eq!(sweep_target(PulseChannel::First, 0b1000_1000), 0x100);
+
+    // Sweep divider with period 2 updates the period every 3 half frames
+    let mut pulse = Pulse::new(PulseChannel::Second);
+    pulse.set_enabled(true);
+    pulse.write_register(2, 0x00);
+    pulse.write_register(3, 0b0000_1001);
+    pulse.write_register(1, 0b1010_1011);
+    let periods: Vec<u16> = (0..7).map(|_| { pulse.clock_half_frame(); pulse.timer_period }).collect();
+    assert_eq!(periods, [0xE0, 0xE0, 0xE0, 0xC4, 0xC4, 0xC4, 0xAC]);
+
+    // Target above $7FF mutes the channel even with disabled sweep
+    pulse.write_register(0, 0b1101_1111);
+    pulse.write_register(1, 0b0000_0000);
+    pulse.write_register(2, 0xFF);
+    pulse.write_register(3, 0b0000_1100);
+    assert!(pulse.is_sweep_muting());
+    assert_eq!((0..16).map(|_| { pulse.clock_timer(); pulse.output() }).max(), Some(0));
+    pulse.write_register(3, 0b0000_1011);
+    assert!(!pulse.is_sweep_muting());
+}
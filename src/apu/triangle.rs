@@ -0,0 +1,130 @@
+use crate::common::is_bit_set;
+use super::units::LengthCounter;
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// Halts length counter and keeps linear counter reload flag set
+const CONTROL_FLAG: u8 = 0b1000_0000;
+const LINEAR_RELOAD_MASK: u8 = 0b0111_1111;
+const TIMER_HIGH_MASK: u8 = 0b0000_0111;
+const LENGTH_INDEX_SHIFT: u8 = 3;
+
+/// Triangle wave channel at $4008-$400B, has no volume control
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Triangle {
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    sequencer_step: usize,
+    timer_period: u16,
+    timer: u16,
+    length: LengthCounter,
+}
+
+impl Triangle {
+    /// Register index is the address offset from $4008, $4009 is unused
+    pub(super) fn write_register(&mut self, register: usize, value: u8) {
+        match register {
+            0 => {
+                self.control = is_bit_set(value, CONTROL_FLAG);
+                self.length.set_halted(self.control);
+                self.linear_reload_value = value & LINEAR_RELOAD_MASK;
+            },
+            1 => {},
+            2 => self.timer_period = (self.timer_period & 0xFF00) | value as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | (((value & TIMER_HIGH_MASK) as u16) << 8);
+                self.length.load(value >> LENGTH_INDEX_SHIFT);
+                self.linear_reload = true;
+            },
+            _ => unreachable!("Triangle channel has only 4 registers"),
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.length.is_active()
+    }
+
+    /// Clocked every CPU cycle, sequencer stops while any of the counters is 0
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.is_active() {
+                self.sequencer_step = (self.sequencer_step + 1) % TRIANGLE_SEQUENCE.len();
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    /// Stopped sequencer keeps its last level, 0-15
+    pub(super) fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.sequencer_step]
+    }
+}
+
+#[test]
+fn test_triangle() {
+    let mut triangle = Triangle::default();
+    triangle.set_enabled(true);
+    // Linear counter 2, period 1, length index 1
+    for (register, value) in [(0, 0x02), (2, 0x01), (3, 0b0000_1000)] {
+        triangle.write_register(register, value);
+    }
+
+    // Linear counter is loaded only on quarter frame
+    (0..8).for_each(|_| triangle.clock_timer());
+    assert_eq!(triangle.output(), 15);
+    triangle.clock_quarter_frame();
+
+    let mut waveform = Vec::new();
+    for _ in 0..32 {
+        (0..2).for_each(|_| triangle.clock_timer());
+        waveform.push(triangle.output());
+    }
+    assert_eq!(waveform[..18], [14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2]);
+    assert_eq!(waveform[31], 15);
+
+    // Reload flag was cleared by the quarter frame, so the counter goes down to 0 and the level is held
+    triangle.clock_quarter_frame();
+    (0..6).for_each(|_| triangle.clock_timer());
+    assert_eq!(triangle.output(), 12);
+    triangle.clock_quarter_frame();
+    (0..6).for_each(|_| triangle.clock_timer());
+    assert_eq!(triangle.output(), 12);
+
+    // Control flag keeps reloading linear counter and halts length counter
+    triangle.write_register(0, CONTROL_FLAG | 0x01);
+    triangle.write_register(3, 0b0000_1000);
+    for _ in 0..10 {
+        triangle.clock_quarter_frame();
+        triangle.clock_half_frame();
+    }
+    (0..2).for_each(|_| triangle.clock_timer());
+    assert_eq!(triangle.output(), 11);
+    assert!(triangle.is_active());
+}
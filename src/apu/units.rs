@@ -0,0 +1,159 @@
+use crate::common::is_bit_set;
+
+/// Values loaded into length counter by the upper 5 bits of the 4th channel register
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const ENVELOPE_LOOP: u8 = 0b0010_0000;
+const ENVELOPE_CONSTANT_VOLUME: u8 = 0b0001_0000;
+const ENVELOPE_VOLUME: u8 = 0b0000_1111;
+const ENVELOPE_MAX_DECAY: u8 = 15;
+
+/// Volume source of pulse and noise channels, clocked by quarter frames
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Envelope {
+    /// Period of the divider or constant volume
+    volume: u8,
+    constant_volume: bool,
+    looped: bool,
+    start: bool,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Lower 6 bits of the 1st channel register, loop bit is also length counter halt
+    pub(super) fn write_control(&mut self, value: u8) {
+        self.looped = is_bit_set(value, ENVELOPE_LOOP);
+        self.constant_volume = is_bit_set(value, ENVELOPE_CONSTANT_VOLUME);
+        self.volume = value & ENVELOPE_VOLUME;
+    }
+
+    /// Done by the write to the 4th channel register
+    pub(super) fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub(super) fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = ENVELOPE_MAX_DECAY;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looped {
+                self.decay = ENVELOPE_MAX_DECAY;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+/// Silences the channel after given number of half frames
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct LengthCounter {
+    counter: u8,
+    enabled: bool,
+    halted: bool,
+}
+
+impl LengthCounter {
+    /// Disabling through $4015 clears the counter immediately
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub(super) fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    /// Index is the upper 5 bits of the 4th channel register, ignored while disabled
+    pub(super) fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(index & 0b0001_1111) as usize];
+        }
+    }
+
+    pub(super) fn clock(&mut self) {
+        if self.counter > 0 && !self.halted {
+            self.counter -= 1;
+        }
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+#[test]
+fn test_envelope() {
+    // Decaying envelope with period 2 lowers volume every 3 clocks
+    let mut envelope = Envelope::default();
+    envelope.write_control(0b0000_0010);
+    envelope.restart();
+    envelope.clock();
+    assert_eq!(envelope.output(), 15);
+    let outputs: Vec<u8> = (0..6).map(|_| { envelope.clock(); envelope.output() }).collect();
+    assert_eq!(outputs, [15, 15, 14, 14, 14, 13]);
+
+    // Without loop it stays at 0, with loop it starts again from 15
+    for _ in 0..13 * 3 {
+        envelope.clock();
+    }
+    assert_eq!(envelope.output(), 0);
+    (0..3).for_each(|_| envelope.clock());
+    assert_eq!(envelope.output(), 0);
+
+    envelope.write_control(ENVELOPE_LOOP | 0b0000_0010);
+    (0..3).for_each(|_| envelope.clock());
+    assert_eq!(envelope.output(), 15);
+
+    // Constant volume ignores decay level
+    envelope.write_control(ENVELOPE_CONSTANT_VOLUME | 0b0000_0111);
+    assert_eq!(envelope.output(), 7);
+}
+
+#[test]
+fn test_length_counter() {
+    let mut length = LengthCounter::default();
+    length.load(0b0000_0001);
+    assert!(!length.is_active());
+
+    length.set_enabled(true);
+    length.load(0b0000_0011);
+    length.clock();
+    assert!(length.is_active());
+    length.clock();
+    assert!(!length.is_active());
+
+    // Halt keeps the counter, disable clears it
+    length.load(0b0001_1111);
+    length.set_halted(true);
+    (0..100).for_each(|_| length.clock());
+    assert!(length.is_active());
+    length.set_halted(false);
+    (0..29).for_each(|_| length.clock());
+    assert!(length.is_active());
+    length.clock();
+    assert!(!length.is_active());
+
+    length.load(0b0000_0000);
+    length.set_enabled(false);
+    assert!(!length.is_active());
+}
@@ -0,0 +1,155 @@
+use log::{debug, info};
+
+use crate::cpu::Cpu;
+use crate::bus::Bus;
+
+const STATUS_ADDRESS: u16 = 0x6000;
+const SIGNATURE_ADDRESS: u16 = 0x6001;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const MESSAGE_ADDRESS: u16 = 0x6004;
+const MESSAGE_END_ADDRESS: u16 = 0x7FFF;
+
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET_REQUIRED: u8 = 0x81;
+/// Test ROM asks to press reset not earlier than 100 ms after the request
+const RESET_DELAY_FRAMES: u64 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlarggError {
+    /// Test didn't finish in time, message contains text printed so far
+    Timeout { message: String },
+    EmulationFailed(&'static str),
+}
+
+impl std::fmt::Display for BlarggError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Timeout { message } => write!(f, "Test didn't finish in time, output: {message:?}"),
+            Self::EmulationFailed(err) => write!(f, "Emulation failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for BlarggError {}
+
+/// Result of finished test ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlarggReport {
+    /// 0 is pass, other values are test specific error codes
+    pub status: u8,
+    pub message: String,
+    pub resets_done: usize,
+}
+
+impl BlarggReport {
+    pub fn passed(&self) -> bool {
+        self.status == 0
+    }
+}
+
+fn peek(cpu: &Cpu, bus: &mut Bus, address: u16) -> u8 {
+    bus.read_8bit_cpu(address, &cpu.get_exec_cycles())
+}
+
+/// Status byte at $6000 is valid only after the ROM writes signature to $6001-$6003
+fn read_status(cpu: &Cpu, bus: &mut Bus) -> Option<u8> {
+    let signature = [0, 1, 2].map(|now_byte| peek(cpu, bus, SIGNATURE_ADDRESS + now_byte));
+    (signature == SIGNATURE).then(|| peek(cpu, bus, STATUS_ADDRESS))
+}
+
+/// NUL terminated text from $6004
+fn read_message(cpu: &Cpu, bus: &mut Bus) -> String {
+    let mut message = Vec::new();
+    for now_address in MESSAGE_ADDRESS..=MESSAGE_END_ADDRESS {
+        match peek(cpu, bus, now_address) {
+            0 => break,
+            now_char => message.push(now_char),
+        }
+    }
+    String::from_utf8_lossy(&message).into_owned()
+}
+
+/// Runs test ROM which reports through $6000 protocol until it writes final status.
+/// Reset requests (status $81) are served with a reset after delay
+pub fn run_blargg_test(cpu: &mut Cpu, bus: &mut Bus, max_frames: u64) -> Result<BlarggReport, BlarggError> {
+    let mut reset_requested_at: Option<u64> = None;
+    // Status stays $81 after reset until the ROM overwrites it, the old request must not be served again
+    let mut reset_served = false;
+    let mut resets_done = 0;
+
+    for now_frame in 0..max_frames {
+        if reset_served {
+            // ROM may overwrite the status and request the next reset within one frame,
+            // so the status is checked after every instruction
+            let target_frame = bus.ppu().frame_count() + 1;
+            while bus.ppu().frame_count() < target_frame {
+                cpu.execute_cpu_iteration(bus).map_err(BlarggError::EmulationFailed)?;
+                reset_served = reset_served && read_status(cpu, bus) == Some(STATUS_RESET_REQUIRED);
+            }
+        } else {
+            cpu.run_frames(bus, 1).map_err(BlarggError::EmulationFailed)?;
+        }
+
+        match read_status(cpu, bus) {
+            Some(STATUS_RESET_REQUIRED) if reset_served => {},
+            None | Some(STATUS_RUNNING) => reset_requested_at = None,
+            Some(STATUS_RESET_REQUIRED) => {
+                let requested_at = *reset_requested_at.get_or_insert(now_frame);
+                if now_frame == requested_at + RESET_DELAY_FRAMES {
+                    debug!("Test ROM requested reset, pressing it at frame {now_frame}");
+                    cpu.reset(bus);
+                    reset_requested_at = None;
+                    reset_served = true;
+                    resets_done += 1;
+                }
+            },
+            Some(status) => {
+                let message = read_message(cpu, bus);
+                info!("Test ROM finished with status {status:#04X} at frame {now_frame}");
+                return Ok(BlarggReport { status, message, resets_done })
+            },
+        }
+    }
+
+    Err(BlarggError::Timeout { message: read_message(cpu, bus) })
+}
+
+#[test]
+fn test_blargg_protocol() {
+    use crate::cartridges;
+    use crate::headless::{nrom_test_image, controller_echo_rom};
+
+    // Asks for reset on the first two runs, passes after them. Counter at $6010 survives resets.
+    // Running status is overwritten by the next request in the same frame
+    let nes_data = nrom_test_image(&[
+        0xA9, 0xDE, 0x8D, 0x01, 0x60, // $8000: LDA #$DE; STA $6001
+        0xA9, 0xB0, 0x8D, 0x02, 0x60, // $8005: LDA #$B0; STA $6002
+        0xA9, 0x61, 0x8D, 0x03, 0x60, // $800A: LDA #$61; STA $6003
+        0xA9, 0x80, 0x8D, 0x00, 0x60, // $800F: LDA #$80; STA $6000
+        0xAD, 0x10, 0x60,             // $8014: LDA $6010
+        0xC9, 0x02, 0xB0, 0x0B,       // $8017: CMP #2; BCS $8026
+        0xEE, 0x10, 0x60,             // $801B: INC $6010
+        0xA9, 0x81, 0x8D, 0x00, 0x60, // $801E: LDA #$81; STA $6000
+        0x4C, 0x23, 0x80,             // $8023: JMP $8023
+        0xA9, 0x4F, 0x8D, 0x04, 0x60, // $8026: LDA #'O'; STA $6004
+        0xA9, 0x4B, 0x8D, 0x05, 0x60, // $802B: LDA #'K'; STA $6005
+        0xA9, 0x00, 0x8D, 0x06, 0x60, // $8030: LDA #0; STA $6006
+        0x8D, 0x00, 0x60,             // $8035: STA $6000
+        0x4C, 0x38, 0x80,             // $8038: JMP $8038
+    ]);
+    let (mut cpu, mut bus) = cartridges::load_nes_data(&nes_data).unwrap();
+    let stack_pointer = cpu.get_stack_pointer();
+
+    let report = run_blargg_test(&mut cpu, &mut bus, 60).unwrap();
+    assert!(report.passed());
+    assert_eq!(report.message, "OK");
+    assert_eq!(report.resets_done, 2);
+    assert_eq!(cpu.get_stack_pointer(), stack_pointer.wrapping_sub(6));
+
+    // ROM without signature never finishes
+    let (mut cpu, mut bus) = cartridges::load_nes_data(&controller_echo_rom()).unwrap();
+    assert_eq!(
+        run_blargg_test(&mut cpu, &mut bus, 3),
+        Err(BlarggError::Timeout { message: String::new() }),
+    );
+}
@@ -1,18 +1,32 @@
 use better_assertions::inst_assert;
-use log::warn;
 
 use crate::memory::Memory;
 use crate::memory::{PPU_REGS_MIRRORS, APU_REGS, APU_IO_FUNC, PPU_REGS, RAM_MIRRORS, RAM, EXPANSION_ROM};
-use crate::memory::{PPU_PATTERN_TABLES, PPU_NAME_TABLES, PPU_UNUSED_SPACE, PPU_PALETTES};
-use crate::ppu::Ppu;
+use crate::memory::{PPU_PATTERN_TABLES, PPU_NAME_TABLES, PPU_NAME_TABLES_MIRRORS, PPU_PALETTES, PPU_PALETTES_MIRRORS};
+use crate::ppu::{Ppu, PpuEnhancements, RenderBackend, PPU_DATA_REG, OAM_DATA_REG};
 use crate::mappers::{Mappers, MapperRW};
+use crate::apu::{Apu, APU_STATUS_ADDRESS};
+use crate::region::Region;
+use crate::controller::{Controller, CONTROLLER_1_ADDRESS, CONTROLLER_2_ADDRESS, CONTROLLER_PORTS};
+
+/// Palette RAM stores only 6 bits per entry
+const PALETTE_ENTRY_MASK: u8 = 0b0011_1111;
+
+const OAM_DMA_ADDRESS: usize = 0x4014;
+const OAM_DMA_CYCLES: usize = 513;
 
 #[derive(Debug, Clone, Default)]
 pub struct Bus {
     memory: Memory,
     ppu: Ppu,
+    apu: Apu,
     mapper: Mappers,
+    /// CPU cycle up to which other modules were executed
     cpu_cycles_num: usize,
+    ppu_dots_num: usize,
+    dma_stall_cycles: usize,
+    region: Region,
+    controllers: [Controller; CONTROLLER_PORTS],
 }
 
 impl Bus {
@@ -27,6 +41,30 @@ impl Bus {
     pub fn set_mapper(&mut self, mapper: Mappers) {
         self.mapper = mapper;
     }
+
+    pub fn mapper(&self) -> &Mappers {
+        &self.mapper
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Selects timing of all modules, should be done before execution starts
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+    }
+
+    /// Selects PPU renderer, should be done before execution starts
+    pub fn set_ppu_backend(&mut self, backend: RenderBackend) {
+        self.ppu.set_backend(backend);
+    }
+
+    pub fn set_ppu_enhancements(&mut self, enhancements: PpuEnhancements) {
+        self.ppu.set_enhancements(enhancements);
+    }
 }
 
 impl Bus {
@@ -41,13 +79,25 @@ impl Bus {
             self.mapper.read(requested_address, self.memory.prg_data())
         } else if requested_address >= APU_REGS.start {
             inst_assert!((APU_REGS.start..=APU_IO_FUNC.end).contains(&requested_address));
-            (actual_cpu_cycles % 2) as u8 //FIX: Add APU and IO registers
+            match requested_address {
+                CONTROLLER_1_ADDRESS => self.controllers[0].read(),
+                CONTROLLER_2_ADDRESS => self.controllers[1].read(),
+                APU_STATUS_ADDRESS => {
+                    self.execute_modules(*actual_cpu_cycles);
+                    self.apu.read_status()
+                },
+                // Write only APU registers and unused $4018-$401F are open bus. There is no CPU
+                // data bus latch, absolute addressing leaves high byte of the address on the bus
+                _ => (requested_address >> 8) as u8,
+            }
         } else if requested_address >= PPU_REGS_MIRRORS.start { // PPU REGS
             inst_assert!((PPU_REGS_MIRRORS.start..=PPU_REGS_MIRRORS.end).contains(&requested_address));
-            self.ppu.read_from_registers(requested_address % 8) //TODO: Implement this function
+            self.execute_modules(*actual_cpu_cycles);
+            self.read_ppu_register(requested_address % 8)
         } else if requested_address >= PPU_REGS.start { // PPU REGS
             inst_assert!((PPU_REGS.start..=PPU_REGS.end).contains(&requested_address));
-            self.ppu.read_from_registers(requested_address - PPU_REGS.start) //TODO: Implement this function
+            self.execute_modules(*actual_cpu_cycles);
+            self.read_ppu_register(requested_address - PPU_REGS.start)
         } else if requested_address >= RAM_MIRRORS.start { // RAM MIRRORS
             inst_assert!((RAM_MIRRORS.start..=RAM_MIRRORS.end).contains(&requested_address));
             self.memory.ram()[requested_address % RAM.size]
@@ -69,16 +119,23 @@ impl Bus {
             self.mapper.write(requested_address, value, self.memory.prg_data_mut());
         } else if requested_address >= APU_REGS.start {
             inst_assert!((APU_REGS.start..=APU_IO_FUNC.end).contains(&requested_address));
-            if *actual_cpu_cycles > 0xFFFFFFFF {
-                println!("here")
+            if requested_address == OAM_DMA_ADDRESS {
+                self.execute_oam_dma(value, *actual_cpu_cycles);
+            } else if requested_address == CONTROLLER_1_ADDRESS {
+                // Strobe line is shared by both ports
+                self.controllers.iter_mut().for_each(|controller| controller.write_strobe(value));
+            } else {
+                self.execute_modules(*actual_cpu_cycles);
+                self.apu.write_register(requested_address, value);
             }
-            //FIX: Add APU and IO registers
         } else if requested_address >= PPU_REGS_MIRRORS.start { // PPU REGS
             inst_assert!((PPU_REGS_MIRRORS.start..=PPU_REGS_MIRRORS.end).contains(&requested_address));
-            self.ppu.write_to_registers(requested_address % 8, value); //TODO: Implement this function
+            self.execute_modules(*actual_cpu_cycles);
+            self.write_ppu_register(requested_address % 8, value);
         } else if requested_address >= PPU_REGS.start { // PPU REGS
             inst_assert!((PPU_REGS.start..=PPU_REGS.end).contains(&requested_address));
-            self.ppu.write_to_registers(requested_address - PPU_REGS.start, value); //TODO: Implement this function
+            self.execute_modules(*actual_cpu_cycles);
+            self.write_ppu_register(requested_address - PPU_REGS.start, value);
         } else if requested_address >= RAM_MIRRORS.start { // RAM MIRRORS
             inst_assert!((RAM_MIRRORS.start..=RAM_MIRRORS.end).contains(&requested_address));
             self.memory.ram_mut()[requested_address % RAM.size] = value
@@ -90,6 +147,100 @@ impl Bus {
 }
 
 impl Bus {
+    fn read_ppu_register(&mut self, register: usize) -> u8 {
+        if register == PPU_DATA_REG {
+            let mut ppu_bus = PpuBus::new(&mut self.memory, &mut self.mapper);
+            self.ppu.read_ppu_data(&mut ppu_bus)
+        } else {
+            self.ppu.read_from_registers(register)
+        }
+    }
+
+    fn write_ppu_register(&mut self, register: usize, value: u8) {
+        if register == PPU_DATA_REG {
+            let mut ppu_bus = PpuBus::new(&mut self.memory, &mut self.mapper);
+            self.ppu.write_ppu_data(value, &mut ppu_bus);
+        } else {
+            self.ppu.write_to_registers(register, value);
+        }
+    }
+}
+
+impl Bus {
+    pub fn read_8bit_ppu<T>(&mut self, requested_address: T) -> u8
+    where 
+        T: Into<usize> + Copy
+    {
+        PpuBus::new(&mut self.memory, &mut self.mapper).read_8bit_ppu(requested_address)
+    }
+
+    pub fn write_8bit_ppu<T>(&mut self, requested_address: T, value: u8)
+    where 
+        T: Into<usize> + Copy
+    {
+        PpuBus::new(&mut self.memory, &mut self.mapper).write_8bit_ppu(requested_address, value)
+    }
+
+    /// PPU side of the bus for tests which run standalone PPU
+    #[cfg(test)]
+    pub(crate) fn ppu_bus(&mut self) -> PpuBus<'_> {
+        PpuBus::new(&mut self.memory, &mut self.mapper)
+    }
+}
+
+/// PPU address space ($0000-$3FFF): pattern tables through mapper, nametables and palettes.
+/// Borrows only memory and mapper, so PPU can use it while being a part of the bus
+pub struct PpuBus<'a> {
+    memory: &'a mut Memory,
+    mapper: &'a mut Mappers,
+    /// PPU dot of current access, accesses without it (debug views) are hidden from mapper
+    ppu_cycle: Option<usize>,
+    /// (address, PPU dot) of every access seen by mapper, for tracing
+    access_log: Option<&'a mut Vec<(usize, usize)>>,
+}
+
+impl<'a> PpuBus<'a> {
+    pub fn new(memory: &'a mut Memory, mapper: &'a mut Mappers) -> PpuBus<'a> {
+        PpuBus {
+            memory,
+            mapper,
+            ppu_cycle: None,
+            access_log: None,
+        }
+    }
+
+    /// Records following accesses which are seen by mapper
+    pub fn set_access_log(&mut self, access_log: &'a mut Vec<(usize, usize)>) {
+        self.access_log = Some(access_log);
+    }
+
+    /// Marks following accesses as made by PPU at given dot
+    #[inline(always)]
+    pub fn set_ppu_cycle(&mut self, ppu_cycle: usize) {
+        self.ppu_cycle = Some(ppu_cycle);
+    }
+
+    /// Read which is never seen by mapper, for emulator side accesses
+    pub fn peek_8bit_ppu(&mut self, requested_address: u16) -> u8 {
+        let ppu_cycle = self.ppu_cycle.take();
+        let value = self.read_8bit_ppu(requested_address);
+        self.ppu_cycle = ppu_cycle;
+        value
+    }
+
+    /// Palette RAM is inside PPU, so only pattern table and nametable accesses reach cartridge
+    #[inline(always)]
+    fn notify_mapper(&mut self, requested_address: usize) {
+        if let Some(ppu_cycle) = self.ppu_cycle {
+            self.mapper.notify_ppu_access(requested_address, ppu_cycle);
+            if let Some(access_log) = &mut self.access_log {
+                access_log.push((requested_address, ppu_cycle));
+            }
+        }
+    }
+}
+
+impl PpuBus<'_> {
     pub fn read_8bit_ppu<T>(&mut self, requested_address: T) -> u8
     where 
         T: Into<usize> + Copy
@@ -98,24 +249,545 @@ impl Bus {
         inst_assert!(requested_address <= 0b0011_1111_1111_1111);
 
         if requested_address < PPU_NAME_TABLES.start {
-            inst_assert!((PPU_PATTERN_TABLES.start..=PPU_PATTERN_TABLES.end).contains(&requested_address)); //TODO: PATTERN TABLES READ
+            inst_assert!((PPU_PATTERN_TABLES.start..=PPU_PATTERN_TABLES.end).contains(&requested_address));
+            self.notify_mapper(requested_address);
             self.mapper.read_ppu(requested_address, self.memory.chr_data())
-        } else if requested_address < PPU_UNUSED_SPACE.start {
-            inst_assert!((PPU_NAME_TABLES.start..=PPU_NAME_TABLES.end).contains(&requested_address)); //TODO: NAME_TABLES READ
-            self.memory.vram()[requested_address - PPU_NAME_TABLES.start]
         } else if requested_address < PPU_PALETTES.start {
-            inst_assert!((PPU_UNUSED_SPACE.start..=PPU_UNUSED_SPACE.end).contains(&requested_address)); //TODO: UNUSED SPACE READ
-            warn!("Tried to read from PPU unused space");
-            0
+            inst_assert!((PPU_NAME_TABLES.start..=PPU_NAME_TABLES_MIRRORS.end).contains(&requested_address));
+            self.notify_mapper(requested_address);
+            *self.nametable_byte(requested_address)
+        } else {
+            inst_assert!((PPU_PALETTES.start..=PPU_PALETTES_MIRRORS.end).contains(&requested_address));
+            *self.palette_byte(requested_address) & PALETTE_ENTRY_MASK
+        }
+    }
+
+    pub fn write_8bit_ppu<T>(&mut self, requested_address: T, value: u8)
+    where 
+        T: Into<usize> + Copy
+    {
+        let requested_address: usize = requested_address.into();
+        inst_assert!(requested_address <= 0b0011_1111_1111_1111);
+
+        if requested_address < PPU_NAME_TABLES.start {
+            inst_assert!((PPU_PATTERN_TABLES.start..=PPU_PATTERN_TABLES.end).contains(&requested_address));
+            self.notify_mapper(requested_address);
+            self.mapper.write_ppu(requested_address, value, self.memory.chr_data_mut())
+        } else if requested_address < PPU_PALETTES.start {
+            inst_assert!((PPU_NAME_TABLES.start..=PPU_NAME_TABLES_MIRRORS.end).contains(&requested_address));
+            self.notify_mapper(requested_address);
+            *self.nametable_byte(requested_address) = value
+        } else {
+            inst_assert!((PPU_PALETTES.start..=PPU_PALETTES_MIRRORS.end).contains(&requested_address));
+            *self.palette_byte(requested_address) = value & PALETTE_ENTRY_MASK
+        }
+    }
+}
+
+impl PpuBus<'_> {
+    /// Nametable byte for $2000-$3EFF address, $3000-$3EFF mirrors $2000-$2EFF.
+    /// Layout is taken from the mapper on every access
+    fn nametable_byte(&mut self, requested_address: usize) -> &mut u8 {
+        let nametable_offset = self.mapper.mirroring().nametable_offset(requested_address);
+        let vram_size = self.memory.vram().len();
+
+        if nametable_offset < vram_size {
+            &mut self.memory.vram_mut()[nametable_offset]
         } else {
-            inst_assert!((PPU_PALETTES.start..=PPU_PALETTES.end).contains(&requested_address)); //TODO: PPU_PALETTES READ
-            self.memory.palettes_table()[requested_address - PPU_PALETTES.start]
+            &mut self.memory.cartridge_vram_mut()[nametable_offset - vram_size]
+        }
+    }
+
+    /// Palette RAM byte for $3F00-$3FFF address. 32 bytes are repeated up to $3FFF and
+    /// $3F10/$3F14/$3F18/$3F1C are shared with backdrop entries $3F00/$3F04/$3F08/$3F0C
+    fn palette_byte(&mut self, requested_address: usize) -> &mut u8 {
+        let mut palette_index = (requested_address - PPU_PALETTES.start) % PPU_PALETTES.size;
+        if palette_index & 0b1_0011 == 0b1_0000 {
+            palette_index &= 0b0_1111;
         }
+
+        &mut self.memory.palettes_table_mut()[palette_index]
     }
 }
 
 impl Bus {
-    pub fn execute_modules(&mut self) {
+    /// Runs other modules until they reach given CPU cycle. Modules are executed lazily:
+    /// at the end of every CPU instruction and before every access to their registers
+    pub fn execute_modules(&mut self, cpu_cycles: usize) {
+        if cpu_cycles <= self.cpu_cycles_num {
+            return
+        }
+
+        // PAL ratio is fractional, so dots are counted from the power on
+        let (dots_numerator, dots_denominator) = self.region.ppu_dots_per_cpu_cycle();
+        let target_ppu_dots = cpu_cycles * dots_numerator / dots_denominator;
+        let mut ppu_bus = PpuBus::new(&mut self.memory, &mut self.mapper);
+        self.ppu.execute_cycles(target_ppu_dots - self.ppu_dots_num, &mut ppu_bus);
+        let (mapper, memory) = (&self.mapper, &self.memory);
+        self.apu.execute_cycles(cpu_cycles - self.cpu_cycles_num, &mut |address| {
+            mapper.read(address as usize, memory.prg_data())
+        });
+        self.dma_stall_cycles += self.apu.take_dma_stall_cycles();
+
+        self.cpu_cycles_num = cpu_cycles;
+        self.ppu_dots_num = target_ppu_dots;
+    }
+
+    /// IRQ line is level triggered, held until the source is acknowledged
+    pub fn irq_line(&self) -> bool {
+        self.mapper.irq_asserted() || self.apu.irq_asserted()
+    }
+
+    /// Returns true once per NMI raised by PPU
+    pub fn poll_nmi(&mut self) -> bool {
+        self.ppu.poll_nmi()
+    }
+
+    /// CPU cycles stolen by DMA since the last call
+    pub fn take_dma_stall_cycles(&mut self) -> usize {
+        std::mem::take(&mut self.dma_stall_cycles)
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    /// Reset line of the console, memories and timing are kept
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+    }
+
+    /// Buttons currently held on controller port 0 or 1, see `controller::BUTTON_*`
+    pub fn set_buttons(&mut self, port: usize, buttons: u8) {
+        self.controllers[port].set_buttons(buttons);
+    }
+
+    /// Audio is produced only while enabled, the consumer has to take it regularly
+    pub fn set_audio_output(&mut self, enabled: bool) {
+        self.apu.set_sample_output(enabled);
+    }
+
+    /// Audio output since the last take, see `apu::DEFAULT_SAMPLE_RATE`
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.apu.take_samples()
+    }
+
+    /// Copies 256 bytes from CPU page to OAM through $2004, CPU is halted for 513 cycles
+    /// (514 if DMA starts on odd cycle)
+    fn execute_oam_dma(&mut self, page: u8, actual_cpu_cycles: usize) {
+        self.execute_modules(actual_cpu_cycles);
+
+        let page_start = (page as usize) << 8;
+        for now_byte in 0..256 {
+            let value = self.read_8bit_cpu(page_start + now_byte, &actual_cpu_cycles);
+            self.ppu.write_to_registers(OAM_DATA_REG, value);
+        }
+
+        self.dma_stall_cycles += OAM_DMA_CYCLES + actual_cpu_cycles % 2;
+    }
+}
+
+#[test]
+fn test_ppu_data_access() {
+    use crate::ppu::MirroringType;
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+
+    let set_ppu_address = |bus: &mut Bus, address: u16| {
+        bus.read_8bit_cpu(0x2002usize, &0);
+        bus.write_8bit_cpu(0x2006usize, (address >> 8) as u8, &0);
+        bus.write_8bit_cpu(0x2006usize, address as u8, &0);
+    };
+
+    // Nametable writes with increment by 1, reads are delayed by internal buffer
+    set_ppu_address(&mut bus, 0x2005);
+    bus.write_8bit_cpu(0x2007usize, 0x11, &0);
+    bus.write_8bit_cpu(0x200Fusize, 0x22, &0); // $2007 mirror
+    assert_eq!(bus.read_8bit_ppu(0x2005usize), 0x11);
+    assert_eq!(bus.read_8bit_ppu(0x2006usize), 0x22);
+
+    set_ppu_address(&mut bus, 0x2005);
+    assert_eq!(bus.read_8bit_cpu(0x2007usize, &0), 0x00);
+    assert_eq!(bus.read_8bit_cpu(0x2007usize, &0), 0x11);
+    assert_eq!(bus.read_8bit_cpu(0x2007usize, &0), 0x22);
+
+    // Increment by 32
+    bus.write_8bit_cpu(0x2000usize, 0b0000_0100, &0);
+    set_ppu_address(&mut bus, 0x2100);
+    bus.write_8bit_cpu(0x2007usize, 0x33, &0);
+    bus.write_8bit_cpu(0x2007usize, 0x44, &0);
+    assert_eq!(bus.read_8bit_ppu(0x2100usize), 0x33);
+    assert_eq!(bus.read_8bit_ppu(0x2120usize), 0x44);
+    bus.write_8bit_cpu(0x2000usize, 0b0000_0000, &0);
+
+    // Palette reads are not buffered, buffer gets nametable data under the palette
+    bus.write_8bit_ppu(0x2F01usize, 0x55);
+    set_ppu_address(&mut bus, 0x3F01);
+    bus.write_8bit_cpu(0x2007usize, 0x2A, &0);
+    assert_eq!(bus.read_8bit_ppu(0x3F01usize), 0x2A);
+
+    set_ppu_address(&mut bus, 0x3F01);
+    assert_eq!(bus.read_8bit_cpu(0x2007usize, &0), 0x2A);
+    set_ppu_address(&mut bus, 0x2000);
+    assert_eq!(bus.read_8bit_cpu(0x2007usize, &0), 0x55);
+
+    // CHR-RAM writes go through the mapper
+    set_ppu_address(&mut bus, 0x1FFF);
+    bus.write_8bit_cpu(0x2007usize, 0x66, &0);
+    assert_eq!(bus.memory().chr_data()[0x1FFF], 0x66);
+    set_ppu_address(&mut bus, 0x1FFF);
+    bus.read_8bit_cpu(0x2007usize, &0);
+    assert_eq!(bus.read_8bit_cpu(0x2007usize, &0), 0x66);
+}
+
+#[test]
+fn test_nametable_mirroring() {
+    use crate::ppu::MirroringType;
+
+    let create_bus = |mirroring: MirroringType| {
+        let mut bus = nrom_test_bus(&[], mirroring);
+
+        // Unique value at the start of every nametable
+        for (now_nametable, now_address) in [0x2000usize, 0x2400, 0x2800, 0x2C00].iter().enumerate() {
+            bus.write_8bit_ppu(*now_address, now_nametable as u8 + 1);
+        }
+        bus
+    };
+
+    let read_nametables = |bus: &mut Bus| {
+        [0x2000usize, 0x2400, 0x2800, 0x2C00].map(|now_address| bus.read_8bit_ppu(now_address))
+    };
+
+    let mut bus = create_bus(MirroringType::Horizontal);
+    assert_eq!(read_nametables(&mut bus), [2, 2, 4, 4]);
+    assert_eq!(bus.memory().vram()[0x0400], 4);
+
+    let mut bus = create_bus(MirroringType::Vertical);
+    assert_eq!(read_nametables(&mut bus), [3, 4, 3, 4]);
+
+    let mut bus = create_bus(MirroringType::SingleScreenLower);
+    assert_eq!(read_nametables(&mut bus), [4, 4, 4, 4]);
+    assert_eq!(bus.memory().vram()[0x0000], 4);
+
+    let mut bus = create_bus(MirroringType::SingleScreenUpper);
+    assert_eq!(read_nametables(&mut bus), [4, 4, 4, 4]);
+    assert_eq!(bus.memory().vram()[0x0400], 4);
+
+    let mut bus = create_bus(MirroringType::FourScreen);
+    assert_eq!(read_nametables(&mut bus), [1, 2, 3, 4]);
+    assert_eq!(bus.memory().cartridge_vram()[0x0400], 4);
+
+    // $3000-$3EFF mirrors $2000-$2EFF
+    bus.write_8bit_ppu(0x3E05usize, 0x77);
+    assert_eq!(bus.read_8bit_ppu(0x2E05usize), 0x77);
+    assert_eq!(bus.read_8bit_ppu(0x3401usize), bus.read_8bit_ppu(0x2401usize));
+}
+
+#[test]
+fn test_palette_memory() {
+    use crate::ppu::MirroringType;
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+
+    // 32 bytes of palette RAM are repeated up to $3FFF
+    bus.write_8bit_ppu(0x3F01usize, 0x16);
+    assert_eq!(bus.read_8bit_ppu(0x3F21usize), 0x16);
+    assert_eq!(bus.read_8bit_ppu(0x3FE1usize), 0x16);
+    bus.write_8bit_ppu(0x3FFFusize, 0x27);
+    assert_eq!(bus.read_8bit_ppu(0x3F1Fusize), 0x27);
+
+    // Sprite palettes entry 0 share memory with background ones
+    for (sprite_address, bg_address) in [(0x3F10usize, 0x3F00usize), (0x3F14, 0x3F04), (0x3F18, 0x3F08), (0x3F1C, 0x3F0C)] {
+        bus.write_8bit_ppu(sprite_address, bg_address as u8);
+        assert_eq!(bus.read_8bit_ppu(bg_address), bg_address as u8);
+        bus.write_8bit_ppu(bg_address + 0x20, 0x01);
+        assert_eq!(bus.read_8bit_ppu(sprite_address), 0x01);
+    }
+    bus.write_8bit_ppu(0x3F11usize, 0x30);
+    assert_eq!(bus.read_8bit_ppu(0x3F01usize), 0x16);
+
+    // Only 6 bits are stored
+    bus.write_8bit_ppu(0x3F02usize, 0xFF);
+    assert_eq!(bus.read_8bit_ppu(0x3F02usize), 0x3F);
+
+    // Greyscale is applied to palette reads through $2007
+    let read_palette = |bus: &mut Bus, address: u16| {
+        bus.read_8bit_cpu(0x2002usize, &0);
+        bus.write_8bit_cpu(0x2006usize, (address >> 8) as u8, &0);
+        bus.write_8bit_cpu(0x2006usize, address as u8, &0);
+        bus.read_8bit_cpu(0x2007usize, &0)
+    };
+    assert_eq!(read_palette(&mut bus, 0x3F01), 0x16);
+    bus.write_8bit_cpu(0x2001usize, 0b0000_0001, &0);
+    assert_eq!(read_palette(&mut bus, 0x3F01), 0x10);
+    assert_eq!(read_palette(&mut bus, 0x3F10), 0x00);
+}
+
+#[test]
+fn test_cpu_ppu_synchronisation() {
+    use crate::common::DataSizes;
+    use crate::cpu::Cpu;
+    use crate::ppu::MirroringType;
+
+    let mut prg_rom = vec![0xEAu8; DataSizes::Size16K.to_bytes()];
+    // $8000: LDA #$80; STA $2000; JMP $8005
+    prg_rom[..8].copy_from_slice(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80]);
+    // $8010: INC $10; RTI
+    prg_rom[0x10..0x13].copy_from_slice(&[0xE6, 0x10, 0x40]);
+    // NMI and reset vectors
+    prg_rom[0x3FFA..0x3FFE].copy_from_slice(&[0x10, 0x80, 0x00, 0x80]);
+
+    let mut bus = nrom_test_bus(&prg_rom, MirroringType::Horizontal);
+
+    let mut cpu = Cpu::default();
+    cpu.init_pc(&mut bus);
+
+    // PPU runs 3 dots per CPU cycle, first vblank starts at dot 1 of line 241
+    let first_v_blank_cycle = (241 * 341 + 1) / 3;
+    while cpu.get_exec_cycles() < first_v_blank_cycle {
+        cpu.execute_cpu_iteration(&mut bus).unwrap();
+    }
+    assert_eq!(bus.memory().ram()[0x10], 0);
+
+    // NMI is taken at the next instruction boundary
+    let cycles = cpu.execute_cpu_iteration(&mut bus).unwrap();
+    assert_eq!(cycles, 7 + 5);
+    assert_eq!(bus.memory().ram()[0x10], 1);
+
+    // One NMI per frame
+    while cpu.get_exec_cycles() < first_v_blank_cycle + 2 * 341 * 262 / 3 + 10 {
+        cpu.execute_cpu_iteration(&mut bus).unwrap();
+    }
+    assert_eq!(bus.memory().ram()[0x10], 3);
+
+    // OAM DMA copies page to OAM and stalls CPU
+    for now_byte in 0..256 {
+        bus.memory_mut().ram_mut()[0x0200 + now_byte] = now_byte as u8;
+    }
+    // PPU catches up before the copy, as it does on $2004 write
+    let dma_cycle = cpu.get_exec_cycles() + 100;
+    bus.write_8bit_cpu(0x4014usize, 0x02, &dma_cycle);
+    assert_eq!(bus.ppu().total_dots(), dma_cycle * 3);
+    assert_eq!(bus.ppu().oam_data()[0xAB], 0xAB);
+    assert_eq!(bus.take_dma_stall_cycles(), 513 + dma_cycle % 2);
+    bus.write_8bit_cpu(0x4014usize, 0x02, &(dma_cycle + 1));
+    assert_eq!(bus.take_dma_stall_cycles(), 514 - dma_cycle % 2);
+    assert_eq!(bus.take_dma_stall_cycles(), 0);
+}
+
+#[test]
+fn test_region_timing() {
+    use crate::common::DataSizes;
+    use crate::cpu::Cpu;
+    use crate::ppu::MirroringType;
+
+    let mut prg_rom = vec![0xEAu8; DataSizes::Size16K.to_bytes()];
+    // $8000: LDA #$80; STA $2000; JMP $8005, NMI handler: INC $10; RTI
+    prg_rom[..8].copy_from_slice(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80]);
+    prg_rom[0x10..0x13].copy_from_slice(&[0xE6, 0x10, 0x40]);
+    prg_rom[0x3FFA..0x3FFE].copy_from_slice(&[0x10, 0x80, 0x00, 0x80]);
+
+    // Returns CPU cycle of the first NMI
+    let first_nmi_cycle = |region: Region| {
+        let mut bus = nrom_test_bus(&prg_rom, MirroringType::Horizontal);
+        bus.set_region(region);
+
+        let mut cpu = Cpu::default();
+        cpu.init_pc(&mut bus);
+        while bus.memory().ram()[0x10] == 0 {
+            cpu.execute_cpu_iteration(&mut bus).unwrap();
+        }
+        cpu.get_exec_cycles()
+    };
+
+    let ntsc_cycle = first_nmi_cycle(Region::Ntsc);
+    assert!(ntsc_cycle.abs_diff((241 * 341) / 3) < 20);
+
+    // PAL runs 16 PPU dots per 5 CPU cycles, vblank on the same line
+    let pal_cycle = first_nmi_cycle(Region::Pal);
+    assert!(pal_cycle.abs_diff((241 * 341) * 5 / 16) < 20);
+
+    // Dendy vblank starts at line 291
+    let dendy_cycle = first_nmi_cycle(Region::Dendy);
+    assert!(dendy_cycle.abs_diff((291 * 341) / 3) < 20);
+
+    // Frame lengths in PPU dots
+    let mut bus = Bus::default();
+    bus.set_region(Region::Pal);
+    bus.execute_modules(5);
+    assert_eq!(bus.ppu_dots_num, 16);
+    bus.execute_modules(7);
+    assert_eq!(bus.ppu_dots_num, 22);
+    assert_eq!(Region::Pal.scanlines_per_frame(), 312);
+    assert_eq!(Region::Dendy.pre_render_scanline(), 311);
+    assert_eq!("dendy".parse::<Region>(), Ok(Region::Dendy));
+}
+
+/// Bus with NROM CHR-RAM cartridge, empty PRG-ROM is replaced with 16 KB of zeros
+#[cfg(test)]
+pub(crate) fn nrom_test_bus(prg_rom: &[u8], mirroring: crate::ppu::MirroringType) -> Bus {
+    use crate::mappers;
+    use crate::common::DataSizes;
+
+    let zero_prg_rom = vec![0u8; DataSizes::Size16K.to_bytes()];
+    let prg_rom = if prg_rom.is_empty() { &zero_prg_rom } else { prg_rom };
+    let mut bus = Bus::default();
+    let mapper = mappers::create_mapper(0, mirroring, bus.memory_mut(), prg_rom, &[]).unwrap();
+    bus.set_mapper(mapper);
+    bus
+}
+
+/// Bus with NROM CHR-RAM cartridge: tile 1 is solid pixel 1, tile 2 is solid pixel 2.
+/// Left half of nametable 0 uses tile 1, right half uses tile 2
+#[cfg(test)]
+fn split_screen_bus(prg_rom: &[u8]) -> Bus {
+    use crate::ppu::MirroringType;
+
+    let mut bus = nrom_test_bus(prg_rom, MirroringType::Horizontal);
+
+    for now_row in 0..8u16 {
+        bus.write_8bit_ppu(0x0010 + now_row, 0xFF);
+        bus.write_8bit_ppu(0x0028 + now_row, 0xFF);
+    }
+    for now_tile in 0..32 * 30u16 {
+        let tile_id = if now_tile % 32 < 16 { 0x01 } else { 0x02 };
+        bus.write_8bit_ppu(0x2000 + now_tile, tile_id);
+    }
+    for (now_address, now_color) in [(0x3F00u16, 0x0F), (0x3F01, 0x16), (0x3F02, 0x27), (0x3F11, 0x30)] {
+        bus.write_8bit_ppu(now_address, now_color);
+    }
+
+    bus
+}
+
+#[test]
+fn test_mid_scanline_register_writes() {
+    use crate::common::DataSizes;
+    use crate::ppu::SCREEN_WIDTH;
+
+    let mut bus = split_screen_bus(&vec![0xEAu8; DataSizes::Size16K.to_bytes()]);
+    bus.write_8bit_cpu(0x2001usize, 0b0000_1010, &0);
+
+    // Frame 0 is 262 * 341 dots, CPU cycle 30951 ends after dot 100 of line 10 in frame 1
+    let write_cycle = 30951;
+    assert_eq!(write_cycle * 3 - 262 * 341 - 10 * 341, 101);
+    bus.write_8bit_cpu(0x2001usize, 0b0000_0000, &write_cycle);
+    bus.execute_modules(write_cycle + 341);
+
+    let frame = bus.ppu().frame_buffer();
+    assert_eq!(frame[9 * SCREEN_WIDTH + 255], 0x27);
+    assert_eq!(frame[10 * SCREEN_WIDTH + 99], 0x16);
+    assert_eq!(frame[10 * SCREEN_WIDTH + 100], 0x0F);
+    assert_eq!(frame[11 * SCREEN_WIDTH], 0x0F);
+}
+
+#[test]
+fn test_sprite_zero_split() {
+    use crate::common::DataSizes;
+    use crate::cpu::Cpu;
+    use crate::ppu::SCREEN_WIDTH;
+
+    let mut prg_rom = vec![0xEAu8; DataSizes::Size16K.to_bytes()];
+    let program = [
+        0x2C, 0x02, 0x20, 0x10, 0xFB, // $8000: BIT $2002; BPL $8000, wait for vblank
+        0xA9, 0x00, 0x8D, 0x05, 0x20, // $8005: LDA #0; STA $2005
+        0x8D, 0x05, 0x20,             // $800A: STA $2005
+        0xA9, 0x1E, 0x8D, 0x01, 0x20, // $800D: LDA #$1E; STA $2001
+        0x2C, 0x02, 0x20, 0x70, 0xFB, // $8012: BIT $2002; BVS $8012, wait for hit clear
+        0x2C, 0x02, 0x20, 0x50, 0xFB, // $8017: BIT $2002; BVC $8017, wait for hit
+        0xA9, 0x80, 0x8D, 0x05, 0x20, // $801C: LDA #$80; STA $2005
+        0xA9, 0x00, 0x8D, 0x05, 0x20, // $8021: LDA #0; STA $2005
+        0x4C, 0x00, 0x80,             // $8026: JMP $8000
+    ];
+    prg_rom[..program.len()].copy_from_slice(&program);
+    prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+
+    // Sprite 0 covers lines 31-38 at x = 64, other sprites are hidden
+    let mut bus = split_screen_bus(&prg_rom);
+    let mut oam_data = [0xF0u8; 256];
+    oam_data[..4].copy_from_slice(&[30, 0x01, 0x00, 64]);
+    for now_byte in oam_data {
+        bus.write_8bit_cpu(0x2004usize, now_byte, &0);
+    }
+
+    let mut cpu = Cpu::default();
+    cpu.init_pc(&mut bus);
+    cpu.run_frames(&mut bus, 3).unwrap();
+
+    // Status bar above the hit line isn't scrolled
+    let frame = bus.ppu().frame_buffer();
+    for now_row in [1, 20, 31] {
+        assert_eq!(frame[now_row * SCREEN_WIDTH], 0x16);
+        assert_eq!(frame[now_row * SCREEN_WIDTH + 200], 0x27);
+    }
+    assert_eq!(frame[31 * SCREEN_WIDTH + 64], 0x30);
+
+    // X scroll written after the hit applies from the next line
+    for now_row in [32, 100, 239] {
+        assert_eq!(frame[now_row * SCREEN_WIDTH], 0x27);
+        assert_eq!(frame[now_row * SCREEN_WIDTH + 200], 0x16);
+    }
+}
+
+#[test]
+fn test_dmc_dma() {
+    use crate::common::DataSizes;
+    use crate::ppu::MirroringType;
+
+    // 16 KB PRG ROM is mirrored to $C000, so the sample is all $FF
+    let prg_rom = vec![0xFFu8; DataSizes::Size16K.to_bytes()];
+    let mut bus = nrom_test_bus(&prg_rom, MirroringType::Horizontal);
+    bus.set_audio_output(true);
+
+    // No frame IRQ; DMC IRQ at the fastest rate, sample of 17 bytes at $C000
+    let mut cpu_cycles = 0;
+    for (address, value) in [(0x4017usize, 0x40u8), (0x4010, 0x8F), (0x4012, 0x00), (0x4013, 0x01), (0x4015, 0x10)] {
+        bus.write_8bit_cpu(address, value, &cpu_cycles);
+        cpu_cycles += 4;
+    }
+    bus.execute_modules(cpu_cycles + 1);
+    assert_eq!(bus.take_dma_stall_cycles(), 4);
+    assert_eq!(bus.read_8bit_cpu(0x4015usize, &cpu_cycles) & 0b1001_0000, 0b0001_0000);
+
+    // Every fetched byte stalls CPU, the last one raises IRQ which is not cleared by $4015 read
+    cpu_cycles += 17 * 8 * 54;
+    bus.execute_modules(cpu_cycles);
+    assert_eq!(bus.take_dma_stall_cycles(), 16 * 4);
+    assert!(bus.irq_line());
+    assert_eq!(bus.read_8bit_cpu(0x4015usize, &cpu_cycles) & 0b1001_0000, 0b1000_0000);
+    assert!(bus.irq_line());
+    bus.write_8bit_cpu(0x4015usize, 0x00, &cpu_cycles);
+    assert!(!bus.irq_line());
+
+    // Sample of set bits raises output level
+    let samples = bus.take_audio_samples();
+    assert!(samples.last().unwrap() > samples.first().unwrap());
+}
+
+#[test]
+fn test_irq_line() {
+    use crate::cartridges;
+    use crate::headless::nrom_test_image;
+
+    // $8000: CLI; JMP $8001, IRQ handler at $8010: INC $10; LDA $4015; RTI
+    let mut nes_data = nrom_test_image(&[0x58, 0x4C, 0x01, 0x80]);
+    nes_data[0x10 + 0x10..0x10 + 0x16].copy_from_slice(&[0xE6, 0x10, 0xAD, 0x15, 0x40, 0x40]);
+    nes_data[0x10 + 0x3FFE..0x10 + 0x4000].copy_from_slice(&[0x10, 0x80]);
+    let (mut cpu, mut bus) = cartridges::load_nes_data(&nes_data).unwrap();
+
+    // APU frame IRQ comes every 29830 CPU cycles and is acknowledged by the handler
+    cpu.run_frames(&mut bus, 3).unwrap();
+    assert_eq!(bus.memory().ram()[0x10], 2);
+    assert!(!bus.irq_line());
+}
+
+#[test]
+fn test_apu_open_bus() {
+    use crate::ppu::MirroringType;
 
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    for (address, cycles) in [(0x4000usize, 7), (0x4014, 8), (0x4018, 9), (0x401F, 10)] {
+        assert_eq!(bus.read_8bit_cpu(address, &cycles), 0x40);
     }
 }
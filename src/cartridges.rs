@@ -9,6 +9,7 @@ use crate::bus::Bus;
 use crate::mappers;
 use crate::common::DataSizes;
 use crate::ppu::MirroringType;
+use crate::region::Region;
 
 const PRGROM_BYTES_IN_UNITS: usize = DataSizes::Size16K.to_bytes();
 const CHRROM_BYTES_IN_UNITS: usize = DataSizes::Size8K.to_bytes();
@@ -17,6 +18,8 @@ const CB1_VRAM_LAYOUT: usize = 3;
 const CB1_TRAINER: usize = 2;
 const CB1_BATTERY_RAM: usize = 1;
 const CB1_MIRRORING_TYPE: usize = 0;
+const CB2_NES2_FORMAT: u8 = 0b0000_1100;
+const NES2_FORMAT_ID: u8 = 0b0000_1000;
 
 #[derive(Debug, Clone, Copy)]
 enum NesCartridgeError {
@@ -40,9 +43,11 @@ impl std::error::Error for NesCartridgeError {}
 impl std::fmt::Display for MirroringType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Self::None => write!(f, "None"),
             Self::Horizontal => write!(f, "Horizontal"),
             Self::Vertical => write!(f, "Vertical"),
+            Self::SingleScreenLower => write!(f, "Single-screen (lower)"),
+            Self::SingleScreenUpper => write!(f, "Single-screen (upper)"),
+            Self::FourScreen => write!(f, "Four-screen"),
         }
     }
 
@@ -58,15 +63,17 @@ struct NESHeaderInfo {
     /// Rom mapper type
     _mapper_type: u8,
     /// Four-screen VRAM layout
-    _four_screen_vram: bool,
+    four_screen_vram: bool,
     /// Trainer include status
     trainer_include: bool,
     /// Battery packed RAM to store saves
     _battery_packed_ram: bool,
     /// Mirroring status
-    _mirroring_type: MirroringType,
+    mirroring_type: MirroringType,
     /// Size of PRG RAM in 8kB units
     _prgram_size: u8,
+    /// TV system from byte 9 (iNES) or byte 12 (NES 2.0)
+    region: Region,
 }
 
 impl NESHeaderInfo {
@@ -106,20 +113,36 @@ impl NESHeaderInfo {
         let prgram_size = input_header_data[8];
         debug!("PRG RAM size (in 8kB units): {prgram_size}");
 
-        if input_header_data[9..].iter().any(|el| *el != 0) {
-            error!("At least one byte at the end(10-16 bytes) of NES header are not zeros");
-            return Err(NesCartridgeError::NESHeaderMustBeZero);
-        }
+        let region = if second_control_byte & CB2_NES2_FORMAT == NES2_FORMAT_ID {
+            match input_header_data[12] & 0b0000_0011 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc, // Multiple-region games run as NTSC
+            }
+        } else {
+            if (input_header_data[9] & 0b1111_1110) != 0 || input_header_data[10..].iter().any(|el| *el != 0) {
+                error!("At least one byte at the end(10-16 bytes) of NES header are not zeros");
+                return Err(NesCartridgeError::NESHeaderMustBeZero);
+            }
+
+            if is_bit_set(input_header_data[9], 0) {
+                Region::Pal
+            } else {
+                Region::Ntsc
+            }
+        };
+        debug!("Region: {region}");
 
         let header_info = NESHeaderInfo {
             number_prgrom_banks,
             _number_chrrom_banks: number_chrrom_banks,
             _mapper_type: mapper_type,
-            _four_screen_vram: four_screen_vram,
+            four_screen_vram,
             trainer_include,
             _battery_packed_ram: battery_packed_ram,
-            _mirroring_type: mirroring_type,
+            mirroring_type,
             _prgram_size: prgram_size,
+            region,
         };
 
         info!("NES header parsed successfully");
@@ -147,6 +170,7 @@ impl NESHeaderInfo {
         info!("Start of creating NES modules");
         let mut cpu = Cpu::default();
         let mut bus = Bus::default();
+        bus.set_region(self.region);
         debug!("CPU and bus initialization complete");
 
         if self.trainer_include {
@@ -161,8 +185,16 @@ impl NESHeaderInfo {
         let prg_data = &file_data[0..prg_rom_size];
         let chr_data = &file_data[prg_rom_size..prg_rom_size+chr_rom_size];
         
+        // Four-screen layout overrides mirroring bit
+        let mirroring = if self.four_screen_vram {
+            MirroringType::FourScreen
+        } else {
+            self.mirroring_type
+        };
+
         let mapper = mappers::create_mapper(
             self._mapper_type,
+            mirroring,
             bus.memory_mut(),
             prg_data,
             chr_data
@@ -186,13 +218,17 @@ fn is_bit_set(input_byte: u8, target_bit: usize) -> bool {
 pub fn read_nes_file(path_to_nes_file: OsString) -> Result<(Cpu, Bus), Box<dyn std::error::Error>> {
     info!("Start of processing NES file");
     let nes_file_data = fs::read(path_to_nes_file)?;
+    debug!("NES file read successfully");
+    load_nes_data(&nes_file_data)
+}
 
+/// Creates modules from iNES file contents
+pub fn load_nes_data(nes_file_data: &[u8]) -> Result<(Cpu, Bus), Box<dyn std::error::Error>> {
     let nes_header = if nes_file_data.len() < 17 {
         return Err(Box::new(NesCartridgeError::NoNESHeader));
     } else {
         &nes_file_data[0..16]
     };
-    debug!("NES file read successfully");
 
     let header_info = NESHeaderInfo::from_bytes(nes_header)?;
     let (cpu, bus) = header_info.create_modules(&nes_file_data[16..]);
@@ -207,6 +243,7 @@ pub fn load_raw_commands(bus: &mut Bus, commands: Vec<u8>) {
 
     let mapper = mappers::create_mapper(
         0,
+        MirroringType::Horizontal,
         bus.memory_mut(),
         &prg_data,
         &vec![0u8; DataSizes::Size8K.to_bytes()],
@@ -229,3 +266,25 @@ fn append_missed_data(mut data_to_append: Vec<u8>, target_size: usize) -> Vec<u8
         }
     }
 }
+
+#[test]
+fn test_header_region() {
+    let mut header = [0u8; 16];
+    header[..4].copy_from_slice("NES\x1A".as_bytes());
+    header[4] = 1;
+
+    assert_eq!(NESHeaderInfo::from_bytes(&header).unwrap().region, Region::Ntsc);
+
+    // iNES 1.0 TV system bit
+    header[9] = 0b0000_0001;
+    assert_eq!(NESHeaderInfo::from_bytes(&header).unwrap().region, Region::Pal);
+    header[9] = 0b0000_0011;
+    assert!(NESHeaderInfo::from_bytes(&header).is_err());
+
+    // NES 2.0 timing byte
+    header[7] = NES2_FORMAT_ID;
+    header[12] = 3;
+    assert_eq!(NESHeaderInfo::from_bytes(&header).unwrap().region, Region::Dendy);
+    header[12] = 2;
+    assert_eq!(NESHeaderInfo::from_bytes(&header).unwrap().region, Region::Ntsc);
+}
@@ -0,0 +1,104 @@
+pub const BUTTON_A: u8 = 0b0000_0001;
+pub const BUTTON_B: u8 = 0b0000_0010;
+pub const BUTTON_SELECT: u8 = 0b0000_0100;
+pub const BUTTON_START: u8 = 0b0000_1000;
+pub const BUTTON_UP: u8 = 0b0001_0000;
+pub const BUTTON_DOWN: u8 = 0b0010_0000;
+pub const BUTTON_LEFT: u8 = 0b0100_0000;
+pub const BUTTON_RIGHT: u8 = 0b1000_0000;
+
+pub const CONTROLLER_1_ADDRESS: usize = 0x4016;
+pub const CONTROLLER_2_ADDRESS: usize = 0x4017;
+pub const CONTROLLER_PORTS: usize = 2;
+
+/// Upper bits of $4016/$4017 are not driven, they keep high byte of address from open bus
+const OPEN_BUS_BITS: u8 = 0x40;
+
+/// Standard joypad: 8-bit shift register reloaded from buttons while strobe is high.
+/// Buttons are read in order A, B, Select, Start, Up, Down, Left, Right, then 1s
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Controller {
+    buttons: u8,
+    shift_register: u8,
+    reads_done: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn write_strobe(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.reload();
+            return OPEN_BUS_BITS | (self.buttons & BUTTON_A);
+        }
+
+        let bit = if self.reads_done < 8 {
+            self.reads_done += 1;
+            let bit = self.shift_register & 1;
+            self.shift_register >>= 1;
+            bit
+        } else {
+            1
+        };
+        OPEN_BUS_BITS | bit
+    }
+
+    fn reload(&mut self) {
+        self.shift_register = self.buttons;
+        self.reads_done = 0;
+    }
+}
+
+/// Button bit by name, case insensitive
+pub fn button_from_name(button_name: &str) -> Option<u8> {
+    match button_name.to_ascii_lowercase().as_str() {
+        "a" => Some(BUTTON_A),
+        "b" => Some(BUTTON_B),
+        "select" => Some(BUTTON_SELECT),
+        "start" => Some(BUTTON_START),
+        "up" => Some(BUTTON_UP),
+        "down" => Some(BUTTON_DOWN),
+        "left" => Some(BUTTON_LEFT),
+        "right" => Some(BUTTON_RIGHT),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_controller_reads() {
+    let mut controller = Controller::default();
+    controller.set_buttons(BUTTON_A | BUTTON_START | BUTTON_RIGHT);
+
+    // While strobe is high only A is reported
+    controller.write_strobe(1);
+    assert_eq!(controller.read(), 0x41);
+    assert_eq!(controller.read(), 0x41);
+
+    controller.write_strobe(0);
+    let bits: Vec<u8> = (0..10).map(|_| controller.read() & 1).collect();
+    assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+
+    // Buttons changed after latch are not visible until next strobe
+    controller.write_strobe(1);
+    controller.write_strobe(0);
+    controller.set_buttons(BUTTON_B);
+    assert_eq!(controller.read() & 1, 1);
+    assert_eq!(controller.read() & 1, 0);
+
+    assert_eq!(button_from_name("Select"), Some(BUTTON_SELECT));
+    assert_eq!(button_from_name("turbo"), None);
+}
@@ -1,12 +1,15 @@
 use better_assertions::{inst_assert_eq, fast_assert};
-use log::{trace, debug, info, warn, error};
+#[cfg(feature = "cpu_trace")]
+use log::trace;
+use log::{debug, info, warn, error};
 
 use crate::memory::MemoryType;
 use crate::bus::Bus;
 use crate::common;
-use instructions::{Operation, CPUInstByte};
+use instructions::{Operation, DecodedOperation, CPUInstByte};
 
 const RESET_ON_CPU_EXEC_ERR: bool = true;
+const INTERRUPT_CYCLES: usize = 7;
 
 pub mod instructions;
 
@@ -21,6 +24,9 @@ const NEGATIVE_FLAG: usize = 7;
 
 static INSTRUCTION_SET: [Operation; 256] = instructions::init_all_operations().0;
 static INSTRUCTION_COUNT: usize = instructions::init_all_operations().1;
+static DECODED_INSTRUCTION_SET: [DecodedOperation; 256] = instructions::init_decoded_operations(
+    &instructions::init_all_operations().0
+);
 
 #[derive(Debug, Clone, Copy)]
 pub enum CpuState {
@@ -36,9 +42,13 @@ pub struct Cpu {
     cpu_status: u8,
     stack_pointer: u8,
     program_counter: u16,
-    instruction_set: &'static [Operation; 256],
+    instruction_set: &'static [DecodedOperation; 256],
     state: CpuState,
+    /// CPU cycles since power on, during instruction execution points to its last cycle
     exec_cycles: usize,
+    /// Additional cycles of current instruction: taken branches and crossed pages
+    extra_cycles: u8,
+    page_crossed: bool,
 }
 
 impl Default for Cpu {
@@ -55,9 +65,11 @@ impl Default for Cpu {
             cpu_status: 0b0010_0100,
             stack_pointer: 0xFD,
             program_counter: 0xFFFF,
-            instruction_set: &INSTRUCTION_SET,
+            instruction_set: &DECODED_INSTRUCTION_SET,
             state: CpuState::Running,
             exec_cycles: 0,
+            extra_cycles: 0,
+            page_crossed: false,
         }
     }
 }
@@ -97,6 +109,10 @@ impl Cpu {
     pub fn get_program_counter(&self) -> u16 {
         self.program_counter
     }
+
+    pub fn get_exec_cycles(&self) -> usize {
+        self.exec_cycles
+    }
 }
 
 impl Cpu {
@@ -135,7 +151,7 @@ impl Cpu {
             },
             MemoryType::IndirectY => {
                 let value_data = self.read_16bit_zp_wrap(bus, value as u16);
-                value_data.wrapping_add(self.reg_y as u16)
+                self.indexed_address(value_data, self.reg_y)
             },
             _ => unreachable!(),
         }
@@ -157,16 +173,48 @@ impl Cpu {
                 self.read_16bit_jmp_bug(bus, value)
             },
             MemoryType::AbsoluteX => {
-                value.wrapping_add(self.reg_x as u16)
+                self.indexed_address(value, self.reg_x)
             },
             MemoryType::AbsoluteY => {
-                value.wrapping_add(self.reg_y as u16)
+                self.indexed_address(value, self.reg_y)
             },
             _ => unreachable!(),
         }
     }
 }
 
+impl Cpu {
+    /// Adds index register to base address, remembers page cross for extra cycle
+    #[inline(always)]
+    fn indexed_address(&mut self, base_address: u16, index: u8) -> u16 {
+        let target_address = base_address.wrapping_add(index as u16);
+        self.page_crossed = (base_address & 0xFF00) != (target_address & 0xFF00);
+        target_address
+    }
+
+    /// Reads 1 byte operand after opcode, converts it to address and moves PC after operand
+    #[inline(always)]
+    pub fn fetch_1byte_address(&mut self, mt: MemoryType, bus: &mut Bus) -> u16 {
+        let next_data_byte = self.read_8bit(bus, self.program_counter);
+        let target_address = self.conv_1byte_address(mt, next_data_byte, bus);
+        #[cfg(feature = "cpu_trace")]
+        trace!("Current data value: {}", common::number_to_hex(target_address, true));
+        self.program_counter = self.program_counter.wrapping_add(1);
+        target_address
+    }
+
+    /// Reads 2 bytes operand after opcode, converts it to address and moves PC after operand
+    #[inline(always)]
+    pub fn fetch_2byte_address(&mut self, mt: MemoryType, bus: &mut Bus) -> u16 {
+        let next_value = self.read_16bit(bus, self.program_counter);
+        let target_address = self.conv_2byte_address(mt, next_value, bus);
+        #[cfg(feature = "cpu_trace")]
+        trace!("Address:{} -> {}", common::number_to_hex(next_value, true), common::number_to_hex(target_address, true));
+        self.program_counter = self.program_counter.wrapping_add(2);
+        target_address
+    }
+}
+
 impl Cpu {
     #[inline(always)]
     pub fn read_8bit<T>(&mut self, bus: &mut Bus, data_ref: T) -> u8
@@ -237,106 +285,136 @@ impl Cpu {
         info!("Leaving RUN CPU on {now_oper}");
     }
 
+    /// Executes instructions until PPU finishes given number of frames
+    pub fn run_frames(&mut self, bus: &mut Bus, frames_num: u64) -> Result<(), &'static str> {
+        let target_frame = bus.ppu().frame_count() + frames_num;
+        while bus.ppu().frame_count() < target_frame {
+            self.execute_cpu_iteration(bus)?;
+        }
+
+        Ok(())
+    }
+
+    /// Executes one instruction (after NMI handler entry, if NMI is pending) and runs other
+    /// modules up to the end of it. Returns number of CPU cycles spent.
     /// CHANGE ALSO execute_cpu_iteration_info
-    pub fn execute_cpu_iteration(&mut self, bus: &mut Bus) -> Result<u8, &'static str> {
+    pub fn execute_cpu_iteration(&mut self, bus: &mut Bus) -> Result<usize, &'static str> {
+        let start_cycles = self.exec_cycles;
+        self.poll_interrupts(bus);
+
         let now_command = self.read_8bit(bus, self.program_counter);
         let now_inst = self.instruction_set[now_command as usize];
-        trace!("CPU got command: {}, instruction: {now_inst}", common::number_to_hex(now_command, true));
-        trace!(
-            "Working with {} bytes of data from {}",
-            now_inst.op_name().as_digit(),
-            common::number_to_hex(self.program_counter, true)
-        );
+        let now_operation = now_inst.operation();
+        #[cfg(feature = "cpu_trace")]
+        self.trace_operation(now_command, &now_operation);
 
-        match now_inst.op_name() {
-            CPUInstByte::One(inst_entry) => {
-                self.program_counter = self.program_counter.wrapping_add(1);
-                self.execute_inst_1_byte(inst_entry, bus);
+        if matches!(now_operation.op_name(), CPUInstByte::NoOp) {
+            return Err(self.noop_parsed(now_command))
+        }
 
-                if matches!(self.state, CpuState::Stopped) {
-                    return Err("CPU was stopped by STP instruction")
-                }
-            },
-            CPUInstByte::Two(inst_entry) => {
-                self.program_counter = self.program_counter.wrapping_add(1);
-                let next_data_byte = self.read_8bit(bus, self.program_counter);
-                let target_byte = self.conv_1byte_address(now_inst.memory_type(), next_data_byte, bus);
-                trace!("Current data value: {}", common::number_to_hex(target_byte, true));
-                self.program_counter = self.program_counter.wrapping_add(1);
-                self.execute_inst_2_byte(bus, inst_entry, target_byte);
-            },
-            CPUInstByte::Three(inst_entry) => {
-                self.program_counter = self.program_counter.wrapping_add(1);
-                let next_value = self.read_16bit(bus, self.program_counter);
-                let target_address = self.conv_2byte_address(now_inst.memory_type(), next_value, bus);
-                trace!("Address:{} -> {}",common::number_to_hex(next_value, true), common::number_to_hex(target_address, true));
-                self.program_counter = self.program_counter.wrapping_add(2);
-                self.execute_inst_3_byte(bus, inst_entry, target_address);
-            },
-            CPUInstByte::NoOp => {
-                error!(
-                    "Trying to parse NoOp instruction at {} with hex {}",
-                    common::number_to_hex(self.program_counter, true),
-                    common::number_to_hex(now_command, true)
-                );
-                return Err("NoOp parsed")
-            }
+        self.start_instruction(&now_operation);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        let target_address = now_inst.fetch_address(self, bus);
+        self.add_page_cross_cycles(&now_operation);
+        now_inst.execute(self, bus, target_address);
+        self.finish_instruction(bus);
+
+        if matches!(self.state, CpuState::Stopped) {
+            return Err("CPU was stopped by STP instruction")
         }
 
-        Ok(now_inst.cycles())
+        Ok(self.exec_cycles - start_cycles)
     }
 
     /// CHANGE ALSO execute_cpu_iteration
     pub fn execute_cpu_iteration_info(&mut self, bus: &mut Bus) -> Result<(Operation, Vec<u8>), &'static str> {
+        self.poll_interrupts(bus);
+
         let now_command = self.read_8bit(bus, self.program_counter);
         let now_inst = self.instruction_set[now_command as usize];
+        let now_operation = now_inst.operation();
+        #[cfg(feature = "cpu_trace")]
+        self.trace_operation(now_command, &now_operation);
+
+        if matches!(now_operation.op_name(), CPUInstByte::NoOp) {
+            return Err(self.noop_parsed(now_command))
+        }
+
         let mut fetched_bytes: Vec<u8> = Vec::new();
         fetched_bytes.push(now_command);
-        trace!("CPU got command: {}, instruction: {now_inst}", common::number_to_hex(now_command, true));
-        trace!(
-            "Working with {} bytes of data from {}",
-            now_inst.op_name().as_digit(),
-            common::number_to_hex(self.program_counter, true)
-        );
+        for now_shift in 1..now_operation.op_name().as_digit() {
+            fetched_bytes.push(self.read_8bit(bus, self.program_counter.wrapping_add(now_shift as u16)));
+        }
 
-        match now_inst.op_name() {
-            CPUInstByte::One(inst_entry) => {
-                self.program_counter = self.program_counter.wrapping_add(1);
-                self.execute_inst_1_byte(inst_entry, bus);
+        self.start_instruction(&now_operation);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        let target_address = now_inst.fetch_address(self, bus);
+        self.add_page_cross_cycles(&now_operation);
+        now_inst.execute(self, bus, target_address);
+        self.finish_instruction(bus);
 
-                if matches!(self.state, CpuState::Stopped) {
-                    return Err("CPU was stopped by STP instruction")
-                }
-            },
-            CPUInstByte::Two(inst_entry) => {
-                self.program_counter = self.program_counter.wrapping_add(1);
-                let next_data_byte = self.read_8bit(bus, self.program_counter);
-                fetched_bytes.push(next_data_byte);
-                let target_byte = self.conv_1byte_address(now_inst.memory_type(), next_data_byte, bus);
-                trace!("Current data value: {}", common::number_to_hex(target_byte, true));
-                self.program_counter = self.program_counter.wrapping_add(1);
-                self.execute_inst_2_byte(bus, inst_entry, target_byte);
-            },
-            CPUInstByte::Three(inst_entry) => {
-                fetched_bytes.push(self.read_8bit(bus, self.program_counter.wrapping_add(1)));
-                fetched_bytes.push(self.read_8bit(bus, self.program_counter.wrapping_add(2)));
-                self.program_counter = self.program_counter.wrapping_add(1);
-                let next_value = self.read_16bit(bus, self.program_counter);
-                let target_address = self.conv_2byte_address(now_inst.memory_type(), next_value, bus);
-                trace!("Address:{} -> {}",common::number_to_hex(next_value, true), common::number_to_hex(target_address, true));
-                self.program_counter = self.program_counter.wrapping_add(2);
-                self.execute_inst_3_byte(bus, inst_entry, target_address);
-            },
-            CPUInstByte::NoOp => {
-                error!(
-                    "Trying to parse NoOp instruction at {} with hex {}",
-                    common::number_to_hex(self.program_counter, true),
-                    common::number_to_hex(now_command, true)
-                );
-                return Err("NoOp parsed")
-            }
+        if matches!(self.state, CpuState::Stopped) {
+            return Err("CPU was stopped by STP instruction")
         }
 
-        Ok((now_inst, fetched_bytes))
+        Ok((now_operation, fetched_bytes))
+    }
+
+    /// Interrupts are checked only between instructions, NMI has priority over IRQ
+    #[inline(always)]
+    fn poll_interrupts(&mut self, bus: &mut Bus) {
+        if bus.poll_nmi() {
+            self.interrupt_nmi(bus);
+        } else if bus.irq_line() && !common::is_bit_set(self.cpu_status, 1 << INTERRUPT_FLAG) {
+            self.interrupt_irq(bus);
+        } else {
+            return
+        }
+
+        self.exec_cycles += INTERRUPT_CYCLES;
+        bus.execute_modules(self.exec_cycles);
+    }
+
+    /// Bus accesses of instruction are timed to its last cycle, which is the exact time
+    /// for the most of register reads and writes
+    #[inline(always)]
+    fn start_instruction(&mut self, now_operation: &Operation) {
+        self.extra_cycles = 0;
+        self.page_crossed = false;
+        self.exec_cycles += now_operation.cycles() as usize - 1;
+    }
+
+    /// Page cross cycle comes before operand access, so register reads see PPU one cycle later
+    #[inline(always)]
+    fn add_page_cross_cycles(&mut self, now_operation: &Operation) {
+        if self.page_crossed {
+            self.exec_cycles += now_operation.cycles_pgcr() as usize;
+        }
+    }
+
+    #[inline(always)]
+    fn finish_instruction(&mut self, bus: &mut Bus) {
+        self.exec_cycles += self.extra_cycles as usize + 1;
+        self.exec_cycles += bus.take_dma_stall_cycles();
+        bus.execute_modules(self.exec_cycles);
+    }
+
+    fn noop_parsed(&self, now_command: u8) -> &'static str {
+        error!(
+            "Trying to parse NoOp instruction at {} with hex {}",
+            common::number_to_hex(self.program_counter, true),
+            common::number_to_hex(now_command, true)
+        );
+        "NoOp parsed"
+    }
+
+    #[cfg(feature = "cpu_trace")]
+    fn trace_operation(&self, now_command: u8, now_operation: &Operation) {
+        trace!("CPU got command: {}, instruction: {now_operation}", common::number_to_hex(now_command, true));
+        trace!(
+            "Working with {} bytes of data from {}",
+            now_operation.op_name().as_digit(),
+            common::number_to_hex(self.program_counter, true)
+        );
     }
 }
@@ -172,132 +172,135 @@ pub enum Inst3Byte {
     LASop,
 }
 
-impl Cpu {
-    pub fn execute_inst_1_byte(&mut self, now_inst: Inst1Byte, bus: &mut Bus) {
-        match now_inst {
-            Inst1Byte::TAXop => self.op_tax(),
-            Inst1Byte::TAYop => self.op_tay(),
-            Inst1Byte::TXAop => self.op_txa(),
-            Inst1Byte::TYAop => self.op_tya(),
-            Inst1Byte::TSXop => self.op_tsx(),
-            Inst1Byte::TXSop => self.op_txs(),
-            Inst1Byte::PHAop => self.op_pha(bus),
-            Inst1Byte::PHPop => self.op_php(bus),
-            Inst1Byte::PLAop => self.op_pla(bus),
-            Inst1Byte::PLPop => self.op_plp(bus),
-            Inst1Byte::INXop => self.op_inx(),
-            Inst1Byte::INYop => self.op_iny(),
-            Inst1Byte::DEXop => self.op_dex(),
-            Inst1Byte::DEYop => self.op_dey(),
-            Inst1Byte::ASLop => self.op_asl_acc(),
-            Inst1Byte::LSRop => self.op_lsr_acc(),
-            Inst1Byte::ROLop => self.op_rol_acc(),
-            Inst1Byte::RORop => self.op_ror_acc(),
-            Inst1Byte::RTSop => self.op_rts(bus),
-            Inst1Byte::CLCop => self.op_clc(),
-            Inst1Byte::CLDop => self.op_cld(),
-            Inst1Byte::CLIop => self.op_cli(),
-            Inst1Byte::CLVop => self.op_clv(),
-            Inst1Byte::SECop => self.op_sec(),
-            Inst1Byte::SEDop => self.op_sed(),
-            Inst1Byte::SEIop => self.op_sei(),
-            Inst1Byte::BRKop => self.op_brk(bus),
-            Inst1Byte::NOPop => self.op_nop(),
-            Inst1Byte::RTIop => self.op_rti(bus),
-            Inst1Byte::STPop => self.op_stp(),
-        }
+/// Executes already decoded instruction with converted operand address
+pub type InstHandler = fn(&mut Cpu, &mut Bus, u16);
+/// Fetches instruction operand after opcode, moves PC and returns converted address
+pub type AddressHandler = fn(&mut Cpu, &mut Bus) -> u16;
+
+const fn inst_1_byte_handler(now_inst: Inst1Byte) -> InstHandler {
+    match now_inst {
+        Inst1Byte::TAXop => |cpu, _, _| cpu.op_tax(),
+        Inst1Byte::TAYop => |cpu, _, _| cpu.op_tay(),
+        Inst1Byte::TXAop => |cpu, _, _| cpu.op_txa(),
+        Inst1Byte::TYAop => |cpu, _, _| cpu.op_tya(),
+        Inst1Byte::TSXop => |cpu, _, _| cpu.op_tsx(),
+        Inst1Byte::TXSop => |cpu, _, _| cpu.op_txs(),
+        Inst1Byte::PHAop => |cpu, bus, _| cpu.op_pha(bus),
+        Inst1Byte::PHPop => |cpu, bus, _| cpu.op_php(bus),
+        Inst1Byte::PLAop => |cpu, bus, _| cpu.op_pla(bus),
+        Inst1Byte::PLPop => |cpu, bus, _| cpu.op_plp(bus),
+        Inst1Byte::INXop => |cpu, _, _| cpu.op_inx(),
+        Inst1Byte::INYop => |cpu, _, _| cpu.op_iny(),
+        Inst1Byte::DEXop => |cpu, _, _| cpu.op_dex(),
+        Inst1Byte::DEYop => |cpu, _, _| cpu.op_dey(),
+        Inst1Byte::ASLop => |cpu, _, _| cpu.op_asl_acc(),
+        Inst1Byte::LSRop => |cpu, _, _| cpu.op_lsr_acc(),
+        Inst1Byte::ROLop => |cpu, _, _| cpu.op_rol_acc(),
+        Inst1Byte::RORop => |cpu, _, _| cpu.op_ror_acc(),
+        Inst1Byte::RTSop => |cpu, bus, _| cpu.op_rts(bus),
+        Inst1Byte::CLCop => |cpu, _, _| cpu.op_clc(),
+        Inst1Byte::CLDop => |cpu, _, _| cpu.op_cld(),
+        Inst1Byte::CLIop => |cpu, _, _| cpu.op_cli(),
+        Inst1Byte::CLVop => |cpu, _, _| cpu.op_clv(),
+        Inst1Byte::SECop => |cpu, _, _| cpu.op_sec(),
+        Inst1Byte::SEDop => |cpu, _, _| cpu.op_sed(),
+        Inst1Byte::SEIop => |cpu, _, _| cpu.op_sei(),
+        Inst1Byte::BRKop => |cpu, bus, _| cpu.op_brk(bus),
+        Inst1Byte::NOPop => |cpu, _, _| cpu.op_nop(),
+        Inst1Byte::RTIop => |cpu, bus, _| cpu.op_rti(bus),
+        Inst1Byte::STPop => |cpu, _, _| cpu.op_stp(),
     }
+}
 
-    pub fn execute_inst_2_byte(&mut self, bus: &mut Bus, now_inst: Inst2Byte, conv_data_ref: u16) {
-        match now_inst {
-            Inst2Byte::LDAop => self.op_lda(bus, conv_data_ref),
-            Inst2Byte::LDXop => self.op_ldx(bus, conv_data_ref),
-            Inst2Byte::LDYop => self.op_ldy(bus, conv_data_ref),
-            Inst2Byte::STAop => self.op_sta(bus, conv_data_ref),
-            Inst2Byte::STXop => self.op_stx(bus, conv_data_ref),
-            Inst2Byte::STYop => self.op_sty(bus, conv_data_ref),
-            Inst2Byte::ANDop => self.op_and(bus, conv_data_ref),
-            Inst2Byte::EORop => self.op_eor(bus, conv_data_ref),
-            Inst2Byte::ORAop => self.op_ora(bus, conv_data_ref),
-            Inst2Byte::BITop => self.op_bit(bus, conv_data_ref),
-            Inst2Byte::ADCop => self.op_adc(bus, conv_data_ref),
-            Inst2Byte::SBCop => self.op_sbc(bus, conv_data_ref),
-            Inst2Byte::CMPop => self.op_cmp(bus, conv_data_ref),
-            Inst2Byte::CPXop => self.op_cpx(bus, conv_data_ref),
-            Inst2Byte::CPYop => self.op_cpy(bus, conv_data_ref),
-            Inst2Byte::INCop => self.op_inc(bus, conv_data_ref),
-            Inst2Byte::DECop => self.op_dec(bus, conv_data_ref),
-            Inst2Byte::ASLop => self.op_asl(bus, conv_data_ref),
-            Inst2Byte::LSRop => self.op_lsr(bus, conv_data_ref),
-            Inst2Byte::ROLop => self.op_rol(bus, conv_data_ref),
-            Inst2Byte::RORop => self.op_ror(bus, conv_data_ref),
-            Inst2Byte::BCCop => self.op_bcc(bus, conv_data_ref),
-            Inst2Byte::BCSop => self.op_bcs(bus, conv_data_ref),
-            Inst2Byte::BEQop => self.op_beq(bus, conv_data_ref),
-            Inst2Byte::BMIop => self.op_bmi(bus, conv_data_ref),
-            Inst2Byte::BNEop => self.op_bne(bus, conv_data_ref),
-            Inst2Byte::BPLop => self.op_bpl(bus, conv_data_ref),
-            Inst2Byte::BVCop => self.op_bvc(bus, conv_data_ref),
-            Inst2Byte::BVSop => self.op_bvs(bus, conv_data_ref),
-            Inst2Byte::ALRop => self.op_alr(bus, conv_data_ref),
-            Inst2Byte::ANCop => self.op_anc(bus, conv_data_ref),
-            Inst2Byte::ARRop => self.op_arr(bus, conv_data_ref),
-            Inst2Byte::AXSop => self.op_axs(bus, conv_data_ref),
-            Inst2Byte::LAXop => self.op_lax(bus, conv_data_ref),
-            Inst2Byte::SAXop => self.op_sax(bus, conv_data_ref),
-            Inst2Byte::DCPop => self.op_dcp(bus, conv_data_ref),
-            Inst2Byte::ISCop => self.op_isc(bus, conv_data_ref),
-            Inst2Byte::RLAop => self.op_rla(bus, conv_data_ref),
-            Inst2Byte::RRAop => self.op_rra(bus, conv_data_ref),
-            Inst2Byte::SLOop => self.op_slo(bus, conv_data_ref),
-            Inst2Byte::SREop => self.op_sre(bus, conv_data_ref),
-            Inst2Byte::NOPop => self.op_nop(),
-            Inst2Byte::XAAop => self.op_xaa(bus, conv_data_ref),
-            Inst2Byte::AHXop => self.op_ahx(bus, conv_data_ref),
-            Inst2Byte::LAX2op => self.op_lax_other_ver(bus, conv_data_ref),
-        };
+const fn inst_2_byte_handler(now_inst: Inst2Byte) -> InstHandler {
+    match now_inst {
+        Inst2Byte::LDAop => Cpu::op_lda,
+        Inst2Byte::LDXop => Cpu::op_ldx,
+        Inst2Byte::LDYop => Cpu::op_ldy,
+        Inst2Byte::STAop => Cpu::op_sta,
+        Inst2Byte::STXop => Cpu::op_stx,
+        Inst2Byte::STYop => Cpu::op_sty,
+        Inst2Byte::ANDop => Cpu::op_and,
+        Inst2Byte::EORop => Cpu::op_eor,
+        Inst2Byte::ORAop => Cpu::op_ora,
+        Inst2Byte::BITop => Cpu::op_bit,
+        Inst2Byte::ADCop => Cpu::op_adc,
+        Inst2Byte::SBCop => Cpu::op_sbc,
+        Inst2Byte::CMPop => Cpu::op_cmp,
+        Inst2Byte::CPXop => Cpu::op_cpx,
+        Inst2Byte::CPYop => Cpu::op_cpy,
+        Inst2Byte::INCop => Cpu::op_inc,
+        Inst2Byte::DECop => Cpu::op_dec,
+        Inst2Byte::ASLop => Cpu::op_asl,
+        Inst2Byte::LSRop => Cpu::op_lsr,
+        Inst2Byte::ROLop => Cpu::op_rol,
+        Inst2Byte::RORop => Cpu::op_ror,
+        Inst2Byte::BCCop => Cpu::op_bcc,
+        Inst2Byte::BCSop => Cpu::op_bcs,
+        Inst2Byte::BEQop => Cpu::op_beq,
+        Inst2Byte::BMIop => Cpu::op_bmi,
+        Inst2Byte::BNEop => Cpu::op_bne,
+        Inst2Byte::BPLop => Cpu::op_bpl,
+        Inst2Byte::BVCop => Cpu::op_bvc,
+        Inst2Byte::BVSop => Cpu::op_bvs,
+        Inst2Byte::ALRop => Cpu::op_alr,
+        Inst2Byte::ANCop => Cpu::op_anc,
+        Inst2Byte::ARRop => Cpu::op_arr,
+        Inst2Byte::AXSop => Cpu::op_axs,
+        Inst2Byte::LAXop => Cpu::op_lax,
+        Inst2Byte::SAXop => Cpu::op_sax,
+        Inst2Byte::DCPop => Cpu::op_dcp,
+        Inst2Byte::ISCop => Cpu::op_isc,
+        Inst2Byte::RLAop => Cpu::op_rla,
+        Inst2Byte::RRAop => Cpu::op_rra,
+        Inst2Byte::SLOop => Cpu::op_slo,
+        Inst2Byte::SREop => Cpu::op_sre,
+        Inst2Byte::NOPop => |cpu, _, _| cpu.op_nop(),
+        Inst2Byte::XAAop => Cpu::op_xaa,
+        Inst2Byte::AHXop => Cpu::op_ahx,
+        Inst2Byte::LAX2op => Cpu::op_lax_other_ver,
     }
+}
 
-    pub fn execute_inst_3_byte(&mut self, bus: &mut Bus, now_inst: Inst3Byte, conv_data_ref: u16) {
-        match now_inst {
-            Inst3Byte::LDAop => self.op_lda(bus, conv_data_ref),
-            Inst3Byte::LDXop => self.op_ldx(bus, conv_data_ref),
-            Inst3Byte::LDYop => self.op_ldy(bus, conv_data_ref),
-            Inst3Byte::STAop => self.op_sta(bus, conv_data_ref),
-            Inst3Byte::STXop => self.op_stx(bus, conv_data_ref),
-            Inst3Byte::STYop => self.op_sty(bus, conv_data_ref),
-            Inst3Byte::ANDop => self.op_and(bus, conv_data_ref),
-            Inst3Byte::EORop => self.op_eor(bus, conv_data_ref),
-            Inst3Byte::ORAop => self.op_ora(bus, conv_data_ref),
-            Inst3Byte::BITop => self.op_bit(bus, conv_data_ref),
-            Inst3Byte::ADCop => self.op_adc(bus, conv_data_ref),
-            Inst3Byte::SBCop => self.op_sbc(bus, conv_data_ref),
-            Inst3Byte::CMPop => self.op_cmp(bus, conv_data_ref),
-            Inst3Byte::CPXop => self.op_cpx(bus, conv_data_ref),
-            Inst3Byte::CPYop => self.op_cpy(bus, conv_data_ref),
-            Inst3Byte::INCop => self.op_inc(bus, conv_data_ref),
-            Inst3Byte::DECop => self.op_dec(bus, conv_data_ref),
-            Inst3Byte::ASLop => self.op_asl(bus, conv_data_ref),
-            Inst3Byte::LSRop => self.op_lsr(bus, conv_data_ref),
-            Inst3Byte::ROLop => self.op_rol(bus, conv_data_ref),
-            Inst3Byte::RORop => self.op_ror(bus, conv_data_ref),
-            Inst3Byte::JMPop => self.op_jmp(conv_data_ref),
-            Inst3Byte::JSRop => self.op_jsr(bus, conv_data_ref),
-            Inst3Byte::LAXop => self.op_lax(bus, conv_data_ref),
-            Inst3Byte::SAXop => self.op_sax(bus, conv_data_ref),
-            Inst3Byte::DCPop => self.op_dcp(bus, conv_data_ref),
-            Inst3Byte::ISCop => self.op_isc(bus, conv_data_ref),
-            Inst3Byte::RLAop => self.op_rla(bus, conv_data_ref),
-            Inst3Byte::RRAop => self.op_rra(bus, conv_data_ref),
-            Inst3Byte::SLOop => self.op_slo(bus, conv_data_ref),
-            Inst3Byte::SREop => self.op_sre(bus, conv_data_ref),
-            Inst3Byte::SHXop => self.op_shx(bus, conv_data_ref),
-            Inst3Byte::SHYop => self.op_shy(bus, conv_data_ref),
-            Inst3Byte::NOPop => self.op_nop(),
-            Inst3Byte::AHXop => self.op_ahx(bus, conv_data_ref),
-            Inst3Byte::TASop => self.op_tas(bus, conv_data_ref),
-            Inst3Byte::LASop => self.op_las(bus, conv_data_ref),
-        }
+const fn inst_3_byte_handler(now_inst: Inst3Byte) -> InstHandler {
+    match now_inst {
+        Inst3Byte::LDAop => Cpu::op_lda,
+        Inst3Byte::LDXop => Cpu::op_ldx,
+        Inst3Byte::LDYop => Cpu::op_ldy,
+        Inst3Byte::STAop => Cpu::op_sta,
+        Inst3Byte::STXop => Cpu::op_stx,
+        Inst3Byte::STYop => Cpu::op_sty,
+        Inst3Byte::ANDop => Cpu::op_and,
+        Inst3Byte::EORop => Cpu::op_eor,
+        Inst3Byte::ORAop => Cpu::op_ora,
+        Inst3Byte::BITop => Cpu::op_bit,
+        Inst3Byte::ADCop => Cpu::op_adc,
+        Inst3Byte::SBCop => Cpu::op_sbc,
+        Inst3Byte::CMPop => Cpu::op_cmp,
+        Inst3Byte::CPXop => Cpu::op_cpx,
+        Inst3Byte::CPYop => Cpu::op_cpy,
+        Inst3Byte::INCop => Cpu::op_inc,
+        Inst3Byte::DECop => Cpu::op_dec,
+        Inst3Byte::ASLop => Cpu::op_asl,
+        Inst3Byte::LSRop => Cpu::op_lsr,
+        Inst3Byte::ROLop => Cpu::op_rol,
+        Inst3Byte::RORop => Cpu::op_ror,
+        Inst3Byte::JMPop => |cpu, _, data_ref| cpu.op_jmp(data_ref),
+        Inst3Byte::JSRop => Cpu::op_jsr,
+        Inst3Byte::LAXop => Cpu::op_lax,
+        Inst3Byte::SAXop => Cpu::op_sax,
+        Inst3Byte::DCPop => Cpu::op_dcp,
+        Inst3Byte::ISCop => Cpu::op_isc,
+        Inst3Byte::RLAop => Cpu::op_rla,
+        Inst3Byte::RRAop => Cpu::op_rra,
+        Inst3Byte::SLOop => Cpu::op_slo,
+        Inst3Byte::SREop => Cpu::op_sre,
+        Inst3Byte::SHXop => Cpu::op_shx,
+        Inst3Byte::SHYop => Cpu::op_shy,
+        Inst3Byte::NOPop => |cpu, _, _| cpu.op_nop(),
+        Inst3Byte::AHXop => Cpu::op_ahx,
+        Inst3Byte::TASop => Cpu::op_tas,
+        Inst3Byte::LASop => Cpu::op_las,
     }
 }
 
@@ -314,7 +317,7 @@ impl Operation {
         self.cycles
     }
 
-    pub fn _cycles_pgcr(&self) -> u8 {
+    pub fn cycles_pgcr(&self) -> u8 {
         self.cycles_pgcr
     }
 
@@ -342,6 +345,87 @@ impl Operation {
     }
 }
 
+/// Operation with handlers resolved at compile time, so CPU iteration doesn't need to match
+/// instruction type, instruction name and memory type on every step
+#[derive(Clone, Copy)]
+pub struct DecodedOperation {
+    operation: Operation,
+    address_handler: AddressHandler,
+    inst_handler: InstHandler,
+}
+
+impl DecodedOperation {
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    #[inline(always)]
+    pub fn fetch_address(&self, cpu: &mut Cpu, bus: &mut Bus) -> u16 {
+        (self.address_handler)(cpu, bus)
+    }
+
+    #[inline(always)]
+    pub fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, data_ref: u16) {
+        (self.inst_handler)(cpu, bus, data_ref)
+    }
+}
+
+impl std::fmt::Debug for DecodedOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "DecodedOperation({:?})", self.operation)
+    }
+}
+
+const fn address_1_byte_handler(memory_type: MemoryType) -> AddressHandler {
+    match memory_type {
+        MemoryType::Immediate => |cpu, bus| cpu.fetch_1byte_address(MemoryType::Immediate, bus),
+        MemoryType::ZeroPage => |cpu, bus| cpu.fetch_1byte_address(MemoryType::ZeroPage, bus),
+        MemoryType::ZeroPageX => |cpu, bus| cpu.fetch_1byte_address(MemoryType::ZeroPageX, bus),
+        MemoryType::ZeroPageY => |cpu, bus| cpu.fetch_1byte_address(MemoryType::ZeroPageY, bus),
+        MemoryType::Relative => |cpu, bus| cpu.fetch_1byte_address(MemoryType::Relative, bus),
+        MemoryType::IndirectX => |cpu, bus| cpu.fetch_1byte_address(MemoryType::IndirectX, bus),
+        MemoryType::IndirectY => |cpu, bus| cpu.fetch_1byte_address(MemoryType::IndirectY, bus),
+        _ => panic!("Memory type can't be used with 2 bytes instruction"),
+    }
+}
+
+const fn address_2_byte_handler(memory_type: MemoryType) -> AddressHandler {
+    match memory_type {
+        MemoryType::Absolute => |cpu, bus| cpu.fetch_2byte_address(MemoryType::Absolute, bus),
+        MemoryType::AbsoluteX => |cpu, bus| cpu.fetch_2byte_address(MemoryType::AbsoluteX, bus),
+        MemoryType::AbsoluteY => |cpu, bus| cpu.fetch_2byte_address(MemoryType::AbsoluteY, bus),
+        MemoryType::Indirect => |cpu, bus| cpu.fetch_2byte_address(MemoryType::Indirect, bus),
+        _ => panic!("Memory type can't be used with 3 bytes instruction"),
+    }
+}
+
+const fn decode_operation(operation: Operation) -> DecodedOperation {
+    let (address_handler, inst_handler): (AddressHandler, InstHandler) = match operation.op_name {
+        CPUInstByte::One(inst) => (|_, _| 0, inst_1_byte_handler(inst)),
+        CPUInstByte::Two(inst) => (address_1_byte_handler(operation.memory_type), inst_2_byte_handler(inst)),
+        CPUInstByte::Three(inst) => (address_2_byte_handler(operation.memory_type), inst_3_byte_handler(inst)),
+        CPUInstByte::NoOp => (|_, _| 0, |_, _, _| unreachable!("NoOp must be filtered before execution")),
+    };
+
+    DecodedOperation {
+        operation,
+        address_handler,
+        inst_handler,
+    }
+}
+
+pub const fn init_decoded_operations(all_operations: &[Operation; 256]) -> [DecodedOperation; 256] {
+    let mut decoded_operations: [DecodedOperation; 256] = [decode_operation(NO_OP); 256];
+
+    let mut now_op = 0;
+    while now_op < all_operations.len() {
+        decoded_operations[now_op] = decode_operation(all_operations[now_op]);
+        now_op += 1;
+    }
+
+    decoded_operations
+}
+
 pub const fn init_all_operations() -> ([Operation; 256], usize) {
     let mut all_operations: [Operation; 256] = [NO_OP; 256];
     let mut oper_counter = 0;
@@ -879,3 +963,53 @@ impl std::fmt::Display for Operation {
         write!(f, "{} at {}", self.op_name, self.memory_type)
     }
 }
+
+#[test]
+fn test_decoded_operations() {
+    let (all_operations, _) = init_all_operations();
+    let decoded_operations = init_decoded_operations(&all_operations);
+
+    for (now_op, now_decoded) in all_operations.iter().zip(decoded_operations.iter()) {
+        let decoded_op = now_decoded.operation();
+        assert_eq!(now_op.cycles(), decoded_op.cycles());
+        assert_eq!(now_op.memory_type(), decoded_op.memory_type());
+        assert_eq!(now_op.op_name().as_digit(), decoded_op.op_name().as_digit());
+    }
+
+    // LDX #$05; LDA #$42; STA $10,X; INX; STX $0200; JMP ($0300)
+    let program: [u8; 15] = [0xA2, 0x05, 0xA9, 0x42, 0x95, 0x10, 0xE8, 0x8E, 0x00, 0x02, 0x6C, 0x00, 0x03, 0x00, 0x00];
+
+    let mut cpu = Cpu::default();
+    let mut bus = Bus::default();
+    for (now_shift, now_byte) in program.iter().enumerate() {
+        cpu.write_8bit(&mut bus, 0x0600 + now_shift, *now_byte);
+    }
+    cpu.write_8bit(&mut bus, 0x0300u16, 0x34);
+    cpu.write_8bit(&mut bus, 0x0301u16, 0x12);
+    cpu.set_pc(0x0600);
+
+    let mut cycles: usize = 0;
+    for _ in 0..6 {
+        cycles += cpu.execute_cpu_iteration(&mut bus).unwrap();
+    }
+
+    assert_eq!(cpu.get_registers_state(), (0x42, 0x06, 0x00));
+    assert_eq!(bus.memory().ram()[0x15], 0x42);
+    assert_eq!(bus.memory().ram()[0x0200], 0x06);
+    assert_eq!(cpu.get_program_counter(), 0x1234);
+    assert_eq!(cycles, 2 + 2 + 4 + 2 + 4 + 5);
+
+    // LDX #$FF; LDA $0201,X; BNE -2
+    let program: [u8; 7] = [0xA2, 0xFF, 0xBD, 0x01, 0x02, 0xD0, 0xFE];
+    for (now_shift, now_byte) in program.iter().enumerate() {
+        cpu.write_8bit(&mut bus, 0x06FA + now_shift, *now_byte);
+    }
+    cpu.set_pc(0x06FA);
+
+    // Page crossed by index costs one more cycle
+    assert_eq!(cpu.execute_cpu_iteration(&mut bus).unwrap(), 2);
+    assert_eq!(cpu.execute_cpu_iteration(&mut bus).unwrap(), 5);
+    // Taken branch costs one more cycle, two if it goes to other page
+    assert_eq!(cpu.execute_cpu_iteration(&mut bus).unwrap(), 4);
+    assert_eq!(cpu.get_program_counter(), 0x06FF);
+}
@@ -4,13 +4,23 @@ use crate::cpu::instructions::shared_ops::is_flag_set;
 use crate::cpu::{CARRY_FLAG, ZERO_FLAG, NEGATIVE_FLAG, OVERFLOW_FLAG};
 
 impl Cpu {
+    /// Moves PC by relative displacement, taken branch costs 1 cycle, 1 more if page is crossed
+    #[inline(always)]
+    fn take_branch(&mut self, bus: &mut Bus, data_ref: u16) {
+        let read_data = self.read_8bit(bus, data_ref);
+        let relative_displacement = (read_data as i8) as i16;
+        let target_address = self.program_counter.wrapping_add_signed(relative_displacement);
+
+        let page_crossed = (self.program_counter & 0xFF00) != (target_address & 0xFF00);
+        self.extra_cycles = 1 + page_crossed as u8;
+        self.program_counter = target_address
+    }
+
     /// Branch if carry flag set
     /// Possible operation HEX: 0xB0
     pub fn op_bcs(&mut self, bus: &mut Bus, data_ref: u16) {
         if is_flag_set(&self.cpu_status, CARRY_FLAG) {
-            let read_data = self.read_8bit(bus, data_ref);
-            let relative_displacement = (read_data as i8) as i16;
-            self.program_counter = self.program_counter.wrapping_add_signed(relative_displacement)
+            self.take_branch(bus, data_ref)
         }
     }
 
@@ -18,9 +28,7 @@ impl Cpu {
     /// Possible operation HEX: 0x90
     pub fn op_bcc(&mut self, bus: &mut Bus, data_ref: u16) {
         if !is_flag_set(&self.cpu_status, CARRY_FLAG) {
-            let read_data = self.read_8bit(bus, data_ref);
-            let relative_displacement = (read_data as i8) as i16;
-            self.program_counter = self.program_counter.wrapping_add_signed(relative_displacement)
+            self.take_branch(bus, data_ref)
         }
     }
 
@@ -28,9 +36,7 @@ impl Cpu {
     /// Possible operation HEX: 0xF0
     pub fn op_beq(&mut self, bus: &mut Bus, data_ref: u16) {
         if is_flag_set(&self.cpu_status, ZERO_FLAG) {
-            let read_data = self.read_8bit(bus, data_ref);
-            let relative_displacement = (read_data as i8) as i16;
-            self.program_counter = self.program_counter.wrapping_add_signed(relative_displacement)
+            self.take_branch(bus, data_ref)
         }
     }
 
@@ -38,9 +44,7 @@ impl Cpu {
     /// Possible operation HEX: 0xD0
     pub fn op_bne(&mut self, bus: &mut Bus, data_ref: u16) {
         if !is_flag_set(&self.cpu_status, ZERO_FLAG) {
-            let read_data = self.read_8bit(bus, data_ref);
-            let relative_displacement = (read_data as i8) as i16;
-            self.program_counter = self.program_counter.wrapping_add_signed(relative_displacement)
+            self.take_branch(bus, data_ref)
         }
     }
 
@@ -48,9 +52,7 @@ impl Cpu {
     /// Possible operation HEX: 0x30
     pub fn op_bmi(&mut self, bus: &mut Bus, data_ref: u16) {
         if is_flag_set(&self.cpu_status, NEGATIVE_FLAG) {
-            let read_data = self.read_8bit(bus, data_ref);
-            let relative_displacement = (read_data as i8) as i16;
-            self.program_counter = self.program_counter.wrapping_add_signed(relative_displacement)
+            self.take_branch(bus, data_ref)
         }
     }
 
@@ -58,9 +60,7 @@ impl Cpu {
     /// Possible operation HEX: 0x10
     pub fn op_bpl(&mut self, bus: &mut Bus, data_ref: u16) {
         if !is_flag_set(&self.cpu_status, NEGATIVE_FLAG) {
-            let read_data = self.read_8bit(bus, data_ref);
-            let relative_displacement = (read_data as i8) as i16;
-            self.program_counter = self.program_counter.wrapping_add_signed(relative_displacement)
+            self.take_branch(bus, data_ref)
         }
     }
 
@@ -68,9 +68,7 @@ impl Cpu {
     /// Possible operation HEX: 0x70
     pub fn op_bvs(&mut self, bus: &mut Bus, data_ref: u16) {
         if is_flag_set(&self.cpu_status, OVERFLOW_FLAG) {
-            let read_data = self.read_8bit(bus, data_ref);
-            let relative_displacement = (read_data as i8) as i16;
-            self.program_counter = self.program_counter.wrapping_add_signed(relative_displacement)
+            self.take_branch(bus, data_ref)
         }
     }
 
@@ -78,9 +76,7 @@ impl Cpu {
     /// Possible operation HEX: 0x50
     pub fn op_bvc(&mut self, bus: &mut Bus, data_ref: u16) {
         if !is_flag_set(&self.cpu_status, OVERFLOW_FLAG) {
-            let read_data = self.read_8bit(bus, data_ref);
-            let relative_displacement = (read_data as i8) as i16;
-            self.program_counter = self.program_counter.wrapping_add_signed(relative_displacement)
+            self.take_branch(bus, data_ref)
         }
     }
 }
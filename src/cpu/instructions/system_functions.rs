@@ -1,11 +1,15 @@
 use better_assertions::inst_assert;
 
-use crate::cpu::Cpu;
-use crate::cpu::{BREAK_FLAG, UNUSED_FLAG};
+use crate::cpu::{Cpu, CpuState, INTERRUPT_CYCLES};
+use crate::cpu::{BREAK_FLAG, UNUSED_FLAG, INTERRUPT_FLAG};
 use crate::cpu::instructions::shared_ops::{set_flag, is_flag_set};
 use crate::bus::Bus;
 
 const UNUSED_FLAG_BIT: u8 = 0b0000_0001 << UNUSED_FLAG;
+const BREAK_FLAG_BIT: u8 = 0b0000_0001 << BREAK_FLAG;
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
 
 impl Cpu {
     /// Creates forced interrupt
@@ -14,13 +18,42 @@ impl Cpu {
         inst_assert!(is_flag_set(&self.cpu_status, UNUSED_FLAG));
         bus.memory_mut().stack_push_16bit(self.program_counter, &mut self.stack_pointer);
         bus.memory_mut().stack_push_8bit(self.cpu_status, &mut self.stack_pointer);
-        self.program_counter = self.read_16bit(bus, 0xFFFE);
+        self.program_counter = self.read_16bit(bus, IRQ_VECTOR);
     }
 
     /// Do literally nothing outside normal change of PC
     pub fn op_nop(&mut self) {
     }
 
+    /// Non-maskable interrupt, same as BRK but pushed status has B flag clear
+    pub fn interrupt_nmi(&mut self, bus: &mut Bus) {
+        self.interrupt(bus, NMI_VECTOR);
+    }
+
+    /// Maskable interrupt from mapper or APU, the same sequence as NMI with its own vector
+    pub fn interrupt_irq(&mut self, bus: &mut Bus) {
+        self.interrupt(bus, IRQ_VECTOR);
+    }
+
+    fn interrupt(&mut self, bus: &mut Bus, vector: u16) {
+        bus.memory_mut().stack_push_16bit(self.program_counter, &mut self.stack_pointer);
+        bus.memory_mut().stack_push_8bit((self.cpu_status & !BREAK_FLAG_BIT) | UNUSED_FLAG_BIT, &mut self.stack_pointer);
+        set_flag(&mut self.cpu_status, INTERRUPT_FLAG, true);
+        self.program_counter = self.read_16bit(bus, vector);
+    }
+
+    /// Reset button, interrupt sequence with suppressed stack writes, so only SP is decremented.
+    /// Other modules are reset too
+    pub fn reset(&mut self, bus: &mut Bus) {
+        bus.reset();
+        self.stack_pointer = self.stack_pointer.wrapping_sub(3);
+        set_flag(&mut self.cpu_status, INTERRUPT_FLAG, true);
+        self.state = CpuState::Running;
+        self.program_counter = self.read_16bit(bus, RESET_VECTOR);
+        self.exec_cycles += INTERRUPT_CYCLES;
+        bus.execute_modules(self.exec_cycles);
+    }
+
     /// Return from interrupt, pulls cpu status and pc from stack
     pub fn op_rti(&mut self, bus: &Bus) {
         self.cpu_status = bus.memory().stack_pull_8bit(&mut self.stack_pointer) | UNUSED_FLAG_BIT;
@@ -35,6 +68,7 @@ fn test_system_functions() {
 
     use crate::mappers;
     use crate::common::DataSizes;
+    use crate::ppu::MirroringType;
 
     let mut rng: StdRng = StdRng::seed_from_u64(42);
 
@@ -49,6 +83,7 @@ fn test_system_functions() {
 
     let mapper = mappers::create_mapper(
         0, // NROM 
+        MirroringType::Horizontal,
         bus.memory_mut(),
         &vec![0u8; DataSizes::Size16K.to_bytes()],
         &vec![0u8; DataSizes::Size8K.to_bytes()]
@@ -0,0 +1,246 @@
+use crate::bus::Bus;
+use crate::palette::Palette;
+use crate::frame_export::RgbaImage;
+use crate::memory::{PPU_NAME_TABLES, PPU_PALETTES};
+use crate::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+const TILE_SIZE: usize = 8;
+const TILES_PER_ROW: usize = 16;
+const PATTERN_TABLE_SIZE: u16 = 0x1000;
+const NAMETABLE_SIZE: u16 = 0x0400;
+const NAMETABLE_COLUMNS: usize = 32;
+const NAMETABLE_ROWS: usize = 30;
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x03C0;
+const OAM_SPRITES: usize = 64;
+const SPRITES_PER_ROW: usize = 8;
+const PALETTE_ENTRIES: usize = 32;
+const PALETTE_SWATCH_SIZE: usize = 16;
+/// Sprite palettes follow 4 background palettes
+const SPRITE_PALETTES_START: u8 = 4;
+
+/// Color of scroll window border on the nametables image
+const SCROLL_WINDOW_COLOR: [u8; 3] = [255, 0, 255];
+
+/// Reads 2-bit pixel of the tile row, x = 0 is the leftmost pixel
+fn tile_pixel(pattern_lo: u8, pattern_hi: u8, x: usize) -> u8 {
+    let shift = 7 - x;
+    ((pattern_hi >> shift) & 1) << 1 | ((pattern_lo >> shift) & 1)
+}
+
+/// RGB of pixel from the palette RAM, pixel 0 is backdrop color
+fn palette_rgb(bus: &mut Bus, palette: &Palette, palette_index: u8, pixel: u8) -> [u8; 3] {
+    let palette_address = if pixel == 0 {
+        PPU_PALETTES.start as u16
+    } else {
+        PPU_PALETTES.start as u16 + ((palette_index as u16) << 2) + pixel as u16
+    };
+    palette.rgb(bus.read_8bit_ppu(palette_address) as u16)
+}
+
+/// Draws 8x8 tile from pattern table at given address, mapper banking is applied
+fn draw_tile(
+    image: &mut RgbaImage,
+    bus: &mut Bus,
+    palette: &Palette,
+    tile_address: u16,
+    palette_index: u8,
+    (left, top): (usize, usize),
+) {
+    for now_row in 0..TILE_SIZE {
+        let pattern_lo = bus.read_8bit_ppu(tile_address + now_row as u16);
+        let pattern_hi = bus.read_8bit_ppu(tile_address + now_row as u16 + 8);
+
+        for now_column in 0..TILE_SIZE {
+            let pixel = tile_pixel(pattern_lo, pattern_hi, now_column);
+            let rgb = palette_rgb(bus, palette, palette_index, pixel);
+            image.set_pixel(left + now_column, top + now_row, rgb);
+        }
+    }
+}
+
+/// Pattern table 0 or 1 as 128x128 image of 16x16 tiles, colored with one of 8 palettes
+pub fn pattern_table(bus: &mut Bus, palette: &Palette, table_index: u8, palette_index: u8) -> RgbaImage {
+    let image_size = TILES_PER_ROW * TILE_SIZE;
+    let mut image = RgbaImage::new(image_size, image_size);
+    let table_start = (table_index as u16 & 1) * PATTERN_TABLE_SIZE;
+
+    for now_tile in 0..TILES_PER_ROW * TILES_PER_ROW {
+        let tile_address = table_start + ((now_tile as u16) << 4);
+        let position = ((now_tile % TILES_PER_ROW) * TILE_SIZE, (now_tile / TILES_PER_ROW) * TILE_SIZE);
+        draw_tile(&mut image, bus, palette, tile_address, palette_index & 0b111, position);
+    }
+
+    image
+}
+
+/// All 4 logical nametables as 512x480 image with the current background pattern table.
+/// Border of the visible screen is drawn at scroll position from t register, wrapping around
+pub fn nametables(bus: &mut Bus, palette: &Palette) -> RgbaImage {
+    let mut image = RgbaImage::new(SCREEN_WIDTH * 2, SCREEN_HEIGHT * 2);
+    let pattern_table = bus.ppu().background_pattern_table();
+
+    for now_nametable in 0..4 {
+        let nametable_start = PPU_NAME_TABLES.start as u16 + now_nametable * NAMETABLE_SIZE;
+        let left = (now_nametable as usize & 1) * SCREEN_WIDTH;
+        let top = (now_nametable as usize >> 1) * SCREEN_HEIGHT;
+
+        for now_tile in 0..NAMETABLE_COLUMNS * NAMETABLE_ROWS {
+            let (column, row) = (now_tile % NAMETABLE_COLUMNS, now_tile / NAMETABLE_COLUMNS);
+            let tile_id = bus.read_8bit_ppu(nametable_start + now_tile as u16);
+
+            let attribute_address = nametable_start
+                + ATTRIBUTE_TABLE_OFFSET
+                + ((row as u16 >> 2) << 3)
+                + (column as u16 >> 2);
+            let quadrant_shift = ((row & 0b10) << 1) | (column & 0b10);
+            let palette_index = (bus.read_8bit_ppu(attribute_address) >> quadrant_shift) & 0b11;
+
+            let tile_address = pattern_table + ((tile_id as u16) << 4);
+            let position = (left + column * TILE_SIZE, top + row * TILE_SIZE);
+            draw_tile(&mut image, bus, palette, tile_address, palette_index, position);
+        }
+    }
+
+    draw_scroll_window(&mut image, bus);
+    image
+}
+
+fn draw_scroll_window(image: &mut RgbaImage, bus: &Bus) {
+    let ppu = bus.ppu();
+    let t = ppu.t_register();
+    let scroll_x = (t.nametables() as usize & 1) * SCREEN_WIDTH
+        + t.coarse_x() as usize * TILE_SIZE
+        + ppu.fine_x_scroll() as usize;
+    let scroll_y = (t.nametables() as usize >> 1) * SCREEN_HEIGHT
+        + t.coarse_y() as usize * TILE_SIZE
+        + t.fine_y() as usize;
+
+    let (width, height) = (image.width, image.height);
+    for now_x in 0..SCREEN_WIDTH {
+        image.set_pixel((scroll_x + now_x) % width, scroll_y % height, SCROLL_WINDOW_COLOR);
+        image.set_pixel((scroll_x + now_x) % width, (scroll_y + SCREEN_HEIGHT - 1) % height, SCROLL_WINDOW_COLOR);
+    }
+    for now_y in 0..SCREEN_HEIGHT {
+        image.set_pixel(scroll_x % width, (scroll_y + now_y) % height, SCROLL_WINDOW_COLOR);
+        image.set_pixel((scroll_x + SCREEN_WIDTH - 1) % width, (scroll_y + now_y) % height, SCROLL_WINDOW_COLOR);
+    }
+}
+
+/// 64 OAM sprites in 8x8 grid with 8x16 cells, in OAM order. Flipping is applied,
+/// position and priority are ignored
+pub fn oam_sprites(bus: &mut Bus, palette: &Palette) -> RgbaImage {
+    let cell_height = TILE_SIZE * 2;
+    let mut image = RgbaImage::new(SPRITES_PER_ROW * TILE_SIZE, (OAM_SPRITES / SPRITES_PER_ROW) * cell_height);
+    let sprite_size = bus.ppu().sprite_size() as usize;
+    let sprite_pattern_table = bus.ppu().sprite_pattern_table();
+    let oam_data = *bus.ppu().oam_data();
+
+    for (now_sprite, sprite) in oam_data.chunks_exact(4).enumerate() {
+        let (tile_id, attribute) = (sprite[1] as u16, sprite[2]);
+        let left = (now_sprite % SPRITES_PER_ROW) * TILE_SIZE;
+        let top = (now_sprite / SPRITES_PER_ROW) * cell_height;
+        let palette_index = SPRITE_PALETTES_START + (attribute & 0b11);
+        let flip_horizontal = attribute & 0b0100_0000 != 0;
+        let flip_vertical = attribute & 0b1000_0000 != 0;
+
+        // 8x16 sprites take pattern table from bit 0 of tile index
+        let tile_address = if sprite_size == 16 {
+            (tile_id & 1) * PATTERN_TABLE_SIZE + ((tile_id & 0xFE) << 4)
+        } else {
+            sprite_pattern_table + (tile_id << 4)
+        };
+
+        for now_row in 0..sprite_size {
+            let pattern_row = if flip_vertical { sprite_size - 1 - now_row } else { now_row };
+            // Second tile of 8x16 sprite follows the first one
+            let row_address = tile_address + ((pattern_row / TILE_SIZE) << 4) as u16 + (pattern_row % TILE_SIZE) as u16;
+            let pattern_lo = bus.read_8bit_ppu(row_address);
+            let pattern_hi = bus.read_8bit_ppu(row_address + 8);
+
+            for now_column in 0..TILE_SIZE {
+                let pattern_column = if flip_horizontal { TILE_SIZE - 1 - now_column } else { now_column };
+                let pixel = tile_pixel(pattern_lo, pattern_hi, pattern_column);
+                let rgb = palette_rgb(bus, palette, palette_index, pixel);
+                image.set_pixel(left + now_column, top + now_row, rgb);
+            }
+        }
+    }
+
+    image
+}
+
+/// 32 palette RAM entries as 16x2 swatches, background palettes in the first row
+pub fn palettes(bus: &mut Bus, palette: &Palette) -> RgbaImage {
+    let row_entries = PALETTE_ENTRIES / 2;
+    let mut image = RgbaImage::new(row_entries * PALETTE_SWATCH_SIZE, 2 * PALETTE_SWATCH_SIZE);
+
+    for now_entry in 0..PALETTE_ENTRIES {
+        let color_index = bus.read_8bit_ppu(PPU_PALETTES.start + now_entry);
+        let rgb = palette.rgb(color_index as u16);
+        let left = (now_entry % row_entries) * PALETTE_SWATCH_SIZE;
+        let top = (now_entry / row_entries) * PALETTE_SWATCH_SIZE;
+
+        for now_y in top..top + PALETTE_SWATCH_SIZE {
+            for now_x in left..left + PALETTE_SWATCH_SIZE {
+                image.set_pixel(now_x, now_y, rgb);
+            }
+        }
+    }
+
+    image
+}
+
+#[test]
+fn test_debug_views() {
+    use crate::bus::nrom_test_bus;
+    use crate::ppu::MirroringType;
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Vertical);
+    let palette = Palette::default();
+
+    // Tile 1: solid pixel 3, tile 2: top row pixel 1, the rest is pixel 2
+    for now_row in 0..8u16 {
+        bus.write_8bit_ppu(0x0010 + now_row, 0xFF);
+        bus.write_8bit_ppu(0x0018 + now_row, 0xFF);
+        bus.write_8bit_ppu(0x0020 + now_row, if now_row == 0 { 0xFF } else { 0x00 });
+        bus.write_8bit_ppu(0x0028 + now_row, if now_row == 0 { 0x00 } else { 0xFF });
+    }
+    for (now_address, now_color) in [(0x3F00u16, 0x0F), (0x3F0D, 0x16), (0x3F0F, 0x30), (0x3F11, 0x21), (0x3F12, 0x1A)] {
+        bus.write_8bit_ppu(now_address, now_color);
+    }
+
+    let pattern_image = pattern_table(&mut bus, &palette, 0, 3);
+    assert_eq!((pattern_image.width, pattern_image.height), (128, 128));
+    assert_eq!(pattern_image.pixel(0, 0), palette.rgb(0x0F));
+    assert_eq!(pattern_image.pixel(8, 0), palette.rgb(0x30));
+    assert_eq!(pattern_image.pixel(16, 0), palette.rgb(0x16));
+    assert_eq!(pattern_image.rgba.len(), 128 * 128 * 4);
+
+    // Tile 1 at the second nametable with palette 3, window scrolled by 4 pixels
+    bus.write_8bit_ppu(0x2400u16, 0x01);
+    bus.write_8bit_ppu(0x27C0u16, 0b0000_0011);
+    bus.write_8bit_cpu(0x2000usize, 0b0000_0001, &0);
+    bus.write_8bit_cpu(0x2005usize, 0x04, &0);
+    bus.write_8bit_cpu(0x2005usize, 0x00, &0);
+
+    let nametables_image = nametables(&mut bus, &palette);
+    assert_eq!((nametables_image.width, nametables_image.height), (512, 480));
+    assert_eq!(nametables_image.pixel(256 + 5, 1), palette.rgb(0x30));
+    assert_eq!(nametables_image.pixel(256 + 4, 0), SCROLL_WINDOW_COLOR);
+    assert_eq!(nametables_image.pixel(3, 239), SCROLL_WINDOW_COLOR);
+    assert_eq!(nametables_image.pixel(3, 240), palette.rgb(0x0F));
+
+    // Sprite 1 uses tile 2 flipped vertically with the first sprite palette
+    for now_byte in [0x00, 0x00, 0x00, 0x00, 0x10, 0x02, 0b1000_0000, 0x20] {
+        bus.write_8bit_cpu(0x2004usize, now_byte, &0);
+    }
+    let sprites_image = oam_sprites(&mut bus, &palette);
+    assert_eq!((sprites_image.width, sprites_image.height), (64, 128));
+    assert_eq!(sprites_image.pixel(8, 7), palette.rgb(0x21));
+    assert_eq!(sprites_image.pixel(8, 0), palette.rgb(0x1A));
+
+    let palettes_image = palettes(&mut bus, &palette);
+    assert_eq!((palettes_image.width, palettes_image.height), (256, 32));
+    assert_eq!(palettes_image.pixel(13 * 16, 0), palette.rgb(0x16));
+    assert_eq!(palettes_image.pixel(16, 16 + 15), palette.rgb(0x21));
+}
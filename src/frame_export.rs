@@ -0,0 +1,234 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::palette::Palette;
+use crate::ppu::{PpuEnhancements, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+/// NES pixels are 8:7 wide on NTSC TV
+pub(crate) const PIXEL_ASPECT_NUMERATOR: usize = 8;
+pub(crate) const PIXEL_ASPECT_DENOMINATOR: usize = 7;
+
+#[derive(Debug)]
+pub enum FrameExportError {
+    FileCreateFailed(std::io::Error),
+    FileWriteFailed(std::io::Error),
+    PngEncodingFailed(png::EncodingError),
+    UnknownFormat(String),
+}
+
+impl std::fmt::Display for FrameExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::FileCreateFailed(err) => write!(f, "Can't create image file: {err}"),
+            Self::FileWriteFailed(err) => write!(f, "Can't write image file: {err}"),
+            Self::PngEncodingFailed(err) => write!(f, "Can't encode PNG image: {err}"),
+            Self::UnknownFormat(extension) => write!(f, "Unknown image format '{extension}', expected png or ppm"),
+        }
+    }
+}
+
+impl std::error::Error for FrameExportError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Binary PPM (P6)
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Result<Self, FrameExportError> {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "ppm" => Ok(Self::Ppm),
+            _ => Err(FrameExportError::UnknownFormat(extension.to_string())),
+        }
+    }
+}
+
+/// Number of pixels hidden by TV frame on every side of the screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Overscan {
+    /// Typical NTSC TV hides 8 lines at the top and at the bottom
+    pub const NTSC: Overscan = Overscan { top: 8, bottom: 8, left: 0, right: 0 };
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExportOptions {
+    /// Stretches frame horizontally to 8:7 pixel aspect ratio
+    pub aspect_correction: bool,
+    pub overscan: Overscan,
+}
+
+impl ExportOptions {
+    /// Export options requested by PPU enhancements, no aspect correction
+    pub fn from_enhancements(enhancements: PpuEnhancements) -> Self {
+        Self {
+            aspect_correction: false,
+            overscan: if enhancements.crop_overscan { Overscan::NTSC } else { Overscan::default() },
+        }
+    }
+}
+
+/// RGBA image, 4 bytes per pixel row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            rgba: vec![0xFF; width * height * 4],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let start = (y * self.width + x) * 4;
+        [self.rgba[start], self.rgba[start + 1], self.rgba[start + 2]]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        let start = (y * self.width + x) * 4;
+        self.rgba[start..start + 3].copy_from_slice(&rgb);
+    }
+
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> RgbaImage {
+        let mut cropped = RgbaImage::new(width, height);
+        for now_y in 0..height {
+            let start = ((y + now_y) * self.width + x) * 4;
+            cropped.rgba[now_y * width * 4..(now_y + 1) * width * 4].copy_from_slice(&self.rgba[start..start + width * 4]);
+        }
+        cropped
+    }
+
+    /// Nearest neighbour scaling
+    pub fn resize(&self, width: usize, height: usize) -> RgbaImage {
+        let mut resized = RgbaImage::new(width, height);
+        for now_y in 0..height {
+            for now_x in 0..width {
+                resized.set_pixel(now_x, now_y, self.pixel(now_x * self.width / width, now_y * self.height / height));
+            }
+        }
+        resized
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), FrameExportError> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(FrameExportError::PngEncodingFailed)?;
+        writer.write_image_data(&self.rgba).map_err(FrameExportError::PngEncodingFailed)
+    }
+
+    /// Binary PPM keeps only RGB channels
+    pub fn write_ppm<W: Write>(&self, mut writer: W) -> Result<(), FrameExportError> {
+        let rgb: Vec<u8> = self.rgba.chunks_exact(4).flat_map(|rgba| [rgba[0], rgba[1], rgba[2]]).collect();
+
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height).map_err(FrameExportError::FileWriteFailed)?;
+        writer.write_all(&rgb).map_err(FrameExportError::FileWriteFailed)
+    }
+
+    /// Format is selected by file extension
+    pub fn save(&self, path: &Path) -> Result<(), FrameExportError> {
+        let format = ImageFormat::from_path(path)?;
+        let file = File::create(path).map_err(FrameExportError::FileCreateFailed)?;
+        let mut writer = BufWriter::new(file);
+
+        match format {
+            ImageFormat::Png => self.write_png(&mut writer)?,
+            ImageFormat::Ppm => self.write_ppm(&mut writer)?,
+        }
+        writer.flush().map_err(FrameExportError::FileWriteFailed)
+    }
+}
+
+/// Converts 256x240 PPU frame buffer to image, cropping overscan first and then scaling
+/// width by 8:7 with nearest neighbour
+pub fn export_frame(frame_buffer: &[u16], palette: &Palette, options: ExportOptions) -> RgbaImage {
+    let overscan = options.overscan;
+    let cropped_width = SCREEN_WIDTH.saturating_sub(overscan.left + overscan.right);
+    let cropped_height = SCREEN_HEIGHT.saturating_sub(overscan.top + overscan.bottom);
+
+    let width = if options.aspect_correction {
+        (cropped_width * PIXEL_ASPECT_NUMERATOR + PIXEL_ASPECT_DENOMINATOR / 2) / PIXEL_ASPECT_DENOMINATOR
+    } else {
+        cropped_width
+    };
+    let mut image = RgbaImage::new(width, cropped_height);
+
+    for now_y in 0..cropped_height {
+        let frame_row = (now_y + overscan.top) * SCREEN_WIDTH;
+        for now_x in 0..width {
+            let cropped_x = now_x * cropped_width / width;
+            let frame_entry = frame_buffer[frame_row + overscan.left + cropped_x];
+            image.set_pixel(now_x, now_y, palette.rgb(frame_entry));
+        }
+    }
+
+    image
+}
+
+#[test]
+fn test_frame_export() {
+    let palette = Palette::default();
+    let mut frame_buffer = vec![0x0Fu16; SCREEN_WIDTH * SCREEN_HEIGHT];
+    frame_buffer[0] = 0x30;
+    frame_buffer[8 * SCREEN_WIDTH + 1] = 0x16;
+    frame_buffer[SCREEN_WIDTH * SCREEN_HEIGHT - 1] = 0x21;
+
+    let image = export_frame(&frame_buffer, &palette, ExportOptions::default());
+    assert_eq!((image.width, image.height), (256, 240));
+    assert_eq!(image.pixel(0, 0), palette.rgb(0x30));
+    assert_eq!(image.pixel(255, 239), palette.rgb(0x21));
+
+    // Overscan rows are removed, pixel (1, 8) becomes (1, 0)
+    let options = ExportOptions { overscan: Overscan::NTSC, ..Default::default() };
+    let image = export_frame(&frame_buffer, &palette, options);
+    assert_eq!((image.width, image.height), (256, 224));
+    assert_eq!(image.pixel(1, 0), palette.rgb(0x16));
+
+    // 8:7 stretch makes 8 pixels from every 7, the first one is doubled
+    let options = ExportOptions { aspect_correction: true, ..Default::default() };
+    let image = export_frame(&frame_buffer, &palette, options);
+    assert_eq!((image.width, image.height), (293, 240));
+    assert_eq!(image.pixel(1, 0), palette.rgb(0x30));
+    assert_eq!(image.pixel(2, 0), palette.rgb(0x0F));
+    assert_eq!(image.pixel(292, 239), palette.rgb(0x21));
+    assert_eq!(image.pixel(2, 8), palette.rgb(0x16));
+
+    let mut ppm_data = Vec::new();
+    export_frame(&frame_buffer, &palette, ExportOptions::default()).write_ppm(&mut ppm_data).unwrap();
+    let header = b"P6\n256 240\n255\n";
+    assert_eq!(&ppm_data[..header.len()], header);
+    assert_eq!(ppm_data.len(), header.len() + 256 * 240 * 3);
+    assert_eq!(ppm_data[header.len()..header.len() + 3], palette.rgb(0x30));
+
+    let cropped = image.crop(2, 8, 10, 4);
+    assert_eq!((cropped.width, cropped.height), (10, 4));
+    assert_eq!(cropped.pixel(0, 0), palette.rgb(0x16));
+    let resized = cropped.resize(5, 8);
+    assert_eq!((resized.width, resized.height), (5, 8));
+    assert_eq!((resized.pixel(0, 1), resized.pixel(0, 2)), (palette.rgb(0x16), palette.rgb(0x0F)));
+
+    let mut png_data = Vec::new();
+    image.write_png(&mut png_data).unwrap();
+    assert_eq!(&png_data[..8], b"\x89PNG\r\n\x1a\n");
+
+    assert_eq!(ImageFormat::from_path(Path::new("shot.PPM")).unwrap(), ImageFormat::Ppm);
+    assert!(matches!(ImageFormat::from_path(Path::new("shot.bmp")), Err(FrameExportError::UnknownFormat(_))));
+}
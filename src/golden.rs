@@ -0,0 +1,306 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::cartridges;
+use crate::controller;
+use crate::headless::{self, InputEvent, RunReport};
+use crate::region::Region;
+
+#[derive(Debug)]
+pub enum GoldenError {
+    SuiteReadFailed(std::io::Error),
+    SuiteParseFailed(toml::de::Error),
+    SuiteSerializeFailed(toml::ser::Error),
+    SuiteWriteFailed(std::io::Error),
+    UnknownButton(String),
+    InvalidPort(usize),
+    RomLoadFailed(String),
+    EmulationFailed(&'static str),
+}
+
+impl std::fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::SuiteReadFailed(err) => write!(f, "Can't read suite file: {err}"),
+            Self::SuiteParseFailed(err) => write!(f, "Can't parse suite file: {err}"),
+            Self::SuiteSerializeFailed(err) => write!(f, "Can't serialize suite: {err}"),
+            Self::SuiteWriteFailed(err) => write!(f, "Can't write suite file: {err}"),
+            Self::UnknownButton(button_name) => write!(f, "Unknown button '{button_name}'"),
+            Self::InvalidPort(port) => write!(f, "Invalid controller port {port}, expected 0 or 1"),
+            Self::RomLoadFailed(err) => write!(f, "Can't load ROM: {err}"),
+            Self::EmulationFailed(err) => write!(f, "Emulation failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+/// Buttons held on the port from the given frame, button names as in `controller::button_from_name`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptedInput {
+    pub frame: u64,
+    #[serde(default)]
+    pub port: usize,
+    #[serde(default)]
+    pub buttons: Vec<String>,
+}
+
+/// Expected hashes as 8 hex digits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedHashes {
+    pub frame: String,
+    pub audio: String,
+    pub ram: String,
+}
+
+impl From<RunReport> for ExpectedHashes {
+    fn from(report: RunReport) -> Self {
+        Self {
+            frame: format!("{:08x}", report.frame_hash),
+            audio: format!("{:08x}", report.audio_hash),
+            ram: format!("{:08x}", report.ram_hash),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoldenTest {
+    pub name: String,
+    /// Relative paths start from the suite file directory
+    pub rom: PathBuf,
+    pub frames: u64,
+    /// Region from the ROM header if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input: Vec<ScriptedInput>,
+    /// Not blessed test has no expectations and always fails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<ExpectedHashes>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GoldenOutcome {
+    Passed,
+    Mismatch { expected: Option<ExpectedHashes>, actual: ExpectedHashes },
+    /// ROM file is missing, ROMs are usually not a part of the repository
+    Skipped,
+    Failed(String),
+}
+
+/// List of ROM runs with expected output hashes, stored as TOML with `[[test]]` tables
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoldenSuite {
+    #[serde(rename = "test", default)]
+    pub tests: Vec<GoldenTest>,
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+impl GoldenTest {
+    fn input_events(&self) -> Result<Vec<InputEvent>, GoldenError> {
+        self.input.iter().map(|now_input| {
+            if now_input.port >= controller::CONTROLLER_PORTS {
+                return Err(GoldenError::InvalidPort(now_input.port))
+            }
+
+            let mut buttons = 0u8;
+            for now_button in &now_input.buttons {
+                buttons |= controller::button_from_name(now_button)
+                    .ok_or_else(|| GoldenError::UnknownButton(now_button.clone()))?;
+            }
+            Ok(InputEvent { frame: now_input.frame, port: now_input.port, buttons })
+        }).collect()
+    }
+
+    pub fn run_with_data(&self, nes_data: &[u8]) -> Result<RunReport, GoldenError> {
+        let input = self.input_events()?;
+        let (mut cpu, mut bus) = cartridges::load_nes_data(nes_data)
+            .map_err(|err| GoldenError::RomLoadFailed(err.to_string()))?;
+        if let Some(region) = self.region {
+            bus.set_region(region);
+        }
+        headless::run_headless(&mut cpu, &mut bus, self.frames, &input).map_err(GoldenError::EmulationFailed)
+    }
+}
+
+impl GoldenSuite {
+    pub fn from_toml(suite_text: &str, base_dir: &Path) -> Result<Self, GoldenError> {
+        let mut suite: GoldenSuite = toml::from_str(suite_text).map_err(GoldenError::SuiteParseFailed)?;
+        suite.base_dir = base_dir.to_path_buf();
+        Ok(suite)
+    }
+
+    pub fn to_toml(&self) -> Result<String, GoldenError> {
+        toml::to_string(self).map_err(GoldenError::SuiteSerializeFailed)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, GoldenError> {
+        let suite_text = fs::read_to_string(path).map_err(GoldenError::SuiteReadFailed)?;
+        Self::from_toml(&suite_text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), GoldenError> {
+        fs::write(path, self.to_toml()?).map_err(GoldenError::SuiteWriteFailed)
+    }
+
+    /// None if ROM file doesn't exist
+    fn run_test(&self, test: &GoldenTest) -> Option<Result<RunReport, GoldenError>> {
+        let rom_path = self.base_dir.join(&test.rom);
+        if !rom_path.exists() {
+            warn!("Golden test '{}': ROM {rom_path:?} not found, skipped", test.name);
+            return None
+        }
+
+        let nes_data = match fs::read(&rom_path) {
+            Ok(nes_data) => nes_data,
+            Err(err) => return Some(Err(GoldenError::RomLoadFailed(err.to_string()))),
+        };
+        Some(test.run_with_data(&nes_data))
+    }
+
+    /// Runs every test and compares hashes with expectations
+    pub fn check(&self) -> Vec<(String, GoldenOutcome)> {
+        self.tests.iter().map(|now_test| {
+            let outcome = match self.run_test(now_test) {
+                None => GoldenOutcome::Skipped,
+                Some(Err(err)) => GoldenOutcome::Failed(err.to_string()),
+                Some(Ok(report)) => {
+                    let actual = ExpectedHashes::from(report);
+                    if now_test.expected.as_ref() == Some(&actual) {
+                        GoldenOutcome::Passed
+                    } else {
+                        GoldenOutcome::Mismatch { expected: now_test.expected.clone(), actual }
+                    }
+                },
+            };
+            (now_test.name.clone(), outcome)
+        }).collect()
+    }
+
+    /// Replaces expectations with the current output, tests with missing ROMs are kept as is
+    pub fn bless(&mut self) -> Result<(), GoldenError> {
+        for now_index in 0..self.tests.len() {
+            if let Some(report) = self.run_test(&self.tests[now_index]) {
+                let now_test = &mut self.tests[now_index];
+                now_test.expected = Some(report?.into());
+                info!("Golden test '{}' blessed", now_test.name);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Program behind golden/roms/stripes.nes: background of striped tiles, constant pulse tone,
+/// holding A on controller 1 scrolls the background by 8 pixels
+#[cfg(test)]
+fn stripes_rom() -> Vec<u8> {
+    headless::nrom_test_image(&[
+        // Reset, wait two vblanks for PPU warm-up
+        0x78, 0xD8, 0xA2, 0xFF, 0x9A, 0xA9, 0x40, 0x8D, 0x17, 0x40,
+        0x2C, 0x02, 0x20, 0x10, 0xFB, 0x2C, 0x02, 0x20, 0x10, 0xFB,
+        // CHR-RAM tile 1 plane 0 = $FF
+        0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA9, 0x10, 0x8D, 0x06, 0x20,
+        0xA2, 0x08, 0xA9, 0xFF, 0x8D, 0x07, 0x20, 0xCA, 0xD0, 0xFA,
+        // Nametable and attributes at $2000 = tiles 0, 1, 0, 1, ...
+        0xA9, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA0, 0x04, 0xA2, 0x00,
+        0x8A, 0x29, 0x01, 0x8D, 0x07, 0x20, 0xE8, 0xD0, 0xF7, 0x88, 0xD0, 0xF4,
+        // Palette entry n = n + $11
+        0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA2, 0x00,
+        0x8A, 0x18, 0x69, 0x11, 0x8D, 0x07, 0x20, 0xE8, 0xE0, 0x20, 0xD0, 0xF4,
+        // Pulse 1: 50% duty, constant volume 15, halted length, period $0FD
+        0xA9, 0x01, 0x8D, 0x15, 0x40, 0xA9, 0xBF, 0x8D, 0x00, 0x40,
+        0xA9, 0xFD, 0x8D, 0x02, 0x40, 0xA9, 0x08, 0x8D, 0x03, 0x40,
+        // Nametable $2000, scroll 0, show background
+        0xA9, 0x00, 0x8D, 0x00, 0x20, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20,
+        0xA9, 0x0A, 0x8D, 0x01, 0x20,
+        // $807E: wait vblank, scroll X = (A button) * 8, loop
+        0x2C, 0x02, 0x20, 0x10, 0xFB,
+        0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40,
+        0xAD, 0x16, 0x40, 0x29, 0x01, 0x0A, 0x0A, 0x0A, 0x8D, 0x05, 0x20,
+        0xA9, 0x00, 0x8D, 0x05, 0x20, 0x4C, 0x7E, 0x80,
+    ])
+}
+
+#[test]
+fn test_golden_suite_format() {
+    let suite_text = r#"
+[[test]]
+name = "echo"
+rom = "roms/echo.nes"
+frames = 3
+input = [{ frame = 1, buttons = ["A", "start"] }]
+
+[[test]]
+name = "missing"
+rom = "roms/missing.nes"
+frames = 1
+region = "pal"
+"#;
+    let mut suite = GoldenSuite::from_toml(suite_text, Path::new("/nonexistent")).unwrap();
+    assert_eq!(suite.tests[0].input[0].port, 0);
+    assert_eq!((suite.tests[0].region, suite.tests[1].region), (None, Some(Region::Pal)));
+    assert_eq!(
+        suite.tests[0].input_events().unwrap(),
+        [InputEvent { frame: 1, port: 0, buttons: controller::BUTTON_A | controller::BUTTON_START }],
+    );
+
+    let nes_data = headless::controller_echo_rom();
+    let report = suite.tests[0].run_with_data(&nes_data).unwrap();
+    assert_eq!(report, suite.tests[0].run_with_data(&nes_data).unwrap());
+
+    // Blessed expectations survive TOML round trip
+    suite.tests[0].expected = Some(report.into());
+    let reloaded = GoldenSuite::from_toml(&suite.to_toml().unwrap(), Path::new("/nonexistent")).unwrap();
+    assert_eq!(reloaded, suite);
+
+    let outcomes = suite.check();
+    assert_eq!(outcomes[1], ("missing".to_string(), GoldenOutcome::Skipped));
+
+    // Region changes the output of the same input
+    suite.tests[0].region = Some(Region::Pal);
+    let pal_report = suite.tests[0].run_with_data(&nes_data).unwrap();
+    assert_ne!(ExpectedHashes::from(pal_report), suite.tests[0].expected.clone().unwrap());
+
+    suite.tests[0].input[0].buttons.push("turbo".to_string());
+    assert!(matches!(suite.tests[0].run_with_data(&nes_data), Err(GoldenError::UnknownButton(_))));
+
+    suite.tests[0].input[0].buttons.pop();
+    suite.tests[0].input[0].port = 2;
+    assert!(matches!(suite.tests[0].run_with_data(&nes_data), Err(GoldenError::InvalidPort(2))));
+}
+
+/// Runs the repository suite, regressions of ROMs available locally fail here.
+/// Use `flynes golden golden/suite.toml --bless` after intended output changes
+#[test]
+fn test_golden_suite() {
+    let suite_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("golden/suite.toml");
+    let suite = GoldenSuite::load(&suite_path).unwrap();
+
+    let outcomes = suite.check();
+    let skipped: Vec<_> = outcomes.iter()
+        .filter(|(_, outcome)| *outcome == GoldenOutcome::Skipped)
+        .map(|(name, _)| name)
+        .collect();
+    if !skipped.is_empty() {
+        eprintln!("Golden tests skipped, ROM not found: {skipped:?}");
+    }
+
+    let failures: Vec<_> = outcomes.iter()
+        .filter(|(_, outcome)| !matches!(outcome, GoldenOutcome::Passed | GoldenOutcome::Skipped))
+        .collect();
+    assert!(failures.is_empty(), "Golden tests failed: {failures:#?}");
+    assert!(outcomes.iter().any(|(_, outcome)| *outcome == GoldenOutcome::Passed), "No golden test ran");
+}
+
+/// In-repo suite ROM must match its source program
+#[test]
+fn test_golden_stripes_rom() {
+    let rom_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("golden/roms/stripes.nes");
+    let nes_data = std::fs::read(rom_path).unwrap();
+    assert!(nes_data == stripes_rom(), "golden/roms/stripes.nes differs from stripes_rom()");
+}
@@ -0,0 +1,112 @@
+use crate::cpu::Cpu;
+use crate::bus::Bus;
+
+/// Buttons held on controller port from the given frame of the run until the next event for this port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub port: usize,
+    pub buttons: u8,
+}
+
+/// CRC32 hashes of emulator output after the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunReport {
+    /// Frame buffer entries (color index and emphasis) as little endian u16
+    pub frame_hash: u32,
+    /// Audio samples as little endian f32
+    pub audio_hash: u32,
+    pub ram_hash: u32,
+}
+
+impl std::fmt::Display for RunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "frame {:08x}, audio {:08x}, ram {:08x}", self.frame_hash, self.audio_hash, self.ram_hash)
+    }
+}
+
+/// Runs emulation for given number of frames without any output devices, applying input
+/// events at the start of their frames. The same ROM and input always give the same report
+pub fn run_headless(
+    cpu: &mut Cpu,
+    bus: &mut Bus,
+    frames_num: u64,
+    input: &[InputEvent],
+) -> Result<RunReport, &'static str> {
+    let mut audio_hasher = crc32fast::Hasher::new();
+    bus.set_audio_output(true);
+
+    for now_frame in 0..frames_num {
+        for now_event in input.iter().filter(|now_event| now_event.frame == now_frame) {
+            bus.set_buttons(now_event.port, now_event.buttons);
+        }
+
+        cpu.run_frames(bus, 1)?;
+
+        for now_sample in bus.take_audio_samples() {
+            audio_hasher.update(&now_sample.to_le_bytes());
+        }
+    }
+
+    let mut frame_hasher = crc32fast::Hasher::new();
+    for now_entry in bus.ppu().frame_buffer() {
+        frame_hasher.update(&now_entry.to_le_bytes());
+    }
+
+    Ok(RunReport {
+        frame_hash: frame_hasher.finalize(),
+        audio_hash: audio_hasher.finalize(),
+        ram_hash: crc32fast::hash(bus.memory().ram()),
+    })
+}
+
+/// iNES image of 16 KB NROM cartridge with CHR-RAM, program starts at $8000
+#[cfg(test)]
+pub(crate) fn nrom_test_image(program: &[u8]) -> Vec<u8> {
+    let mut prg_rom = vec![0xEAu8; 0x4000];
+    prg_rom[..program.len()].copy_from_slice(program);
+    prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+
+    let mut nes_data = b"NES\x1A\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    nes_data.extend(prg_rom);
+    nes_data
+}
+
+/// Program which latches controller 1 every frame and stores the first bit read from $4016 to $0010
+#[cfg(test)]
+pub(crate) fn controller_echo_rom() -> Vec<u8> {
+    // $8000: LDA #1; STA $4016; LDA #0; STA $4016; LDA $4016; STA $10; JMP $8000
+    nrom_test_image(&[
+        0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40,
+        0xAD, 0x16, 0x40, 0x85, 0x10, 0x4C, 0x00, 0x80,
+    ])
+}
+
+#[test]
+fn test_headless_run() {
+    use crate::cartridges;
+    use crate::controller::BUTTON_A;
+
+    let nes_data = controller_echo_rom();
+    let run = |input: &[InputEvent]| {
+        let (mut cpu, mut bus) = cartridges::load_nes_data(&nes_data).unwrap();
+        let report = run_headless(&mut cpu, &mut bus, 4, input).unwrap();
+        (report, bus.memory().ram()[0x10], bus.ppu().frame_count())
+    };
+
+    let (report, a_button, frame_count) = run(&[]);
+    assert_eq!((a_button, frame_count), (0x40, 4));
+    assert_eq!(report, run(&[]).0);
+    // Silent APU still produces samples
+    assert_ne!(report.audio_hash, crc32fast::hash(&[]));
+
+    // Pressed A changes RAM, released before the end gives the same RAM as without input
+    let press = InputEvent { frame: 1, port: 0, buttons: BUTTON_A };
+    let (pressed_report, a_button, _) = run(&[press]);
+    assert_eq!(a_button, 0x41);
+    assert_ne!(pressed_report.ram_hash, report.ram_hash);
+    assert_eq!(pressed_report.frame_hash, report.frame_hash);
+
+    let release = InputEvent { frame: 2, port: 0, buttons: 0 };
+    assert_eq!(run(&[press, release]).0, report);
+}
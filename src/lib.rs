@@ -4,4 +4,14 @@ pub mod cartridges;
 pub mod common;
 pub mod bus;
 pub mod ppu;
+pub mod apu;
+pub mod palette;
+pub mod region;
 pub mod mappers;
+pub mod debug_view;
+pub mod frame_export;
+pub mod controller;
+pub mod headless;
+pub mod golden;
+pub mod blargg;
+pub mod ntsc_filter;
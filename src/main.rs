@@ -5,19 +5,259 @@ pub mod memory;
 pub mod cartridges;
 pub mod common;
 pub mod ppu;
+pub mod apu;
+pub mod palette;
+pub mod region;
 pub mod bus;
 pub mod mappers;
+pub mod debug_view;
+pub mod frame_export;
+pub mod controller;
+pub mod headless;
+pub mod golden;
+pub mod blargg;
+pub mod ntsc_filter;
 
 const WORKFLOW_MODE: u8 = 2;
 
+const USAGE: &str = "Usage:
+    flynes
+    flynes run <ROM> [--screenshot-at-frame N] [--screenshot PATH] [--aspect-8-7] [--pal-file PATH]
+        [--crop-overscan] [--no-sprite-limit] [--no-left-clip] [--ntsc composite|svideo|rgb]
+        [--renderer dot|scanline] [--region ntsc|pal|dendy]
+    flynes debug-view <ROM> <OUTPUT DIR> [--frames N] [--palette N] [--pal-file PATH]
+    flynes golden <SUITE> [--bless] [--region ntsc|pal|dendy]
+    flynes blargg <ROM> [--max-frames N] [--region ntsc|pal|dendy]";
+
 fn main() {
     pretty_env_logger::init();
 
-    match WORKFLOW_MODE {
-        1 => temp_unit(),
-        2 => test_rom(),
-        _ => unreachable!(),
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("run") => run_command(&args[1..]),
+        Some("debug-view") => debug_view_command(&args[1..]),
+        Some("golden") => golden_command(&args[1..]),
+        Some("blargg") => blargg_command(&args[1..]),
+        Some(other) => Err(format!("Unknown command '{other}'\n{USAGE}").into()),
+        None => {
+            match WORKFLOW_MODE {
+                1 => temp_unit(),
+                2 => test_rom(),
+                _ => unreachable!(),
+            }
+            Ok(())
+        },
+    };
+
+    if let Err(err) = result {
+        println!("Error: {err}");
+        std::process::exit(1);
+    }
+}
+
+/// Checks golden suite hashes or re-blesses them with the current output.
+/// Region option applies to tests which don't set their own region
+fn golden_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let [suite_path, options @ ..] = args else {
+        return Err(USAGE.into())
+    };
+
+    let mut bless = false;
+    let mut region: Option<region::Region> = None;
+    let mut options = options.iter();
+    while let Some(now_option) = options.next() {
+        match now_option.as_str() {
+            "--bless" => bless = true,
+            "--region" => region = Some(option_value(&mut options, now_option)?.parse()?),
+            _ => return Err(format!("Unknown option '{now_option}'\n{USAGE}").into()),
+        }
+    }
+
+    let suite_path = std::path::Path::new(suite_path);
+    let mut suite = golden::GoldenSuite::load(suite_path)?;
+    if let Some(region) = region {
+        for now_test in &mut suite.tests {
+            now_test.region.get_or_insert(region);
+        }
+    }
+
+    if bless {
+        suite.bless()?;
+        suite.save(suite_path)?;
+        println!("Blessed {} tests in {suite_path:?}", suite.tests.len());
+        return Ok(())
+    }
+
+    let (mut passed_num, mut skipped_num, mut failed_num) = (0, 0, 0);
+    for (test_name, outcome) in suite.check() {
+        match outcome {
+            golden::GoldenOutcome::Passed => {
+                passed_num += 1;
+                println!("PASS {test_name}");
+            },
+            golden::GoldenOutcome::Skipped => {
+                skipped_num += 1;
+                println!("SKIP {test_name}: ROM not found");
+            },
+            golden::GoldenOutcome::Mismatch { expected, actual } => {
+                failed_num += 1;
+                println!("FAIL {test_name}: expected {expected:?}, got {actual:?}");
+            },
+            golden::GoldenOutcome::Failed(err) => {
+                failed_num += 1;
+                println!("FAIL {test_name}: {err}");
+            },
+        }
+    }
+
+    println!("{passed_num} passed, {skipped_num} skipped, {failed_num} failed");
+    if failed_num > 0 {
+        return Err(format!("{failed_num} golden tests failed").into())
+    }
+    if passed_num == 0 {
+        return Err("No golden test ran".into())
+    }
+    Ok(())
+}
+
+/// Runs test ROM with $6000 status protocol, fails if the test doesn't pass
+fn blargg_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let [rom_path, options @ ..] = args else {
+        return Err(USAGE.into())
+    };
+
+    let mut max_frames: u64 = 60 * 60;
+    let mut region: Option<region::Region> = None;
+    let mut options = options.iter();
+    while let Some(now_option) = options.next() {
+        match now_option.as_str() {
+            "--max-frames" => max_frames = option_value(&mut options, now_option)?.parse()?,
+            "--region" => region = Some(option_value(&mut options, now_option)?.parse()?),
+            _ => return Err(format!("Unknown option '{now_option}'\n{USAGE}").into()),
+        }
+    }
+
+    let (mut cpu_unit, mut bus_unit) = cartridges::read_nes_file(rom_path.into())?;
+    if let Some(region) = region {
+        bus_unit.set_region(region);
     }
+    let report = blargg::run_blargg_test(&mut cpu_unit, &mut bus_unit, max_frames)?;
+    println!("{}", report.message.trim_end());
+
+    if !report.passed() {
+        return Err(format!("Test failed with status {}", report.status).into())
+    }
+    Ok(())
+}
+
+fn option_value<'a>(options: &mut impl Iterator<Item = &'a String>, option_name: &str) -> Result<&'a String, String> {
+    options.next().ok_or_else(|| format!("Missing value for '{option_name}'"))
+}
+
+/// Runs ROM, with screenshot option it stops after given number of frames and saves the last frame
+fn run_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let [rom_path, options @ ..] = args else {
+        return Err(USAGE.into())
+    };
+
+    let mut screenshot_frame: Option<u64> = None;
+    let mut screenshot_path = std::path::PathBuf::from("screenshot.png");
+    let mut aspect_correction = false;
+    let mut ntsc_preset: Option<ntsc_filter::NtscPreset> = None;
+    let mut backend = ppu::RenderBackend::default();
+    let mut region: Option<region::Region> = None;
+    let mut enhancements = ppu::PpuEnhancements::default();
+    let mut pal_file: Option<palette::Palette> = None;
+    let mut options = options.iter();
+    while let Some(now_option) = options.next() {
+        match now_option.as_str() {
+            "--screenshot-at-frame" => screenshot_frame = Some(option_value(&mut options, now_option)?.parse()?),
+            "--screenshot" => screenshot_path = option_value(&mut options, now_option)?.into(),
+            "--aspect-8-7" => aspect_correction = true,
+            "--crop-overscan" => enhancements.crop_overscan = true,
+            "--no-sprite-limit" => enhancements.unlimited_sprites = true,
+            "--no-left-clip" => enhancements.disable_left_clip = true,
+            "--ntsc" => ntsc_preset = Some(option_value(&mut options, now_option)?.parse()?),
+            "--renderer" => backend = option_value(&mut options, now_option)?.parse()?,
+            "--region" => region = Some(option_value(&mut options, now_option)?.parse()?),
+            "--pal-file" => pal_file = Some(palette::Palette::load_pal_file(option_value(&mut options, now_option)?.into())?),
+            _ => return Err(format!("Unknown option '{now_option}'\n{USAGE}").into()),
+        }
+    }
+    if pal_file.is_some() && ntsc_preset.is_some_and(|ntsc_preset| ntsc_preset != ntsc_filter::NtscPreset::Rgb) {
+        return Err("--pal-file applies only to --ntsc rgb, other presets decode colors from the signal".into())
+    }
+
+    let (mut cpu_unit, mut bus_unit) = cartridges::read_nes_file(rom_path.into())?;
+    if let Some(region) = region {
+        bus_unit.set_region(region);
+    }
+    bus_unit.set_ppu_backend(backend);
+    bus_unit.set_ppu_enhancements(enhancements);
+    let Some(screenshot_frame) = screenshot_frame else {
+        run_cpu_measure_time(&mut cpu_unit, &mut bus_unit);
+        return Ok(())
+    };
+
+    cpu_unit.run_frames(&mut bus_unit, screenshot_frame)?;
+    let export_options = frame_export::ExportOptions {
+        aspect_correction,
+        ..frame_export::ExportOptions::from_enhancements(enhancements)
+    };
+    let frame_buffer = bus_unit.ppu().frame_buffer();
+    let image = match ntsc_preset {
+        // NTSC output is already wide, so only overscan rows are cropped and 8:7 stretches rows
+        Some(ntsc_preset) => {
+            let overscan = export_options.overscan;
+            let image = match &pal_file {
+                Some(palette) => ntsc_filter::palette_frame(frame_buffer, palette),
+                None => ntsc_filter::filter_frame(frame_buffer, ntsc_preset, bus_unit.ppu().frame_count()),
+            };
+            let image = image.crop(0, overscan.top, image.width, image.height - overscan.top - overscan.bottom);
+            if aspect_correction { ntsc_filter::correct_aspect(&image) } else { image }
+        },
+        None => frame_export::export_frame(frame_buffer, &pal_file.unwrap_or_default(), export_options),
+    };
+    image.save(&screenshot_path)?;
+
+    info!("Frame {screenshot_frame} saved to {screenshot_path:?}");
+    Ok(())
+}
+
+/// Runs ROM for some frames and dumps pattern tables, nametables, OAM and palettes as PNG files
+fn debug_view_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let [rom_path, output_dir, options @ ..] = args else {
+        return Err(USAGE.into())
+    };
+
+    let mut frames_num: u64 = 60;
+    let mut palette_index: u8 = 0;
+    let mut palette = palette::Palette::default();
+    let mut options = options.iter();
+    while let Some(now_option) = options.next() {
+        match now_option.as_str() {
+            "--frames" => frames_num = option_value(&mut options, now_option)?.parse()?,
+            "--palette" => palette_index = option_value(&mut options, now_option)?.parse()?,
+            "--pal-file" => palette = palette::Palette::load_pal_file(option_value(&mut options, now_option)?.into())?,
+            _ => return Err(format!("Unknown option '{now_option}'\n{USAGE}").into()),
+        }
+    }
+
+    let (mut cpu_unit, mut bus_unit) = cartridges::read_nes_file(rom_path.into())?;
+    cpu_unit.run_frames(&mut bus_unit, frames_num)?;
+
+    let output_dir = std::path::Path::new(output_dir);
+    std::fs::create_dir_all(output_dir)?;
+    for table_index in 0..2 {
+        debug_view::pattern_table(&mut bus_unit, &palette, table_index, palette_index)
+            .save(&output_dir.join(format!("pattern_table_{table_index}.png")))?;
+    }
+    debug_view::nametables(&mut bus_unit, &palette).save(&output_dir.join("nametables.png"))?;
+    debug_view::oam_sprites(&mut bus_unit, &palette).save(&output_dir.join("oam.png"))?;
+    debug_view::palettes(&mut bus_unit, &palette).save(&output_dir.join("palettes.png"))?;
+
+    info!("Debug views saved to {output_dir:?}");
+    Ok(())
 }
 
 fn temp_unit() {
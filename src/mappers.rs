@@ -2,6 +2,8 @@ use better_assertions::inst_assert_eq;
 use enum_dispatch::enum_dispatch;
 
 use crate::memory::Memory;
+use crate::ppu::MirroringType;
+use crate::common::DataSizes;
 use no_mapper::NoMapper;
 use nrom::NROM;
 
@@ -24,8 +26,20 @@ impl Default for Mappers {
 #[enum_dispatch(Mappers)]
 pub trait MapperRW {
     fn read(&self, req_addr: usize, prg_data: &[u8]) -> u8;
-    fn write(&self, req_addr: usize, value: u8, prg_data: &mut [u8]);
+    fn write(&mut self, req_addr: usize, value: u8, prg_data: &mut [u8]);
     fn read_ppu(&self, data_ref: usize, chr_data: &[u8]) -> u8;
+    fn write_ppu(&self, data_ref: usize, value: u8, chr_data: &mut [u8]);
+    /// Current nametable layout, checked on every nametable access so mappers can switch it with register writes
+    fn mirroring(&self) -> MirroringType;
+    /// Every PPU access to pattern tables and nametables made by rendering or $2007, with PPU dot
+    /// since power on. Scanline counters watch A12 here, CHR latches watch tile fetches
+    #[inline(always)]
+    fn notify_ppu_access(&mut self, _ppu_address: usize, _ppu_cycle: usize) {}
+    /// Mapper IRQ output, CPU takes IRQ while it's asserted and I flag is clear
+    #[inline(always)]
+    fn irq_asserted(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -48,11 +62,101 @@ pub fn fill_ram(prg_data: &mut Vec<u8>, num: usize) {
     *prg_data = vec![0u8; num];
 }
 
-pub fn create_mapper(mapper_type: u8, mem_module: &mut Memory, prg_rom: &[u8], chr_rom: &[u8]) -> Result<Mappers, MappersError> {
+pub fn create_mapper(
+    mapper_type: u8,
+    mirroring: MirroringType,
+    mem_module: &mut Memory,
+    prg_rom: &[u8],
+    chr_rom: &[u8]
+) -> Result<Mappers, MappersError> {
     let mapper = match mapper_type {
-        0 => NROM::init(mem_module, prg_rom, chr_rom)?,
+        0 => NROM::init(mem_module, mirroring, prg_rom, chr_rom)?,
         _ => unimplemented!("Other mappers unimplemented")
     };
 
+    if mirroring == MirroringType::FourScreen {
+        *mem_module.cartridge_vram_mut() = vec![0u8; DataSizes::Size2K.to_bytes()];
+    }
+
     Ok(mapper)
 }
+
+/// Scanline counter input of MMC3-like mappers: A12 rises filtered by the time A12 was low
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct A12Watcher {
+    a12_rises: usize,
+    a12_low_since: Option<usize>,
+}
+
+#[cfg(test)]
+impl MapperRW for A12Watcher {
+    fn read(&self, _req_addr: usize, _prg_data: &[u8]) -> u8 {
+        0
+    }
+
+    fn write(&mut self, _req_addr: usize, _value: u8, _prg_data: &mut [u8]) {}
+
+    fn read_ppu(&self, _data_ref: usize, _chr_data: &[u8]) -> u8 {
+        0
+    }
+
+    fn write_ppu(&self, _data_ref: usize, _value: u8, _chr_data: &mut [u8]) {}
+
+    fn mirroring(&self) -> MirroringType {
+        MirroringType::Horizontal
+    }
+
+    fn notify_ppu_access(&mut self, ppu_address: usize, ppu_cycle: usize) {
+        // Rise counts only after A12 was low for a while, short drops between sprite fetches are ignored
+        const A12_LOW_DOTS: usize = 10;
+
+        if ppu_address & 0x1000 == 0 {
+            self.a12_low_since.get_or_insert(ppu_cycle);
+            return
+        }
+
+        if let Some(low_since) = self.a12_low_since.take()
+            && ppu_cycle - low_since >= A12_LOW_DOTS
+        {
+            self.a12_rises += 1;
+        }
+    }
+}
+
+#[test]
+fn test_mapper_ppu_notifications() {
+    use crate::bus::nrom_test_bus;
+    use crate::ppu::{Ppu, RenderBackend, PPU_CTRL_REG, PPU_MASK_REG};
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+
+    for backend in [RenderBackend::Dot, RenderBackend::Scanline] {
+        let mut ppu = Ppu::default();
+        ppu.set_backend(backend);
+        // Background at $0000, sprites at $1000
+        ppu.write_to_registers(PPU_CTRL_REG, 0x08);
+        ppu.write_to_registers(PPU_MASK_REG, 0x18);
+
+        let mut accesses = Vec::new();
+        let mut ppu_bus = bus.ppu_bus();
+        ppu_bus.set_access_log(&mut accesses);
+        while ppu.frame_count() < 2 {
+            ppu.execute_cycles(1, &mut ppu_bus);
+        }
+
+        // One rise per rendered line, pre-render line included
+        let mut watcher = A12Watcher::default();
+        for (ppu_address, ppu_cycle) in accesses {
+            watcher.notify_ppu_access(ppu_address, ppu_cycle);
+        }
+        assert_eq!(watcher.a12_rises, 2 * 241, "{backend:?}");
+    }
+
+    // Debug accesses are not visible to mapper
+    let mut debug_accesses = Vec::new();
+    let mut debug_bus = bus.ppu_bus();
+    debug_bus.set_access_log(&mut debug_accesses);
+    debug_bus.read_8bit_ppu(0x1000usize);
+    assert!(debug_accesses.is_empty());
+}
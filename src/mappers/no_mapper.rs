@@ -1,4 +1,5 @@
 use crate::mappers::MapperRW;
+use crate::ppu::MirroringType;
 
 #[derive(Debug, Clone)]
 pub struct NoMapper {
@@ -15,11 +16,19 @@ impl MapperRW for NoMapper {
         unreachable!("Trying to use NoMapper (CPU read)");
     }
 
-    fn write(&self, _data_ref: usize, _value: u8, _prg_data: &mut [u8]) {
+    fn write(&mut self, _data_ref: usize, _value: u8, _prg_data: &mut [u8]) {
         unreachable!("Trying to use NoMapper (CPU write)");
     }
 
     fn read_ppu(&self, _data_ref: usize, _chr_data: &[u8]) -> u8 {
         unreachable!("Trying to use NoMapper (PPU read)");
     }
+
+    fn write_ppu(&self, _data_ref: usize, _value: u8, _chr_data: &mut [u8]) {
+        unreachable!("Trying to use NoMapper (PPU write)");
+    }
+
+    fn mirroring(&self) -> MirroringType {
+        unreachable!("Trying to use NoMapper (nametable mirroring)");
+    }
 }
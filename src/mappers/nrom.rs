@@ -5,6 +5,7 @@ use crate::mappers;
 use crate::mappers::{Mappers, MapperRW, MappersError};
 use crate::memory::Memory;
 use crate::common::DataSizes;
+use crate::ppu::MirroringType;
 
 const PRG_ROM_CAPACITY: [usize; 2] = [DataSizes::Size16K.to_bytes(), DataSizes::Size32K.to_bytes()];
 const PRG_RAM_CAPACITY: usize = DataSizes::Size8K.to_bytes();
@@ -15,10 +16,12 @@ const CHR_CAPACITY: usize = DataSizes::Size8K.to_bytes();
 pub struct NROM {
     prg_rom_start: usize,
     prg_rom_mirror: bool,
+    chr_ram: bool,
+    mirroring: MirroringType,
 }
 
 impl NROM {
-    pub fn init(mem_module: &mut Memory, prg_rom: &[u8], chr_rom: &[u8]) -> Result<Mappers, MappersError> {
+    pub fn init(mem_module: &mut Memory, mirroring: MirroringType, prg_rom: &[u8], chr_rom: &[u8]) -> Result<Mappers, MappersError> {
         if !PRG_ROM_CAPACITY.contains(&prg_rom.len()) {
             return Err(MappersError::IncorrectSizePRGROM);
         }
@@ -27,16 +30,22 @@ impl NROM {
         mappers::fill_ram(mem_module.prg_data_mut(), PRG_RAM_CAPACITY);
         mem_module.prg_data_mut().extend_from_slice(prg_rom);
         
-        if chr_rom.len() != CHR_CAPACITY {
+        // Cartridges without CHR-ROM have 8kB of CHR-RAM instead
+        let chr_ram = chr_rom.is_empty();
+        if chr_ram {
+            *mem_module.chr_data_mut() = vec![0u8; CHR_CAPACITY];
+        } else if chr_rom.len() != CHR_CAPACITY {
             return Err(MappersError::IncorrectSizeCHRROM);
+        } else {
+            *mem_module.chr_data_mut() = chr_rom.to_vec();
         }
-
-        *mem_module.chr_data_mut() = chr_rom.to_vec();
         
         Ok(Mappers::NROM(
             NROM {
                 prg_rom_start: PRG_RAM_CAPACITY,
-                prg_rom_mirror
+                prg_rom_mirror,
+                chr_ram,
+                mirroring,
             }
         ))
     }
@@ -62,7 +71,7 @@ impl MapperRW for NROM {
         }
     }
 
-    fn write(&self, data_ref: usize, value: u8, prg_data: &mut [u8]) {
+    fn write(&mut self, data_ref: usize, value: u8, prg_data: &mut [u8]) {
         inst_assert!((0x4020..=0xFFFF).contains(&data_ref));
 
         if data_ref < PRG_RAM_HW_START {
@@ -84,4 +93,19 @@ impl MapperRW for NROM {
 
         chr_data[data_ref]
     }
+
+    fn write_ppu(&self, data_ref: usize, value: u8, chr_data: &mut [u8]) {
+        inst_assert!((0x0000..=0x1FFF).contains(&data_ref));
+
+        if !self.chr_ram {
+            warn!("Trying to write to CHR-ROM, ignored");
+            return
+        }
+
+        chr_data[data_ref] = value;
+    }
+
+    fn mirroring(&self) -> MirroringType {
+        self.mirroring
+    }
 }
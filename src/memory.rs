@@ -82,7 +82,7 @@ pub const PPU_NAME_TABLES: MemoryAllocInfo = MemoryAllocInfo {
     size: 0x1000,
 };
 
-pub const PPU_UNUSED_SPACE: MemoryAllocInfo = MemoryAllocInfo {
+pub const PPU_NAME_TABLES_MIRRORS: MemoryAllocInfo = MemoryAllocInfo {
     start: 0x3000,
     end: 0x3EFF,
     size: 0x0F00
@@ -94,6 +94,12 @@ pub const PPU_PALETTES: MemoryAllocInfo = MemoryAllocInfo {
     size: 0x0020,
 };
 
+pub const PPU_PALETTES_MIRRORS: MemoryAllocInfo = MemoryAllocInfo {
+    start: 0x3F20,
+    end: 0x3FFF,
+    size: 0x00E0,
+};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryType {
     Implied,
@@ -137,6 +143,8 @@ pub struct Memory {
     chr_data: Vec<u8>,
     ram: [u8; RAM.size],
     vram: [u8; DataSizes::Size2K.to_bytes()],
+    /// Additional nametables on cartridges with four-screen layout
+    cartridge_vram: Vec<u8>,
     palettes_table: [u8; PPU_PALETTES.size]
 }
 
@@ -148,6 +156,7 @@ impl Default for Memory {
             chr_data: Vec::new(),
             ram: [0u8; RAM.size],
             vram: [0u8; DataSizes::Size2K.to_bytes()],
+            cartridge_vram: Vec::new(),
             palettes_table: [0u8; PPU_PALETTES.size],
         }
     }
@@ -187,10 +196,26 @@ impl Memory {
     pub fn vram(&self) -> &[u8; DataSizes::Size2K.to_bytes()] {
         &self.vram
     }
+
+    pub fn vram_mut(&mut self) -> &mut [u8; DataSizes::Size2K.to_bytes()] {
+        &mut self.vram
+    }
+
+    pub fn cartridge_vram(&self) -> &Vec<u8> {
+        &self.cartridge_vram
+    }
+
+    pub fn cartridge_vram_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.cartridge_vram
+    }
     
     pub fn palettes_table(&self) -> &[u8; PPU_PALETTES.size] {
         &self.palettes_table
     }
+
+    pub fn palettes_table_mut(&mut self) -> &mut [u8; PPU_PALETTES.size] {
+        &mut self.palettes_table
+    }
 }
 
 impl Memory {
@@ -328,6 +353,7 @@ fn test_stack_push_pull() {
         chr_data: Vec::new(),
         ram: [0u8; RAM.size],
         vram: [0u8; DataSizes::Size2K.to_bytes()],
+        cartridge_vram: Vec::new(),
         palettes_table: [0u8; PPU_PALETTES.size],
     };
 
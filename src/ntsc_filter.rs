@@ -0,0 +1,227 @@
+use std::f32::consts::PI;
+use std::sync::LazyLock;
+
+use crate::frame_export::{RgbaImage, PIXEL_ASPECT_NUMERATOR, PIXEL_ASPECT_DENOMINATOR};
+use crate::palette::{self, NtscParameters, Palette, SUBCARRIER_PHASES, COLOR_BURST_PHASE, SIGNAL_GAMMA};
+use crate::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+/// Output width close to what TV shows for 256 pixels at 8:7, every pixel is 2.35 output pixels wide
+pub const NTSC_OUTPUT_WIDTH: usize = 602;
+
+/// PPU pixel lasts 8 master clock ticks, colour subcarrier cycle takes 12 ticks
+const SAMPLES_PER_PIXEL: usize = 8;
+const LINE_SAMPLES: usize = SCREEN_WIDTH * SAMPLES_PER_PIXEL;
+/// Scanline of 341 dots shifts subcarrier phase by 341 * 8 % 12 ticks
+const LINE_PHASE_SHIFT: usize = 4;
+/// Odd frames are one dot shorter with rendering enabled, so frames alternate between two phases
+const FRAME_PHASE_SHIFT: usize = 4;
+
+/// RGB preset decodes every entry alone, so it's the generated palette without gamma correction
+static RGB_PRESET_PALETTE: LazyLock<Palette> = LazyLock::new(|| {
+    Palette::generate_ntsc(NtscParameters { gamma: SIGNAL_GAMMA, ..Default::default() })
+});
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NtscPreset {
+    /// Luma and chroma share one signal: dithering is blended and edges get colour fringes
+    #[default]
+    Composite,
+    /// Separate luma and chroma: sharp picture without fringes, chroma is still blurred
+    SVideo,
+    /// Every pixel is decoded alone, NTSC palette without signal artefacts
+    Rgb,
+}
+
+impl NtscPreset {
+    /// Samples averaged for luma and chroma, 12 samples is one subcarrier cycle
+    fn filter_widths(&self) -> (usize, usize) {
+        match self {
+            // RGB preset takes colors from the palette table and isn't filtered
+            Self::Composite | Self::Rgb => (SUBCARRIER_PHASES, SUBCARRIER_PHASES),
+            Self::SVideo => (SAMPLES_PER_PIXEL / 2, SUBCARRIER_PHASES * 2),
+        }
+    }
+}
+
+impl std::str::FromStr for NtscPreset {
+    type Err = String;
+
+    fn from_str(preset_name: &str) -> Result<Self, Self::Err> {
+        match preset_name.to_ascii_lowercase().as_str() {
+            "composite" => Ok(Self::Composite),
+            "svideo" | "s-video" => Ok(Self::SVideo),
+            "rgb" => Ok(Self::Rgb),
+            _ => Err(format!("Unknown NTSC preset '{preset_name}', expected composite, svideo or rgb")),
+        }
+    }
+}
+
+fn yiq_to_rgb(y: f32, i: f32, q: f32) -> [u8; 3] {
+    palette::yiq_to_rgb(y, i, q).map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// (cos, sin) of demodulation angle for every subcarrier phase
+fn subcarrier() -> [(f32, f32); SUBCARRIER_PHASES] {
+    std::array::from_fn(|now_phase| {
+        let angle = 2.0 * PI * (now_phase as f32 + COLOR_BURST_PHASE) / SUBCARRIER_PHASES as f32;
+        (angle.cos(), angle.sin())
+    })
+}
+
+/// Averages luma and demodulates chroma of the samples window centred at given sample
+fn decode_window(
+    luma: &[f32],
+    chroma: &[f32],
+    center: usize,
+    line_phase: usize,
+    widths: (usize, usize),
+    carrier: &[(f32, f32); SUBCARRIER_PHASES],
+) -> (f32, f32, f32) {
+    let sample = |signal: &[f32], index: isize| {
+        usize::try_from(index).ok().and_then(|index| signal.get(index)).copied().unwrap_or(0.0)
+    };
+    let window = |width: usize| {
+        let start = center as isize - width as isize / 2;
+        start..start + width as isize
+    };
+
+    let (luma_width, chroma_width) = widths;
+    let y = window(luma_width).map(|now_index| sample(luma, now_index)).sum::<f32>() / luma_width as f32;
+
+    let (mut i, mut q) = (0.0, 0.0);
+    for now_index in window(chroma_width) {
+        let phase = (line_phase as isize + now_index).rem_euclid(SUBCARRIER_PHASES as isize) as usize;
+        let value = sample(chroma, now_index);
+        i += value * carrier[phase].0;
+        q += value * carrier[phase].1;
+    }
+
+    (y, 2.0 * i / chroma_width as f32, 2.0 * q / chroma_width as f32)
+}
+
+/// Stretches frame to NTSC output width taking every pixel colour from the palette, like RGB preset does
+pub fn palette_frame(frame_buffer: &[u16], palette: &Palette) -> RgbaImage {
+    let mut image = RgbaImage::new(NTSC_OUTPUT_WIDTH, SCREEN_HEIGHT);
+    for (now_y, now_line) in frame_buffer.chunks_exact(SCREEN_WIDTH).enumerate() {
+        for now_x in 0..NTSC_OUTPUT_WIDTH {
+            image.set_pixel(now_x, now_y, palette.rgb(now_line[now_x * SCREEN_WIDTH / NTSC_OUTPUT_WIDTH]));
+        }
+    }
+    image
+}
+
+/// Output pixels are wider than high, stretching rows gives square pixels with 8:7 picture aspect
+pub fn correct_aspect(image: &RgbaImage) -> RgbaImage {
+    let scaled_width = SCREEN_WIDTH * PIXEL_ASPECT_NUMERATOR;
+    let height = (image.height * NTSC_OUTPUT_WIDTH * PIXEL_ASPECT_DENOMINATOR + scaled_width / 2) / scaled_width;
+    image.resize(image.width, height)
+}
+
+/// Simulates NTSC signal of 256x240 frame buffer (colour index and emphasis bits) and decodes it
+/// the way TV does. Artefacts move between frames, so frame number selects subcarrier phase
+pub fn filter_frame(frame_buffer: &[u16], preset: NtscPreset, frame_count: u64) -> RgbaImage {
+    if preset == NtscPreset::Rgb {
+        return palette_frame(frame_buffer, &RGB_PRESET_PALETTE)
+    }
+    let mut image = RgbaImage::new(NTSC_OUTPUT_WIDTH, SCREEN_HEIGHT);
+
+    let frame_phase = (frame_count % 2) as usize * FRAME_PHASE_SHIFT;
+    let widths = preset.filter_widths();
+    let carrier = subcarrier();
+    let mut composite = vec![0.0f32; LINE_SAMPLES];
+    let mut luma = vec![0.0f32; LINE_SAMPLES];
+    let mut chroma = vec![0.0f32; LINE_SAMPLES];
+
+    for (now_y, now_line) in frame_buffer.chunks_exact(SCREEN_WIDTH).enumerate() {
+        let line_phase = (frame_phase + now_y * LINE_PHASE_SHIFT) % SUBCARRIER_PHASES;
+
+        for (now_x, &now_entry) in now_line.iter().enumerate() {
+            let pixel_samples = now_x * SAMPLES_PER_PIXEL..(now_x + 1) * SAMPLES_PER_PIXEL;
+            for now_sample in pixel_samples.clone() {
+                composite[now_sample] = palette::signal_level(now_entry, (line_phase + now_sample) % SUBCARRIER_PHASES);
+            }
+
+            // S-Video luma is the square wave average, chroma is what remains of the signal
+            let pixel_luma = (0..SUBCARRIER_PHASES).map(|now_phase| palette::signal_level(now_entry, now_phase)).sum::<f32>()
+                / SUBCARRIER_PHASES as f32;
+            for now_sample in pixel_samples {
+                luma[now_sample] = pixel_luma;
+                chroma[now_sample] = composite[now_sample] - pixel_luma;
+            }
+        }
+
+        let (luma_signal, chroma_signal) = match preset {
+            NtscPreset::SVideo => (&luma, &chroma),
+            _ => (&composite, &composite),
+        };
+        for now_x in 0..NTSC_OUTPUT_WIDTH {
+            let center = (now_x * 2 + 1) * LINE_SAMPLES / (NTSC_OUTPUT_WIDTH * 2);
+            let (y, i, q) = decode_window(luma_signal, chroma_signal, center, line_phase, widths, &carrier);
+            image.set_pixel(now_x, now_y, yiq_to_rgb(y, i, q));
+        }
+    }
+
+    image
+}
+
+#[test]
+fn test_ntsc_filter() {
+    let rgb_distance = |left: [u8; 3], right: [u8; 3]| {
+        (0..3).map(|now_channel| left[now_channel].abs_diff(right[now_channel])).max().unwrap()
+    };
+    let is_gray = |rgb: [u8; 3]| rgb_distance(rgb, [rgb[0]; 3]) <= 2;
+    let decode_entry = |frame_entry: u16| RGB_PRESET_PALETTE.rgb(frame_entry);
+
+    // Plain decoding keeps grays neutral and hues in place
+    assert!(is_gray(decode_entry(0x00)) && is_gray(decode_entry(0x10)) && is_gray(decode_entry(0x20)));
+    assert_eq!(decode_entry(0x30), [255, 255, 255]);
+    assert_eq!(decode_entry(0x0F), [0, 0, 0]);
+    let red = decode_entry(0x16);
+    assert!(red[0] > 2 * red[1] && red[0] > 2 * red[2]);
+    let blue = decode_entry(0x12);
+    assert!(blue[2] > 2 * blue[0] && blue[2] > 2 * blue[1]);
+    let red_emphasis = decode_entry(0x30 | 0b001 << 6);
+    assert!(red_emphasis[0] > red_emphasis[2] + 20);
+
+    // Flat colour decodes the same with every preset
+    let frame_buffer = vec![0x16u16; SCREEN_WIDTH * SCREEN_HEIGHT];
+    for now_preset in [NtscPreset::Composite, NtscPreset::SVideo, NtscPreset::Rgb] {
+        let image = filter_frame(&frame_buffer, now_preset, 0);
+        assert_eq!((image.width, image.height), (NTSC_OUTPUT_WIDTH, SCREEN_HEIGHT));
+        assert!(rgb_distance(image.pixel(300, 100), red) <= 3);
+    }
+
+    // Black to white edge gets colour fringes on composite only
+    let edge_frame: Vec<u16> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+        .map(|now_index| if now_index % SCREEN_WIDTH < 128 { 0x0F } else { 0x30 })
+        .collect();
+    let edge_pixels = |preset| {
+        let image = filter_frame(&edge_frame, preset, 0);
+        (290..312).map(|now_x| image.pixel(now_x, 100)).collect::<Vec<_>>()
+    };
+    assert!(edge_pixels(NtscPreset::Composite).into_iter().any(|rgb| !is_gray(rgb)));
+    assert!(edge_pixels(NtscPreset::SVideo).into_iter().all(is_gray));
+
+    // Dithered columns are blended by composite luma filter
+    let dither_frame: Vec<u16> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+        .map(|now_index| if now_index % 2 == 0 { 0x0F } else { 0x30 })
+        .collect();
+    let luma_range = |preset| {
+        let image = filter_frame(&dither_frame, preset, 1);
+        let lumas: Vec<u32> = (280..320).map(|now_x| {
+            let [r, g, b] = image.pixel(now_x, 50).map(u32::from);
+            (r * 299 + g * 587 + b * 114) / 1000
+        }).collect();
+        lumas.iter().max().unwrap() - lumas.iter().min().unwrap()
+    };
+    assert_eq!(luma_range(NtscPreset::Rgb), 255);
+    assert!(luma_range(NtscPreset::Composite) < 128);
+
+    // 8:7 picture aspect with square pixels
+    let image = correct_aspect(&filter_frame(&frame_buffer, NtscPreset::Rgb, 0));
+    assert_eq!((image.width, image.height), (NTSC_OUTPUT_WIDTH, 494));
+    assert_eq!(palette_frame(&frame_buffer, &Palette::default()).pixel(300, 100), Palette::default().rgb(0x16));
+
+    assert_eq!("S-Video".parse(), Ok(NtscPreset::SVideo));
+    assert!("pal".parse::<NtscPreset>().is_err());
+}
@@ -0,0 +1,297 @@
+use std::fs;
+use std::ffi::OsString;
+
+use log::info;
+
+/// Number of colors addressable by 6-bit palette index
+pub const PALETTE_COLORS: usize = 64;
+/// Number of colors for every combination of 3 emphasis bits
+pub const PALETTE_COLORS_WITH_EMPHASIS: usize = PALETTE_COLORS * 8;
+
+const PAL_FILE_SIZE: usize = PALETTE_COLORS * 3;
+const PAL_FILE_WITH_EMPHASIS_SIZE: usize = PALETTE_COLORS_WITH_EMPHASIS * 3;
+
+const COLOR_INDEX_MASK: u16 = 0b0000_0000_0011_1111;
+const EMPHASIS_SHIFT: u16 = 6;
+const EMPHASIS_RED: usize = 0b001;
+const EMPHASIS_GREEN: usize = 0b010;
+const EMPHASIS_BLUE: usize = 0b100;
+
+/// Level of color channels which are not emphasized
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/// 2C02 signal voltages for low and high part of the wave, per luma level
+const LEVELS_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const LEVELS_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK_LEVEL: f32 = LEVELS_LOW[1];
+const WHITE_LEVEL: f32 = LEVELS_HIGH[3];
+/// Signal samples per color subcarrier cycle
+pub(crate) const SUBCARRIER_PHASES: usize = 12;
+/// Decoder reference phase in samples, aligns hue $6 with red
+pub(crate) const COLOR_BURST_PHASE: f32 = 3.5;
+/// Display gamma the signal is made for, generating palette with it skips gamma correction
+pub(crate) const SIGNAL_GAMMA: f32 = 2.2;
+
+/// Default 2C02 palette, 64 RGB colors
+const NTSC_PALETTE: [[u8; 3]; PALETTE_COLORS] = [
+    [84, 84, 84], [0, 30, 116], [8, 16, 144], [48, 0, 136], [68, 0, 100], [92, 0, 48], [84, 4, 0], [60, 24, 0],
+    [32, 42, 0], [8, 58, 0], [0, 64, 0], [0, 60, 0], [0, 50, 60], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [152, 150, 152], [8, 76, 196], [48, 50, 236], [92, 30, 228], [136, 20, 176], [160, 20, 100], [152, 34, 32], [120, 60, 0],
+    [84, 90, 0], [40, 114, 0], [8, 124, 0], [0, 118, 40], [0, 102, 120], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [76, 154, 236], [120, 124, 236], [176, 98, 236], [228, 84, 236], [236, 88, 180], [236, 106, 100], [212, 136, 32],
+    [160, 170, 0], [116, 196, 0], [76, 208, 32], [56, 204, 108], [56, 180, 204], [60, 60, 60], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236], [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180], [160, 214, 228], [160, 162, 160], [0, 0, 0], [0, 0, 0],
+];
+
+#[derive(Debug)]
+pub enum PaletteError {
+    IncorrectFileSize(usize),
+    FileReadFailed(std::io::Error),
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::IncorrectFileSize(size) => write!(
+                f, "Palette file must contain {PAL_FILE_SIZE} or {PAL_FILE_WITH_EMPHASIS_SIZE} bytes, got {size}"
+            ),
+            Self::FileReadFailed(err) => write!(f, "Can't read palette file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+/// Parameters of composite video decoding used to generate palette
+#[derive(Debug, Clone, Copy)]
+pub struct NtscParameters {
+    /// Hue rotation in degrees
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+    pub gamma: f32,
+}
+
+impl Default for NtscParameters {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.8,
+        }
+    }
+}
+
+/// RGB colors for every palette index and emphasis combination.
+/// Entry is indexed by `emphasis << 6 | color index`, emphasis bits are red, green, blue from lowest
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: [[u8; 3]; PALETTE_COLORS_WITH_EMPHASIS],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::from_colors(&NTSC_PALETTE)
+    }
+}
+
+impl Palette {
+    /// Builds emphasis variants by attenuating channels which are not emphasized
+    fn from_colors(base_colors: &[[u8; 3]; PALETTE_COLORS]) -> Self {
+        let mut colors = [[0u8; 3]; PALETTE_COLORS_WITH_EMPHASIS];
+
+        for (now_entry, now_color) in colors.iter_mut().enumerate() {
+            let color_index = now_entry & COLOR_INDEX_MASK as usize;
+            let emphasis = now_entry >> EMPHASIS_SHIFT;
+            *now_color = base_colors[color_index];
+
+            // Emphasis doesn't affect black columns $xE and $xF
+            if emphasis == 0 || color_index & 0x0F >= 0x0E {
+                continue
+            }
+
+            let emphasized_channels = [emphasis & EMPHASIS_RED, emphasis & EMPHASIS_GREEN, emphasis & EMPHASIS_BLUE];
+            for (channel, is_emphasized) in now_color.iter_mut().zip(emphasized_channels) {
+                if is_emphasized == 0 {
+                    *channel = (*channel as f32 * EMPHASIS_ATTENUATION).round() as u8;
+                }
+            }
+        }
+
+        Self { colors }
+    }
+
+    /// Parses .pal file data: 64 RGB colors or 512 RGB colors with all emphasis combinations
+    pub fn from_pal_bytes(pal_data: &[u8]) -> Result<Self, PaletteError> {
+        match pal_data.len() {
+            PAL_FILE_SIZE => {
+                let mut base_colors = [[0u8; 3]; PALETTE_COLORS];
+                for (now_color, rgb) in base_colors.iter_mut().zip(pal_data.chunks_exact(3)) {
+                    now_color.copy_from_slice(rgb);
+                }
+                Ok(Self::from_colors(&base_colors))
+            },
+            PAL_FILE_WITH_EMPHASIS_SIZE => {
+                let mut colors = [[0u8; 3]; PALETTE_COLORS_WITH_EMPHASIS];
+                for (now_color, rgb) in colors.iter_mut().zip(pal_data.chunks_exact(3)) {
+                    now_color.copy_from_slice(rgb);
+                }
+                Ok(Self { colors })
+            },
+            other_size => Err(PaletteError::IncorrectFileSize(other_size)),
+        }
+    }
+
+    pub fn load_pal_file(path_to_pal_file: OsString) -> Result<Self, PaletteError> {
+        let pal_data = fs::read(&path_to_pal_file).map_err(PaletteError::FileReadFailed)?;
+        let palette = Self::from_pal_bytes(&pal_data)?;
+        info!("Palette loaded from {path_to_pal_file:?}");
+        Ok(palette)
+    }
+
+    /// Generates palette by decoding 2C02 composite signal, 12 samples per color subcarrier cycle
+    pub fn generate_ntsc(parameters: NtscParameters) -> Self {
+        let mut colors = [[0u8; 3]; PALETTE_COLORS_WITH_EMPHASIS];
+
+        for (now_entry, now_color) in colors.iter_mut().enumerate() {
+            let (mut y, mut i, mut q) = (0.0f32, 0.0f32, 0.0f32);
+            for now_phase in 0..SUBCARRIER_PHASES {
+                let level = signal_level(now_entry as u16, now_phase);
+                let angle = 2.0 * std::f32::consts::PI * (now_phase as f32 + COLOR_BURST_PHASE) / SUBCARRIER_PHASES as f32
+                    + parameters.hue.to_radians();
+                y += level;
+                i += level * angle.cos();
+                q += level * angle.sin();
+            }
+
+            // Product with the carrier averages to half of chroma amplitude
+            let phases = SUBCARRIER_PHASES as f32;
+            let y = (y / phases) * parameters.contrast + parameters.brightness;
+            let i = (2.0 * i / phases) * parameters.saturation * parameters.contrast;
+            let q = (2.0 * q / phases) * parameters.saturation * parameters.contrast;
+
+            let to_channel = |value: f32| {
+                let value = value.clamp(0.0, 1.0).powf(SIGNAL_GAMMA / parameters.gamma);
+                (value * 255.0).round() as u8
+            };
+            *now_color = yiq_to_rgb(y, i, q).map(to_channel);
+        }
+
+        Self { colors }
+    }
+
+    /// RGB color for PPU frame buffer entry: 6-bit color index with emphasis bits above
+    pub fn rgb(&self, frame_entry: u16) -> [u8; 3] {
+        self.colors[frame_entry as usize % PALETTE_COLORS_WITH_EMPHASIS]
+    }
+
+    /// Converts PPU frame buffer to RGBA bytes, 4 bytes per pixel
+    pub fn fill_rgba_frame(&self, frame_buffer: &[u16], rgba_frame: &mut [u8]) {
+        for (now_entry, rgba) in frame_buffer.iter().zip(rgba_frame.chunks_exact_mut(4)) {
+            let [r, g, b] = self.rgb(*now_entry);
+            rgba.copy_from_slice(&[r, g, b, 0xFF]);
+        }
+    }
+
+    pub fn rgba_frame(&self, frame_buffer: &[u16]) -> Vec<u8> {
+        let mut rgba_frame = vec![0u8; frame_buffer.len() * 4];
+        self.fill_rgba_frame(frame_buffer, &mut rgba_frame);
+        rgba_frame
+    }
+}
+
+/// Normalized 2C02 signal (0 is black, 1 is white) of frame buffer entry at given subcarrier phase
+pub(crate) fn signal_level(frame_entry: u16, phase: usize) -> f32 {
+    let hue = (frame_entry & 0x0F) as usize;
+    let emphasis = (frame_entry >> EMPHASIS_SHIFT) as usize;
+    // Columns $xE and $xF are forced black
+    let luma = if hue >= 0x0E { 1 } else { ((frame_entry >> 4) & 0b11) as usize };
+
+    let (low, high) = match hue {
+        0x00 => (LEVELS_HIGH[luma], LEVELS_HIGH[luma]),
+        0x0D..=0x0F => (LEVELS_LOW[luma], LEVELS_LOW[luma]),
+        _ => (LEVELS_LOW[luma], LEVELS_HIGH[luma]),
+    };
+    let in_color_phase = |hue: usize| (hue + phase) % SUBCARRIER_PHASES < SUBCARRIER_PHASES / 2;
+    let mut signal = if in_color_phase(hue) { high } else { low };
+
+    let is_attenuated = (emphasis & EMPHASIS_RED != 0 && in_color_phase(0))
+        || (emphasis & EMPHASIS_GREEN != 0 && in_color_phase(4))
+        || (emphasis & EMPHASIS_BLUE != 0 && in_color_phase(8));
+    if is_attenuated && hue < 0x0E {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+
+    (signal - BLACK_LEVEL) / (WHITE_LEVEL - BLACK_LEVEL)
+}
+
+/// Linear RGB channels of decoded YIQ color, not clamped
+pub(crate) fn yiq_to_rgb(y: f32, i: f32, q: f32) -> [f32; 3] {
+    [
+        y + 0.946_882 * i + 0.623_557 * q,
+        y - 0.274_788 * i - 0.635_691 * q,
+        y - 1.108_545 * i + 1.709_007 * q,
+    ]
+}
+
+#[test]
+fn test_palette_conversion() {
+    let palette = Palette::default();
+    assert_eq!(palette.rgb(0x00), [84, 84, 84]);
+    assert_eq!(palette.rgb(0x30), [236, 238, 236]);
+    assert_eq!(palette.rgb(0x0F), [0, 0, 0]);
+
+    // Red emphasis darkens green and blue channels, black stays untouched
+    assert_eq!(palette.rgb(0x30 | (EMPHASIS_RED as u16) << 6), [236, 178, 176]);
+    assert_eq!(palette.rgb(0x3F | 0b111 << 6), [0, 0, 0]);
+
+    let rgba = palette.rgba_frame(&[0x00, 0x30]);
+    assert_eq!(rgba, [84, 84, 84, 0xFF, 236, 238, 236, 0xFF]);
+
+    // 192 bytes file gets generated emphasis, 1536 bytes file is used as is
+    let pal_data: Vec<u8> = (0..PAL_FILE_SIZE).map(|i| (i / 3) as u8).collect();
+    let palette = Palette::from_pal_bytes(&pal_data).unwrap();
+    assert_eq!(palette.rgb(0x21), [0x21; 3]);
+    assert_eq!(palette.rgb(0x21 | (EMPHASIS_BLUE as u16) << 6), [25, 25, 33]);
+
+    let pal_data: Vec<u8> = (0..PAL_FILE_WITH_EMPHASIS_SIZE).map(|i| (i / 3 / 8) as u8).collect();
+    let palette = Palette::from_pal_bytes(&pal_data).unwrap();
+    assert_eq!(palette.rgb(0x1C0), [0x38; 3]);
+
+    assert!(matches!(Palette::from_pal_bytes(&[0u8; 100]), Err(PaletteError::IncorrectFileSize(100))));
+}
+
+#[test]
+fn test_palette_generation() {
+    let palette = Palette::generate_ntsc(NtscParameters::default());
+
+    // Greys have no chroma, brightness grows with luma
+    let greys = [0x00, 0x10, 0x20].map(|now_index| palette.rgb(now_index));
+    for [r, g, b] in greys {
+        assert!(r.abs_diff(g) <= 1 && g.abs_diff(b) <= 1);
+    }
+    assert!(greys[0][0] < greys[1][0] && greys[1][0] < greys[2][0]);
+    assert_eq!(palette.rgb(0x0F), [0, 0, 0]);
+    assert_eq!(palette.rgb(0x1D), [0, 0, 0]);
+
+    // Color $16 is red, $1A is green, $12 is blue
+    let [r, g, b] = palette.rgb(0x16);
+    assert!(r > g && r > b);
+    let [r, g, b] = palette.rgb(0x1A);
+    assert!(g > r && g > b);
+    let [r, g, b] = palette.rgb(0x12);
+    assert!(b > r && b > g);
+
+    // Emphasis only makes color darker
+    let plain = palette.rgb(0x20);
+    let emphasized = palette.rgb(0x20 | 0b111 << 6);
+    assert!(emphasized.iter().zip(plain).all(|(e, p)| *e < p));
+
+    let desaturated = Palette::generate_ntsc(NtscParameters { saturation: 0.0, ..Default::default() });
+    let [r, g, b] = desaturated.rgb(0x16);
+    assert!(r.abs_diff(g) <= 1 && g.abs_diff(b) <= 1);
+}
@@ -2,29 +2,53 @@ use log::warn;
 use better_assertions::inst_assert;
 
 use crate::common::is_bit_set;
+use crate::bus::PpuBus;
+use crate::memory::{PPU_PALETTES, PPU_NAME_TABLES_MIRRORS, PPU_NAME_TABLES};
+use crate::region::Region;
+use background::BackgroundPipeline;
+use sprites::SpritePipeline;
 
-const PPU_CTRL_REG: usize = 0;
-const PPU_MASK_REG: usize = 1;
-const PPU_STATUS_REG: usize = 2;
-const OAM_ADDR_REG: usize = 3;
-const OAM_DATA_REG: usize = 4;
-const PPU_SCROLL_REG: usize = 5;
-const PPU_ADDR_REG: usize = 6;
-const PPU_DATA_REG: usize = 7;
-const OAM_DMA_REG: usize = 8;
+mod background;
+mod sprites;
+mod scanline;
 
-#[derive(Debug, Clone, Copy)]
-pub enum PpuRenderStatus {
-    NmiTrigger,
-    EndOfFrame,
-}
+pub const PPU_CTRL_REG: usize = 0;
+pub const PPU_MASK_REG: usize = 1;
+pub const PPU_STATUS_REG: usize = 2;
+pub const OAM_ADDR_REG: usize = 3;
+pub const OAM_DATA_REG: usize = 4;
+pub const PPU_SCROLL_REG: usize = 5;
+pub const PPU_ADDR_REG: usize = 6;
+pub const PPU_DATA_REG: usize = 7;
+pub const OAM_DMA_REG: usize = 8;
+
+const PPU_ADDRESS_SPACE_MASK: u16 = 0b0011_1111_1111_1111;
+
+const LAST_DOT: u16 = 340;
+/// Bits 2-4 of sprite attribute byte are not implemented in OAM and read back as 0
+const SPRITE_ATTRIBUTE_BYTE: usize = 2;
+const SPRITE_ATTRIBUTE_MASK: u8 = 0b1110_0011;
+/// Status register drives only vblank, sprite 0 hit and overflow bits, the rest comes from I/O latch
+const PPU_STATUS_MASK: u8 = 0b1110_0000;
+/// Palette entries are 6-bit, upper bits of palette reads come from I/O latch
+const PALETTE_ENTRY_MASK: u8 = 0b0011_1111;
+const IO_LATCH_DECAY_MS: u64 = 600;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
 
+/// Layout of 4 logical nametables ($2000, $2400, $2800, $2C00) in physical nametable memory
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MirroringType {
-    None,
     Horizontal,
     Vertical,
+    /// All nametables use first 1kB of console VRAM
+    SingleScreenLower,
+    /// All nametables use second 1kB of console VRAM
+    SingleScreenUpper,
+    /// Cartridge provides additional 2kB of VRAM, every nametable is unique
+    FourScreen,
 }
 
 impl MirroringType {
@@ -35,6 +59,23 @@ impl MirroringType {
             MirroringType::Horizontal
         }
     }
+
+    /// Converts $2000-$3EFF address to offset in 4kB of physical nametable memory,
+    /// offsets from $0800 point to the cartridge VRAM
+    pub fn nametable_offset(&self, requested_address: usize) -> usize {
+        let nametable = (requested_address >> 10) & 0b11;
+        let nametable_byte = requested_address & 0x03FF;
+
+        let physical_nametable = match self {
+            MirroringType::Horizontal => nametable >> 1,
+            MirroringType::Vertical => nametable & 0b01,
+            MirroringType::SingleScreenLower => 0,
+            MirroringType::SingleScreenUpper => 1,
+            MirroringType::FourScreen => nametable,
+        };
+
+        (physical_nametable << 10) | nametable_byte
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -95,12 +136,6 @@ impl PpuCtrlSettings {
     }
 }
 
-impl PpuCtrlSettings {
-    fn base_nametables_addr_to_t_reg(&self) -> u16 {
-        (self.base_nametables_addr as u16) << 14
-    }
-}
-
 #[derive(Debug, Clone, Copy, Default)]
 pub struct PpuMaskSetting {
     emphasize_blue: bool,
@@ -124,6 +159,11 @@ impl PpuMaskSetting {
         self.leftmost_bg_render = is_bit_set(settings, 0b0000_0010);
         self.greyscale = is_bit_set(settings, 0b0000_0001);
     }
+
+    /// Emphasis bits in palette order: red, green, blue from the lowest bit
+    fn emphasis_bits(&self) -> u16 {
+        (self.emphasize_red as u16) | (self.emphasize_green as u16) << 1 | (self.emphasize_blue as u16) << 2
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -156,29 +196,219 @@ impl PpuStatus {
         self.value &= 0b1101_1111
     }
 
-    pub fn open_bus_write(&mut self, value_to_write: u8) {
-        self.value &= 0b1110_0000;
-        self.value |= 0b0001_1111 & value_to_write;
+    pub fn is_v_blank(&self) -> bool {
+        is_bit_set(self.value, 0b1000_0000)
+    }
+
+    pub fn is_sprite_zero_hit(&self) -> bool {
+        is_bit_set(self.value, 0b0100_0000)
+    }
+
+    pub fn is_sprite_overflow(&self) -> bool {
+        is_bit_set(self.value, 0b0010_0000)
     }
 }
 
+/// Way PPU draws frames, selected before execution starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderBackend {
+    /// Dot by dot fetches and pixel output, mid-scanline effects work
+    #[default]
+    Dot,
+    /// Whole line is drawn at once, faster but sees only register state at the end of the line
+    Scanline,
+}
+
+impl std::str::FromStr for RenderBackend {
+    type Err = String;
+
+    fn from_str(backend_name: &str) -> Result<Self, Self::Err> {
+        match backend_name.to_ascii_lowercase().as_str() {
+            "dot" => Ok(RenderBackend::Dot),
+            "scanline" => Ok(RenderBackend::Scanline),
+            _ => Err(format!("Unknown renderer '{backend_name}', expected dot or scanline")),
+        }
+    }
+}
+
+/// Picture improvements beyond hardware, all off by default. They change only the output,
+/// state visible to CPU (status flags, OAM reads, mapper accesses) stays the same
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PpuEnhancements {
+    /// Renders every sprite on the line instead of the first 8, overflow flag is still set
+    pub unlimited_sprites: bool,
+    /// Shows leftmost 8 pixels even if PPUMASK hides them, sprite 0 hit still follows PPUMASK
+    pub disable_left_clip: bool,
+    /// Hides NTSC overscan area in output frames
+    pub crop_overscan: bool,
+}
+
+/// PPU I/O data bus latch. Keeps the last value written to or read from PPU registers,
+/// every bit fades to 0 if it isn't refreshed for about 600 ms
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoLatch {
+    value: u8,
+    /// Frame of the last refresh for every bit
+    refresh_frames: [u64; 8],
+}
+
+impl IoLatch {
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+
+    /// Bits selected by mask are taken from the value and start to decay anew
+    fn refresh(&mut self, value: u8, mask: u8, frame: u64) {
+        self.value = (self.value & !mask) | (value & mask);
+        for now_bit in (0..8).filter(|now_bit| is_bit_set(mask, 1 << now_bit)) {
+            self.refresh_frames[now_bit] = frame;
+        }
+    }
+
+    fn decay(&mut self, frame: u64, decay_frames: u64) {
+        for now_bit in 0..8 {
+            if frame - self.refresh_frames[now_bit] >= decay_frames {
+                self.value &= !(1 << now_bit);
+            }
+        }
+    }
+}
+
+/// Internal PPU "loopy" register (v or t) with layout yyy NN YYYYY XXXXX
+/// (fine Y, nametable select, coarse Y, coarse X)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoopyRegister {
+    value: u16
+}
+
+impl LoopyRegister {
+    const COARSE_X_MASK: u16 = 0b0000_0000_0001_1111;
+    const COARSE_Y_MASK: u16 = 0b0000_0011_1110_0000;
+    const NAMETABLE_X_MASK: u16 = 0b0000_0100_0000_0000;
+    const NAMETABLE_Y_MASK: u16 = 0b0000_1000_0000_0000;
+    const NAMETABLES_MASK: u16 = 0b0000_1100_0000_0000;
+    const FINE_Y_MASK: u16 = 0b0111_0000_0000_0000;
+    const HORIZONTAL_MASK: u16 = Self::COARSE_X_MASK | Self::NAMETABLE_X_MASK;
+    const VERTICAL_MASK: u16 = Self::COARSE_Y_MASK | Self::NAMETABLE_Y_MASK | Self::FINE_Y_MASK;
+    const ADDRESS_MASK: u16 = 0b0111_1111_1111_1111;
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    pub fn coarse_x(&self) -> u16 {
+        self.value & Self::COARSE_X_MASK
+    }
+
+    pub fn coarse_y(&self) -> u16 {
+        (self.value & Self::COARSE_Y_MASK) >> 5
+    }
+
+    pub fn nametables(&self) -> u16 {
+        (self.value & Self::NAMETABLES_MASK) >> 10
+    }
+
+    pub fn fine_y(&self) -> u16 {
+        (self.value & Self::FINE_Y_MASK) >> 12
+    }
+
+    fn set_nametables(&mut self, nametables: u16) {
+        self.value = (self.value & !Self::NAMETABLES_MASK) | ((nametables << 10) & Self::NAMETABLES_MASK);
+    }
+
+    fn set_coarse_x(&mut self, coarse_x: u16) {
+        self.value = (self.value & !Self::COARSE_X_MASK) | (coarse_x & Self::COARSE_X_MASK);
+    }
+
+    fn set_coarse_y(&mut self, coarse_y: u16) {
+        self.value = (self.value & !Self::COARSE_Y_MASK) | ((coarse_y << 5) & Self::COARSE_Y_MASK);
+    }
+
+    fn set_fine_y(&mut self, fine_y: u16) {
+        self.value = (self.value & !Self::FINE_Y_MASK) | ((fine_y << 12) & Self::FINE_Y_MASK);
+    }
+
+    fn set_high_byte(&mut self, data: u8) {
+        // Bit 14 is cleared, only 6 bits of data are used
+        self.value = (self.value & 0x00FF) | (((data & 0b0011_1111) as u16) << 8);
+    }
+
+    fn set_low_byte(&mut self, data: u8) {
+        self.value = (self.value & 0xFF00) | data as u16;
+    }
+
+    fn increment(&mut self, increment: u16) {
+        self.value = self.value.wrapping_add(increment) & Self::ADDRESS_MASK;
+    }
+
+    /// Moves to the next tile horizontally, switching horizontal nametable on wrap
+    pub fn increment_coarse_x(&mut self) {
+        if self.coarse_x() == 31 {
+            self.value &= !Self::COARSE_X_MASK;
+            self.value ^= Self::NAMETABLE_X_MASK;
+        } else {
+            self.value += 1;
+        }
+    }
+
+    /// Moves to the next pixel row, switching vertical nametable after row 29 of tiles
+    pub fn increment_y(&mut self) {
+        if self.fine_y() < 7 {
+            self.set_fine_y(self.fine_y() + 1);
+            return
+        }
+
+        self.set_fine_y(0);
+        match self.coarse_y() {
+            29 => {
+                self.set_coarse_y(0);
+                self.value ^= Self::NAMETABLE_Y_MASK;
+            },
+            31 => self.set_coarse_y(0), // Attribute table rows wrap without nametable switch
+            coarse_y => self.set_coarse_y(coarse_y + 1),
+        }
+    }
+
+    /// Copies coarse X and horizontal nametable bit from other register
+    pub fn copy_horizontal_bits(&mut self, other: &LoopyRegister) {
+        self.value = (self.value & !Self::HORIZONTAL_MASK) | (other.value & Self::HORIZONTAL_MASK);
+    }
+
+    /// Copies fine Y, coarse Y and vertical nametable bit from other register
+    pub fn copy_vertical_bits(&mut self, other: &LoopyRegister) {
+        self.value = (self.value & !Self::VERTICAL_MASK) | (other.value & Self::VERTICAL_MASK);
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Ppu {
     scanline: u16,
     cycles_per_scanline: u16,
     cycles: usize,
-    render_status: Option<PpuRenderStatus>,
+    frame_count: u64,
+    /// NMI raised and not yet taken by CPU
+    nmi_pending: bool,
+    /// $2002 was read right before vblank start, flag isn't set in this frame
+    v_blank_suppressed: bool,
     registers: [u8; 9],
     oam_data: [u8; 256],
-    t_register: u16,
+    v_register: LoopyRegister,
+    t_register: LoopyRegister,
+    fine_x_scroll: u8,
     write_toogle: bool,
-    x_scroll: u8,
-    y_scroll: u8,
+    read_buffer: u8,
+    background: BackgroundPipeline,
+    sprites: SpritePipeline,
+    frame_buffer: Vec<u16>,
     ctrl_settings: PpuCtrlSettings,
     render_settings: PpuMaskSetting,
     ppu_status: PpuStatus,
-    mirroring: MirroringType,
+    io_latch: IoLatch,
+    region: Region,
+    enhancements: PpuEnhancements,
+    backend: RenderBackend,
+    /// v at the start of the next line, for scanline renderer
+    scanline_start_v: LoopyRegister,
 }
 
 impl Default for Ppu {
@@ -187,17 +417,27 @@ impl Default for Ppu {
             scanline: 0,
             cycles_per_scanline: 0,
             cycles: 0,
-            render_status: None,
+            frame_count: 0,
+            nmi_pending: false,
+            v_blank_suppressed: false,
             registers: [0u8; 9],
             oam_data: [0u8; 256],
-            t_register: 0,
+            v_register: LoopyRegister::default(),
+            t_register: LoopyRegister::default(),
+            fine_x_scroll: 0,
             write_toogle: false,
-            x_scroll: 0,
-            y_scroll: 0,
+            read_buffer: 0,
+            background: BackgroundPipeline::default(),
+            sprites: SpritePipeline::default(),
+            frame_buffer: vec![0u16; SCREEN_WIDTH * SCREEN_HEIGHT],
             ctrl_settings: PpuCtrlSettings::default(),
             render_settings: PpuMaskSetting::default(),
             ppu_status: PpuStatus::default(),
-            mirroring: MirroringType::None,
+            io_latch: IoLatch::default(),
+            region: Region::default(),
+            enhancements: PpuEnhancements::default(),
+            backend: RenderBackend::default(),
+            scanline_start_v: LoopyRegister::default(),
         }
     }
 }
@@ -205,52 +445,70 @@ impl Default for Ppu {
 impl Ppu {
     pub fn write_to_registers(&mut self, register: usize, data: u8) {
         inst_assert!((0..=8).contains(&register));
+        if register != OAM_DMA_REG {
+            self.io_latch.refresh(data, 0xFF, self.frame_count);
+        }
         match register {
             PPU_CTRL_REG => {
+                // Enabling NMI during vblank raises it immediately
+                let nmi_was_enabled = self.ctrl_settings.v_blank_nmi;
                 self.registers[PPU_CTRL_REG] = data;
                 self.ctrl_settings.set(data);
-                self.t_register &= 0b0011_1111_1111_1111;
-                self.t_register |= self.ctrl_settings.base_nametables_addr_to_t_reg();
+                if !nmi_was_enabled && self.ctrl_settings.v_blank_nmi && self.ppu_status.is_v_blank() {
+                    self.nmi_pending = true;
+                }
+                self.t_register.set_nametables(self.ctrl_settings.base_nametables_addr as u16);
             },
             PPU_MASK_REG => {
                 self.registers[PPU_MASK_REG] = data;
                 self.render_settings.set(data);
             },
             PPU_STATUS_REG => {
-                self.ppu_status.open_bus_write(data);
-                warn!("Trying to write to $2002, which is read only, open bus write");
+                warn!("Trying to write to $2002, which is read only, only I/O latch is updated");
             },
             OAM_ADDR_REG => {
                 self.registers[OAM_ADDR_REG] = data;
             },
             OAM_DATA_REG => {
-                self.registers[OAM_ADDR_REG] = self.registers[OAM_ADDR_REG].wrapping_add(1);
                 self.registers[OAM_DATA_REG] = data;
+                if self.is_rendering_line() {
+                    // OAM is busy with sprite evaluation, the write is lost and only high 6 bits of address increment
+                    self.registers[OAM_ADDR_REG] = self.registers[OAM_ADDR_REG].wrapping_add(4);
+                    return
+                }
+
+                let oam_address = self.registers[OAM_ADDR_REG] as usize;
+                self.oam_data[oam_address] = if oam_address % 4 == SPRITE_ATTRIBUTE_BYTE {
+                    data & SPRITE_ATTRIBUTE_MASK
+                } else {
+                    data
+                };
+                self.registers[OAM_ADDR_REG] = self.registers[OAM_ADDR_REG].wrapping_add(1);
             },
             PPU_SCROLL_REG => {
                 if !self.write_toogle { // First write, w is 0 (false)
-                    self.x_scroll = data;
+                    self.t_register.set_coarse_x((data >> 3) as u16);
+                    self.fine_x_scroll = data & 0b0000_0111;
                     self.write_toogle = true;
                 } else {
-                    self.y_scroll = data;
+                    self.t_register.set_coarse_y((data >> 3) as u16);
+                    self.t_register.set_fine_y((data & 0b0000_0111) as u16);
+                    self.write_toogle = false;
                 }
                 self.registers[PPU_SCROLL_REG] = data;
             },
             PPU_ADDR_REG => {
                 if !self.write_toogle { // First write, w is 0 (false)
-                    self.t_register &= 0b1000_0000_0000_0000;
-                    self.t_register |= ((data & 0b0011_1111) as u16) << 8;
+                    self.t_register.set_high_byte(data);
                     self.write_toogle = true;
                 } else {
-                    self.t_register += data as u16;
+                    self.t_register.set_low_byte(data);
+                    self.v_register = self.t_register;
                     self.write_toogle = false;
                 }
                 self.registers[PPU_ADDR_REG] = data;
             },
-            PPU_DATA_REG => {
-                self.registers[register] = data;
-                self.t_register = self.t_register.wrapping_add(self.ctrl_settings.vram_address_inc)
-            },
+            PPU_DATA_REG => unreachable!("PPUDATA writes go through write_ppu_data"),
             OAM_DMA_REG => {
                 self.registers[register] = data;
             },
@@ -259,73 +517,625 @@ impl Ppu {
 
     }
 
+    /// Reset clears control, mask, scroll and address latches, status, I/O latch and memories are kept
+    pub fn reset(&mut self) {
+        self.registers[PPU_CTRL_REG] = 0;
+        self.ctrl_settings.set(0);
+        self.registers[PPU_MASK_REG] = 0;
+        self.render_settings.set(0);
+        self.t_register = LoopyRegister::default();
+        self.fine_x_scroll = 0;
+        self.write_toogle = false;
+        self.read_buffer = 0;
+        self.nmi_pending = false;
+    }
+
     pub fn read_from_registers(&mut self, register: usize) -> u8 {
         inst_assert!((0..=8).contains(&register));
         match register {
-            0 | 1 | 3 | 5 | 6 | 8 => {
-                warn!("Trying to read from 0x200{register}, which is write only, ret 0");
+            // Write only registers return I/O latch without refreshing it
+            PPU_CTRL_REG | PPU_MASK_REG | OAM_ADDR_REG | PPU_SCROLL_REG | PPU_ADDR_REG => self.io_latch.value(),
+            OAM_DMA_REG => {
+                warn!("Trying to read from OAM DMA register, which is write only, ret 0");
                 0
             },
             PPU_STATUS_REG => {
                 self.write_toogle = false;
-                let ppu_status_state = self.ppu_status.value;
+                if self.scanline == self.region.v_blank_scanline() {
+                    match self.cycles_per_scanline {
+                        // Read one dot before vblank start: flag and NMI are lost for this frame
+                        1 => self.v_blank_suppressed = true,
+                        // Read at the same time as flag set: flag is returned, but NMI is cancelled
+                        2 | 3 => self.nmi_pending = false,
+                        _ => {},
+                    }
+                }
+                let ppu_status_state = (self.ppu_status.value & PPU_STATUS_MASK) | (self.io_latch.value() & !PPU_STATUS_MASK);
+                self.io_latch.refresh(ppu_status_state, PPU_STATUS_MASK, self.frame_count);
                 self.ppu_status.clear_v_blank();
                 self.registers[PPU_STATUS_REG] = self.ppu_status.value;
                 ppu_status_state
             },
             OAM_DATA_REG => {
-                self.registers[OAM_DATA_REG]
-            },
-            PPU_DATA_REG => {
-                self.t_register = self.t_register.wrapping_add(self.ctrl_settings.vram_address_inc);
-                self.registers[PPU_DATA_REG]
+                let oam_value = if self.is_rendering_line() {
+                    self.rendering_oam_read()
+                } else {
+                    self.oam_data[self.registers[OAM_ADDR_REG] as usize]
+                };
+                self.io_latch.refresh(oam_value, 0xFF, self.frame_count);
+                oam_value
             },
+            PPU_DATA_REG => unreachable!("PPUDATA reads go through read_ppu_data"),
             _ => unreachable!("No more registers")
         }
     }
 }
 
 impl Ppu {
-    pub fn nametable_mirroring(&mut self, vram_address: u16) -> u16 {
-        match self.mirroring {
-            MirroringType::None => unreachable!("Default value, should be overritten"),
-            MirroringType::Horizontal => {
-                if self.ctrl_settings.base_nametables_addr % 2 == 1 {
-                    vram_address - 0x0400
-                } else {
-                    vram_address
-                }
-            },
-            MirroringType::Vertical => {
-                if self.ctrl_settings.base_nametables_addr >= 2 {
-                    vram_address - 0x0800
-                } else {
-                    vram_address
-                }
-            }
+    /// $2007 read. VRAM and CHR data comes one read later through the internal buffer,
+    /// palette data is returned at once while buffer gets nametable byte "under" the palette
+    pub fn read_ppu_data(&mut self, ppu_bus: &mut PpuBus) -> u8 {
+        ppu_bus.set_ppu_cycle(self.cycles);
+        let vram_address = self.v_register.value() & PPU_ADDRESS_SPACE_MASK;
+
+        let read_value = if vram_address as usize >= PPU_PALETTES.start {
+            let nametable_address = vram_address - (PPU_NAME_TABLES_MIRRORS.start - PPU_NAME_TABLES.start) as u16;
+            self.read_buffer = ppu_bus.read_8bit_ppu(nametable_address);
+            let palette_value = ppu_bus.read_8bit_ppu(vram_address) & PALETTE_ENTRY_MASK;
+            let palette_value = if self.render_settings.greyscale {
+                palette_value & 0b0011_0000
+            } else {
+                palette_value
+            };
+            self.io_latch.refresh(palette_value, PALETTE_ENTRY_MASK, self.frame_count);
+            palette_value | (self.io_latch.value() & !PALETTE_ENTRY_MASK)
+        } else {
+            let buffered_value = self.read_buffer;
+            self.read_buffer = ppu_bus.read_8bit_ppu(vram_address);
+            self.io_latch.refresh(buffered_value, 0xFF, self.frame_count);
+            buffered_value
+        };
+
+        self.increment_vram_address();
+        read_value
+    }
+
+    /// $2007 write to nametables, palettes or CHR-RAM (through mapper)
+    pub fn write_ppu_data(&mut self, data: u8, ppu_bus: &mut PpuBus) {
+        ppu_bus.set_ppu_cycle(self.cycles);
+        let vram_address = self.v_register.value() & PPU_ADDRESS_SPACE_MASK;
+        self.io_latch.refresh(data, 0xFF, self.frame_count);
+        self.registers[PPU_DATA_REG] = data;
+        ppu_bus.write_8bit_ppu(vram_address, data);
+
+        self.increment_vram_address();
+    }
+
+    /// Visible and pre-render lines with rendering enabled, PPU owns VRAM and OAM there
+    fn is_rendering_line(&self) -> bool {
+        self.is_rendering_enabled() && (self.scanline < 240 || self.scanline == self.region.pre_render_scanline())
+    }
+
+    fn increment_vram_address(&mut self) {
+        if self.is_rendering_line() {
+            // While rendering, $2007 access triggers both coarse X and Y increments instead
+            self.v_register.increment_coarse_x();
+            self.v_register.increment_y();
+        } else {
+            self.v_register.increment(self.ctrl_settings.vram_address_inc);
         }
     }
 }
 
 impl Ppu {
-    pub fn execute_cycles(&mut self, cycles_num: usize) {
+    pub fn execute_cycles(&mut self, cycles_num: usize, ppu_bus: &mut PpuBus) {
         let end_cycle = self.cycles + cycles_num;
         while self.cycles < end_cycle {
-            if self.cycles_per_scanline >= 341 {
+            ppu_bus.set_ppu_cycle(self.cycles);
+
+            if self.cycles_per_scanline == 1 {
+                if self.scanline == self.region.v_blank_scanline() {
+                    self.start_v_blank();
+                } else if self.scanline == self.region.pre_render_scanline() {
+                    self.ppu_status.clear_v_blank();
+                    self.ppu_status.clear_sprite_zero_hit();
+                    self.ppu_status.clear_sprite_overflow();
+                    if self.is_rendering_enabled() {
+                        self.corrupt_oam();
+                    }
+                }
+            }
+
+            if self.is_rendering_line() {
+                match self.backend {
+                    RenderBackend::Dot => {
+                        self.fetch_background(ppu_bus);
+                        self.process_sprites(ppu_bus);
+                    },
+                    RenderBackend::Scanline => self.process_scanline(ppu_bus),
+                }
+                self.update_scroll_registers();
+            }
+
+            if self.scanline < 240 {
+                match self.backend {
+                    RenderBackend::Dot if (1..=256).contains(&self.cycles_per_scanline) => self.render_pixel(ppu_bus),
+                    RenderBackend::Scanline if self.cycles_per_scanline == scanline::RENDER_DOT => {
+                        self.render_scanline(ppu_bus);
+                    },
+                    _ => {},
+                }
+            }
+
+            self.cycles_per_scanline += 1;
+            if self.cycles_per_scanline == LAST_DOT && self.is_odd_frame_skip() {
+                self.cycles_per_scanline += 1;
+            }
+
+            if self.cycles_per_scanline > LAST_DOT {
                 self.cycles_per_scanline = 0;
                 self.scanline += 1;
 
-                if self.scanline == 241 {
-                    self.render_status = Some(PpuRenderStatus::NmiTrigger);
-
-                } else if self.scanline >= 262 {
-                    self.render_status = Some(PpuRenderStatus::EndOfFrame);
+                if self.scanline > self.region.pre_render_scanline() {
                     self.scanline = 0;
+                    self.frame_count += 1;
+                    self.io_latch.decay(self.frame_count, self.io_latch_decay_frames());
                 }
-
             }
 
             self.cycles += 1;
         }
     }
+
+    /// OAMADDR left at 8 or more when rendering starts makes PPU copy the 8 bytes row at OAMADDR & $F8
+    /// over the first 8 bytes of OAM
+    fn corrupt_oam(&mut self) {
+        let row_start = (self.registers[OAM_ADDR_REG] & 0xF8) as usize;
+        if row_start > 0 {
+            self.oam_data.copy_within(row_start..row_start + 8, 0);
+        }
+    }
+
+    fn io_latch_decay_frames(&self) -> u64 {
+        self.region.frames_per_second() as u64 * IO_LATCH_DECAY_MS / 1000
+    }
+
+    /// NTSC PPU skips the last dot of pre-render line on odd frames while rendering
+    fn is_odd_frame_skip(&self) -> bool {
+        self.region.has_odd_frame_skip()
+            && self.scanline == self.region.pre_render_scanline()
+            && self.is_odd_frame()
+            && self.is_rendering_enabled()
+    }
+
+    fn start_v_blank(&mut self) {
+        if std::mem::take(&mut self.v_blank_suppressed) {
+            return
+        }
+
+        self.ppu_status.set_v_blank();
+        if self.ctrl_settings.v_blank_nmi {
+            self.nmi_pending = true;
+        }
+    }
+
+    /// Returns true once per raised NMI
+    pub fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    fn render_pixel(&mut self, ppu_bus: &mut PpuBus) {
+        let color = if self.is_rendering_enabled() {
+            let (mut bg_pixel, mut bg_palette) = self.background_pixel(true);
+            let (mut sprite_pixel, mut sprite_palette, mut sprite_behind_bg, is_sprite_zero) = self.sprite_pixel(true);
+
+            if is_sprite_zero && bg_pixel != 0 && sprite_pixel != 0 && self.cycles_per_scanline != 256 {
+                self.ppu_status.set_sprite_zero_hit();
+            }
+
+            // Clipped pixels are shown only after sprite 0 hit check, it must see them clipped
+            if self.enhancements.disable_left_clip && self.cycles_per_scanline <= 8 {
+                (bg_pixel, bg_palette) = self.background_pixel(false);
+                (sprite_pixel, sprite_palette, sprite_behind_bg, _) = self.sprite_pixel(false);
+            }
+
+            if sprite_pixel != 0 && (bg_pixel == 0 || !sprite_behind_bg) {
+                self.palette_color(sprite_pixel, sprite_palette, ppu_bus)
+            } else {
+                self.palette_color(bg_pixel, bg_palette, ppu_bus)
+            }
+        } else {
+            // With rendering disabled PPU shows backdrop or the palette entry v points to
+            let vram_address = self.v_register.value() & PPU_ADDRESS_SPACE_MASK;
+            if vram_address as usize >= PPU_PALETTES.start {
+                ppu_bus.read_8bit_ppu(vram_address)
+            } else {
+                self.palette_color(0, 0, ppu_bus)
+            }
+        };
+
+        let x = (self.cycles_per_scanline - 1) as usize;
+        self.frame_buffer[self.scanline as usize * SCREEN_WIDTH + x] = color as u16 | self.render_settings.emphasis_bits() << 6;
+    }
+
+    /// Reads color index from palette RAM for pixel with given palette (0-3 background, 4-7 sprites)
+    fn palette_color(&self, pixel: u8, palette: u8, ppu_bus: &mut PpuBus) -> u8 {
+        let palette_address = if pixel == 0 {
+            PPU_PALETTES.start as u16
+        } else {
+            PPU_PALETTES.start as u16 + ((palette as u16) << 2) + pixel as u16
+        };
+
+        let color = ppu_bus.read_8bit_ppu(palette_address);
+        if self.render_settings.greyscale {
+            color & 0b0011_0000
+        } else {
+            color
+        }
+    }
+
+    fn is_rendering_enabled(&self) -> bool {
+        self.render_settings.bg_render || self.render_settings.sprite_render
+    }
+
+    /// Scroll updates of v register that PPU does on visible and pre-render scanlines
+    fn update_scroll_registers(&mut self) {
+        let dot = self.cycles_per_scanline;
+
+        if ((1..=256).contains(&dot) || (321..=336).contains(&dot)) && dot.is_multiple_of(8) {
+            self.v_register.increment_coarse_x();
+        }
+
+        if dot == 256 {
+            self.v_register.increment_y();
+        } else if dot == 257 {
+            self.v_register.copy_horizontal_bits(&self.t_register);
+        } else if self.scanline == self.region.pre_render_scanline() && (280..=304).contains(&dot) {
+            self.v_register.copy_vertical_bits(&self.t_register);
+        }
+    }
+}
+
+impl Ppu {
+    pub fn v_register(&self) -> LoopyRegister {
+        self.v_register
+    }
+
+    pub fn t_register(&self) -> LoopyRegister {
+        self.t_register
+    }
+
+    pub fn fine_x_scroll(&self) -> u8 {
+        self.fine_x_scroll
+    }
+
+    pub fn write_toggle(&self) -> bool {
+        self.write_toogle
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// Dot of the scanline which will be executed next
+    pub fn dot(&self) -> u16 {
+        self.cycles_per_scanline
+    }
+
+    /// Number of PPU dots executed since power on
+    pub fn total_dots(&self) -> usize {
+        self.cycles
+    }
+
+    /// Number of finished frames, frame ends after pre-render scanline
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn is_odd_frame(&self) -> bool {
+        self.frame_count % 2 == 1
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn backend(&self) -> RenderBackend {
+        self.backend
+    }
+
+    pub fn set_backend(&mut self, backend: RenderBackend) {
+        self.backend = backend;
+    }
+
+    pub fn enhancements(&self) -> PpuEnhancements {
+        self.enhancements
+    }
+
+    pub fn set_enhancements(&mut self, enhancements: PpuEnhancements) {
+        self.enhancements = enhancements;
+    }
+
+    pub fn oam_data(&self) -> &[u8; 256] {
+        &self.oam_data
+    }
+
+    /// Pattern table address used for background tiles, $0000 or $1000
+    pub fn background_pattern_table(&self) -> u16 {
+        self.ctrl_settings.bg_addr
+    }
+
+    /// Pattern table address used for 8x8 sprites, ignored in 8x16 mode
+    pub fn sprite_pattern_table(&self) -> u16 {
+        self.ctrl_settings.sprite_pt_address
+    }
+
+    /// Sprite height in pixels, 8 or 16
+    pub fn sprite_size(&self) -> u8 {
+        self.ctrl_settings.sprite_size
+    }
+
+    /// Palette indices of the last rendered frame, 256x240 pixels row by row.
+    /// Bits 0-5 are color index, bits 6-8 are emphasis bits, see `palette::Palette::rgb`
+    pub fn frame_buffer(&self) -> &[u16] {
+        &self.frame_buffer
+    }
+}
+
+#[test]
+fn test_loopy_register_writes() {
+    let mut ppu = Ppu::default();
+
+    // Nametable select goes to bits 10-11 of t
+    ppu.write_to_registers(PPU_CTRL_REG, 0b0000_0011);
+    assert_eq!(ppu.t_register().value(), 0b0000_1100_0000_0000);
+    ppu.write_to_registers(PPU_CTRL_REG, 0b0000_0000);
+    assert_eq!(ppu.t_register().value(), 0);
+
+    // Scroll writes: X = 0x7D (coarse 15, fine 5), Y = 0x5E (coarse 11, fine 6)
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x7D);
+    assert_eq!(ppu.t_register().coarse_x(), 15);
+    assert_eq!(ppu.fine_x_scroll(), 5);
+    assert!(ppu.write_toggle());
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x5E);
+    assert_eq!(ppu.t_register().coarse_y(), 11);
+    assert_eq!(ppu.t_register().fine_y(), 6);
+    assert!(!ppu.write_toggle());
+
+    // Address writes replace t, second write copies t to v
+    ppu.write_to_registers(PPU_ADDR_REG, 0xFD);
+    assert_eq!(ppu.t_register().value() & 0xFF00, 0x3D00);
+    assert_ne!(ppu.v_register().value(), 0x3D00);
+    ppu.write_to_registers(PPU_ADDR_REG, 0xF0);
+    assert_eq!(ppu.t_register().value(), 0x3DF0);
+    assert_eq!(ppu.v_register().value(), 0x3DF0);
+    assert!(!ppu.write_toggle());
+
+    // Reading status resets write toggle
+    ppu.write_to_registers(PPU_ADDR_REG, 0x21);
+    ppu.read_from_registers(PPU_STATUS_REG);
+    ppu.write_to_registers(PPU_ADDR_REG, 0x22);
+    ppu.write_to_registers(PPU_ADDR_REG, 0x08);
+    assert_eq!(ppu.v_register().value(), 0x2208);
+
+    // Fine X is kept while t is changed by $2006 writes
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x07);
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x00);
+    ppu.write_to_registers(PPU_ADDR_REG, 0x04);
+    assert_eq!(ppu.fine_x_scroll(), 7);
+}
+
+#[test]
+fn test_loopy_register_increments() {
+    let mut loopy = LoopyRegister::default();
+
+    for now_x in 0..31 {
+        assert_eq!(loopy.coarse_x(), now_x);
+        loopy.increment_coarse_x();
+    }
+    assert_eq!(loopy.nametables(), 0);
+    loopy.increment_coarse_x();
+    assert_eq!((loopy.coarse_x(), loopy.nametables()), (0, 0b01));
+    for _ in 0..32 {
+        loopy.increment_coarse_x();
+    }
+    assert_eq!((loopy.coarse_x(), loopy.nametables()), (0, 0b00));
+
+    for now_row in 0..(30 * 8) {
+        assert_eq!(loopy.coarse_y() * 8 + loopy.fine_y(), now_row);
+        loopy.increment_y();
+    }
+    assert_eq!((loopy.coarse_y(), loopy.fine_y(), loopy.nametables()), (0, 0, 0b10));
+
+    // Coarse Y set to attribute rows wraps to 0 without nametable switch
+    loopy.set_coarse_y(31);
+    loopy.set_fine_y(7);
+    loopy.increment_y();
+    assert_eq!((loopy.coarse_y(), loopy.fine_y(), loopy.nametables()), (0, 0, 0b10));
+
+    let mut target = LoopyRegister { value: 0x7FFF };
+    let source = LoopyRegister { value: 0b0010_0101_0101_0101 };
+    target.copy_horizontal_bits(&source);
+    assert_eq!(target.value(), 0b0111_1111_1111_0101);
+    target.copy_vertical_bits(&source);
+    assert_eq!(target.value(), 0b0010_0101_0101_0101);
+}
+
+#[test]
+fn test_scroll_updates_while_rendering() {
+    use crate::bus::nrom_test_bus;
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+
+    let mut ppu = Ppu::default();
+    ppu.write_to_registers(PPU_MASK_REG, 0b0000_1000);
+
+    ppu.write_to_registers(PPU_CTRL_REG, 0b0000_0001);
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x10);
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x21);
+
+    // Go to the end of the pre-render line, v gets vertical bits at 280-304 and horizontal at 257
+    ppu.scanline = 261;
+    ppu.cycles_per_scanline = 0;
+    ppu.execute_cycles(305, &mut ppu_bus);
+    assert_eq!(ppu.v_register().coarse_x(), 2);
+    assert_eq!(ppu.v_register().coarse_y(), 4);
+    assert_eq!(ppu.v_register().fine_y(), 1);
+    assert_eq!(ppu.v_register().nametables(), 0b01);
+
+    // Two tiles are prefetched at dots 328 and 336 for the next scanline
+    ppu.execute_cycles(341 - 305, &mut ppu_bus);
+    assert_eq!(ppu.scanline, 0);
+    assert_eq!(ppu.v_register().coarse_x(), 4);
+
+    // After a visible line, fine Y is incremented and horizontal position is restored
+    ppu.execute_cycles(258, &mut ppu_bus);
+    assert_eq!(ppu.v_register().fine_y(), 2);
+    assert_eq!(ppu.v_register().coarse_x(), 2);
+}
+
+#[test]
+fn test_v_blank_and_nmi() {
+    use crate::bus::nrom_test_bus;
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+
+    let go_to_dot = |ppu: &mut Ppu, scanline: u16, dot: u16| {
+        ppu.scanline = scanline;
+        ppu.cycles_per_scanline = dot;
+    };
+
+    // Vblank flag is set at dot 1 of line 241 and NMI is raised if enabled
+    let mut ppu = Ppu::default();
+    ppu.write_to_registers(PPU_CTRL_REG, 0b1000_0000);
+    go_to_dot(&mut ppu, 241, 0);
+    ppu.execute_cycles(1, &mut ppu_bus);
+    assert!(!ppu.ppu_status.is_v_blank());
+    ppu.execute_cycles(1, &mut ppu_bus);
+    assert!(ppu.ppu_status.is_v_blank());
+    assert!(ppu.poll_nmi());
+    assert!(!ppu.poll_nmi());
+
+    // Toggling NMI enable during vblank raises NMI again
+    ppu.write_to_registers(PPU_CTRL_REG, 0b0000_0000);
+    ppu.write_to_registers(PPU_CTRL_REG, 0b1000_0000);
+    assert!(ppu.poll_nmi());
+
+    // Flag is cleared by read and at dot 1 of pre-render line
+    assert_eq!(ppu.read_from_registers(PPU_STATUS_REG) & 0b1000_0000, 0b1000_0000);
+    assert!(!ppu.ppu_status.is_v_blank());
+    ppu.ppu_status.set_v_blank();
+    go_to_dot(&mut ppu, 261, 1);
+    ppu.execute_cycles(1, &mut ppu_bus);
+    assert!(!ppu.ppu_status.is_v_blank());
+
+    // Read one dot before vblank: flag is never set and NMI is not raised
+    go_to_dot(&mut ppu, 241, 1);
+    assert_eq!(ppu.read_from_registers(PPU_STATUS_REG) & 0b1000_0000, 0);
+    ppu.execute_cycles(10, &mut ppu_bus);
+    assert!(!ppu.ppu_status.is_v_blank());
+    assert!(!ppu.poll_nmi());
+
+    // Read at the same dot as flag set: flag is read, but NMI is cancelled
+    go_to_dot(&mut ppu, 241, 1);
+    ppu.execute_cycles(1, &mut ppu_bus);
+    assert_eq!(ppu.read_from_registers(PPU_STATUS_REG) & 0b1000_0000, 0b1000_0000);
+    assert!(!ppu.poll_nmi());
+
+    // Suppression is only for one frame
+    go_to_dot(&mut ppu, 241, 1);
+    ppu.execute_cycles(1, &mut ppu_bus);
+    ppu.execute_cycles(10, &mut ppu_bus);
+    assert!(ppu.poll_nmi());
+}
+
+#[test]
+fn test_odd_frame_skip() {
+    use crate::bus::nrom_test_bus;
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+
+    let frame_length = |ppu: &mut Ppu, ppu_bus: &mut PpuBus| {
+        let (start_frame, start_dots) = (ppu.frame_count(), ppu.total_dots());
+        while ppu.frame_count() == start_frame {
+            ppu.execute_cycles(1, ppu_bus);
+        }
+        ppu.total_dots() - start_dots
+    };
+
+    // Rendering disabled: every frame is 341 * 262 dots
+    let mut ppu = Ppu::default();
+    assert_eq!(frame_length(&mut ppu, &mut ppu_bus), 341 * 262);
+    assert!(ppu.is_odd_frame());
+    assert_eq!(frame_length(&mut ppu, &mut ppu_bus), 341 * 262);
+    assert!(!ppu.is_odd_frame());
+
+    // Rendering enabled: odd frames skip the last dot of pre-render line
+    ppu.write_to_registers(PPU_MASK_REG, 0b0000_1000);
+    assert_eq!(frame_length(&mut ppu, &mut ppu_bus), 341 * 262);
+    assert_eq!(frame_length(&mut ppu, &mut ppu_bus), 341 * 262 - 1);
+    assert_eq!(frame_length(&mut ppu, &mut ppu_bus), 341 * 262);
+    assert_eq!((ppu.scanline(), ppu.dot()), (0, 0));
+
+    // Skip goes from dot 339 to the first dot of the next frame
+    while (ppu.scanline(), ppu.dot()) != (261, 339) {
+        ppu.execute_cycles(1, &mut ppu_bus);
+    }
+    assert!(ppu.is_odd_frame());
+    ppu.execute_cycles(1, &mut ppu_bus);
+    assert_eq!((ppu.scanline(), ppu.dot()), (0, 0));
+
+    // PAL has no skip
+    let mut ppu = Ppu::default();
+    ppu.set_region(Region::Pal);
+    ppu.write_to_registers(PPU_MASK_REG, 0b0000_1000);
+    assert_eq!(frame_length(&mut ppu, &mut ppu_bus), 341 * 312);
+    assert_eq!(frame_length(&mut ppu, &mut ppu_bus), 341 * 312);
+}
+
+#[test]
+fn test_io_latch() {
+    use crate::bus::nrom_test_bus;
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+    let mut ppu = Ppu::default();
+    let frame_dots = 341 * 262;
+
+    // Any write fills the latch, write only registers read it back
+    ppu.write_to_registers(PPU_STATUS_REG, 0xFF);
+    assert_eq!(ppu.read_from_registers(PPU_CTRL_REG), 0xFF);
+    assert_eq!(ppu.read_from_registers(PPU_SCROLL_REG), 0xFF);
+
+    // Status read refreshes only its 3 high bits
+    assert_eq!(ppu.read_from_registers(PPU_STATUS_REG), 0x1F);
+    assert_eq!(ppu.read_from_registers(OAM_ADDR_REG), 0x1F);
+
+    // Bits decay after 600 ms (36 NTSC frames) without refresh
+    ppu.execute_cycles(frame_dots * 35, &mut ppu_bus);
+    assert_eq!(ppu.read_from_registers(PPU_MASK_REG), 0x1F);
+    ppu.execute_cycles(frame_dots, &mut ppu_bus);
+    assert_eq!(ppu.read_from_registers(PPU_MASK_REG), 0x00);
+
+    // Reset doesn't touch the latch
+    ppu.write_to_registers(PPU_MASK_REG, 0x5A);
+    ppu.reset();
+    assert_eq!(ppu.read_from_registers(PPU_CTRL_REG), 0x5A);
+
+    // Palette reads have 2 high bits from the latch
+    ppu.write_to_registers(PPU_ADDR_REG, 0x3F);
+    ppu.write_to_registers(PPU_ADDR_REG, 0x00);
+    ppu.write_ppu_data(0x2A, &mut ppu_bus);
+    ppu.write_to_registers(PPU_ADDR_REG, 0x3F);
+    ppu.write_to_registers(PPU_ADDR_REG, 0x00);
+    ppu.write_to_registers(PPU_STATUS_REG, 0xC0);
+    assert_eq!(ppu.read_ppu_data(&mut ppu_bus), 0xEA);
+    assert_eq!(ppu.read_from_registers(PPU_ADDR_REG), 0xEA);
 }
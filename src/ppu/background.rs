@@ -0,0 +1,214 @@
+use crate::ppu::{Ppu, LoopyRegister};
+use crate::bus::PpuBus;
+use crate::memory::PPU_NAME_TABLES;
+
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x03C0;
+
+/// Latches and shift registers of background fetch pipeline
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackgroundPipeline {
+    next_tile_id: u8,
+    next_tile_attribute: u8,
+    next_tile_lsb: u8,
+    next_tile_msb: u8,
+    shifter_pattern_lo: u16,
+    shifter_pattern_hi: u16,
+    shifter_attribute_lo: u16,
+    shifter_attribute_hi: u16,
+}
+
+impl BackgroundPipeline {
+    /// Puts fetched tile to the low bytes of shifters, high bytes are still in use
+    fn load_shifters(&mut self) {
+        self.shifter_pattern_lo = (self.shifter_pattern_lo & 0xFF00) | self.next_tile_lsb as u16;
+        self.shifter_pattern_hi = (self.shifter_pattern_hi & 0xFF00) | self.next_tile_msb as u16;
+
+        let attribute_lo = if self.next_tile_attribute & 0b01 != 0 { 0x00FF } else { 0x0000 };
+        let attribute_hi = if self.next_tile_attribute & 0b10 != 0 { 0x00FF } else { 0x0000 };
+        self.shifter_attribute_lo = (self.shifter_attribute_lo & 0xFF00) | attribute_lo;
+        self.shifter_attribute_hi = (self.shifter_attribute_hi & 0xFF00) | attribute_hi;
+    }
+
+    fn update_shifters(&mut self) {
+        self.shifter_pattern_lo <<= 1;
+        self.shifter_pattern_hi <<= 1;
+        self.shifter_attribute_lo <<= 1;
+        self.shifter_attribute_hi <<= 1;
+    }
+
+    /// Returns (pixel, palette) pair selected by fine X scroll
+    pub fn pixel(&self, fine_x_scroll: u8) -> (u8, u8) {
+        let bit_mux: u16 = 0x8000 >> fine_x_scroll;
+
+        let pixel = ((self.shifter_pattern_hi & bit_mux != 0) as u8) << 1
+            | (self.shifter_pattern_lo & bit_mux != 0) as u8;
+        let palette = ((self.shifter_attribute_hi & bit_mux != 0) as u8) << 1
+            | (self.shifter_attribute_lo & bit_mux != 0) as u8;
+
+        (pixel, palette)
+    }
+}
+
+/// Nametable byte of the tile v points to
+pub(super) fn nametable_byte_at(v: LoopyRegister, ppu_bus: &mut PpuBus) -> u8 {
+    let nametable_address = PPU_NAME_TABLES.start as u16 | (v.value() & 0x0FFF);
+    ppu_bus.read_8bit_ppu(nametable_address)
+}
+
+/// Palette bits of the tile v points to
+pub(super) fn attribute_bits_at(v: LoopyRegister, ppu_bus: &mut PpuBus) -> u8 {
+    let attribute_address = PPU_NAME_TABLES.start as u16
+        | ATTRIBUTE_TABLE_OFFSET
+        | (v.nametables() << 10)
+        | ((v.coarse_y() >> 2) << 3)
+        | (v.coarse_x() >> 2);
+
+    // Every attribute byte covers 4x4 tiles, 2 bits per 2x2 tiles quadrant
+    let quadrant_shift = ((v.coarse_y() & 0b10) << 1) | (v.coarse_x() & 0b10);
+    (ppu_bus.read_8bit_ppu(attribute_address) >> quadrant_shift) & 0b11
+}
+
+impl Ppu {
+    /// Background memory fetches for visible and pre-render scanlines, 4 fetches per 8 dots
+    pub(super) fn fetch_background(&mut self, ppu_bus: &mut PpuBus) {
+        let dot = self.cycles_per_scanline;
+
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            if self.render_settings.bg_render {
+                self.background.update_shifters();
+            }
+
+            match (dot - 1) % 8 {
+                0 => {
+                    self.background.load_shifters();
+                    self.background.next_tile_id = self.fetch_nametable_byte(ppu_bus);
+                },
+                2 => {
+                    self.background.next_tile_attribute = self.fetch_attribute_bits(ppu_bus);
+                },
+                4 => {
+                    let pattern_address = self.background_pattern_address();
+                    self.background.next_tile_lsb = ppu_bus.read_8bit_ppu(pattern_address);
+                },
+                6 => {
+                    let pattern_address = self.background_pattern_address() + 8;
+                    self.background.next_tile_msb = ppu_bus.read_8bit_ppu(pattern_address);
+                },
+                _ => {},
+            }
+        }
+
+        if dot == 257 {
+            self.background.load_shifters();
+        } else if dot == 338 || dot == 340 {
+            // Unused nametable fetches at the end of scanline
+            self.background.next_tile_id = self.fetch_nametable_byte(ppu_bus);
+        }
+    }
+
+    pub(super) fn fetch_nametable_byte(&self, ppu_bus: &mut PpuBus) -> u8 {
+        nametable_byte_at(self.v_register, ppu_bus)
+    }
+
+    fn fetch_attribute_bits(&self, ppu_bus: &mut PpuBus) -> u8 {
+        attribute_bits_at(self.v_register, ppu_bus)
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        self.ctrl_settings.bg_addr
+            + ((self.background.next_tile_id as u16) << 4)
+            + self.v_register.fine_y()
+    }
+
+    /// Returns (pixel, palette) pair of background for current dot, pixel 0 is transparent
+    pub(super) fn background_pixel(&self, left_clip: bool) -> (u8, u8) {
+        let x = self.cycles_per_scanline - 1;
+
+        if !self.render_settings.bg_render || (left_clip && x < 8 && !self.render_settings.leftmost_bg_render) {
+            return (0, 0)
+        }
+
+        self.background.pixel(self.fine_x_scroll)
+    }
+}
+
+#[test]
+fn test_background_rendering() {
+    use crate::bus::nrom_test_bus;
+    use crate::ppu::MirroringType;
+    use crate::ppu::{PPU_CTRL_REG, PPU_MASK_REG, PPU_SCROLL_REG, SCREEN_WIDTH};
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+
+    // Tile 1 in both pattern tables: left half pixel 1, right half pixel 2
+    for now_row in 0..8 {
+        for table_start in [0x0000u16, 0x1000] {
+            ppu_bus.write_8bit_ppu(table_start + 0x10 + now_row, 0b1111_0000);
+            ppu_bus.write_8bit_ppu(table_start + 0x18 + now_row, 0b0000_1111);
+        }
+    }
+    // Tile 2 only in second pattern table, solid pixel 3
+    for now_row in 0..8 {
+        ppu_bus.write_8bit_ppu(0x1020u16 + now_row, 0xFF);
+        ppu_bus.write_8bit_ppu(0x1028u16 + now_row, 0xFF);
+    }
+
+    // Tiles (0, 0) and (1, 0) use tile 1, tiles (2, 2) - (3, 3) use palette 3
+    ppu_bus.write_8bit_ppu(0x2000u16, 0x01);
+    ppu_bus.write_8bit_ppu(0x2001u16, 0x01);
+    ppu_bus.write_8bit_ppu(0x2042u16, 0x02);
+    ppu_bus.write_8bit_ppu(0x23C0u16, 0b1100_0000);
+
+    for (now_address, now_color) in [(0x3F00u16, 0x0F), (0x3F01, 0x16), (0x3F02, 0x27), (0x3F0F, 0x30)] {
+        ppu_bus.write_8bit_ppu(now_address, now_color);
+    }
+
+    let render_frame = |ppu: &mut Ppu, ppu_bus: &mut PpuBus| {
+        ppu.scanline = 261;
+        ppu.cycles_per_scanline = 0;
+        ppu.execute_cycles(341 * 241, ppu_bus);
+    };
+
+    let mut ppu = Ppu::default();
+    ppu.write_to_registers(PPU_MASK_REG, 0b0000_1010);
+    render_frame(&mut ppu, &mut ppu_bus);
+
+    let frame = ppu.frame_buffer();
+    assert_eq!(frame[..16], [0x16, 0x16, 0x16, 0x16, 0x27, 0x27, 0x27, 0x27, 0x16, 0x16, 0x16, 0x16, 0x27, 0x27, 0x27, 0x27]);
+    assert_eq!(frame[16], 0x0F);
+    assert_eq!(frame[7 * SCREEN_WIDTH + 3], 0x16);
+    assert_eq!(frame[8 * SCREEN_WIDTH], 0x0F);
+
+    // Fine X scroll
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x02);
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x00);
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[..8], [0x16, 0x16, 0x27, 0x27, 0x27, 0x27, 0x16, 0x16]);
+
+    // Leftmost 8 pixels are hidden with backdrop color
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x00);
+    ppu.write_to_registers(PPU_SCROLL_REG, 0x00);
+    ppu.write_to_registers(PPU_MASK_REG, 0b0000_1000);
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[..8], [0x0F; 8]);
+    assert_eq!(ppu.frame_buffer()[8], 0x16);
+
+    // Second pattern table and attribute palette selection, tile 2 at (2, 2)
+    ppu.write_to_registers(PPU_CTRL_REG, 0b0001_0000);
+    ppu.write_to_registers(PPU_MASK_REG, 0b0000_1010);
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[16 * SCREEN_WIDTH + 16..16 * SCREEN_WIDTH + 24], [0x30; 8]);
+    assert_eq!(ppu.frame_buffer()[0], 0x16);
+
+    // Greyscale keeps only luminance part of color
+    ppu.write_to_registers(PPU_MASK_REG, 0b0000_1011);
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[0], 0x10);
+    assert_eq!(ppu.frame_buffer()[4], 0x20);
+
+    // Emphasis bits are stored above color index
+    ppu.write_to_registers(PPU_MASK_REG, 0b1010_1010);
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[0], 0x16 | 0b101 << 6);
+}
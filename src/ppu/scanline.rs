@@ -0,0 +1,336 @@
+use crate::ppu::{Ppu, LoopyRegister, PPU_ADDRESS_SPACE_MASK, SCREEN_WIDTH, OAM_ADDR_REG, LAST_DOT};
+use crate::ppu::background::{nametable_byte_at, attribute_bits_at};
+use crate::ppu::sprites::{SPRITES_PER_LINE, OAM_SPRITES, SPRITE_FETCH_START, SPRITE_FETCH_END};
+use crate::ppu::sprites::{ATTRIBUTE_PALETTE, ATTRIBUTE_BEHIND_BG, ATTRIBUTE_FLIP_HORIZONTAL};
+use crate::bus::PpuBus;
+use crate::memory::PPU_PALETTES;
+
+/// Dot where scanline renderer draws the line, all visible dots are passed by then
+pub(super) const RENDER_DOT: u16 = 256;
+/// v register is ready for the next line after horizontal and vertical copies
+const NEXT_LINE_V_DOT: u16 = 320;
+/// The first 2 tiles of the line are fetched at dots 321-336 of the previous line
+const PREFETCH_DOT: u16 = 321;
+const PREFETCH_TILES: usize = 2;
+/// 33 tiles cover the line with any fine X scroll
+const LINE_TILES: usize = SCREEN_WIDTH / 8 + 1;
+
+/// Sprite of the line with pattern row bytes, flipped horizontally if needed
+#[derive(Debug, Clone, Copy)]
+struct ScanlineSprite {
+    pattern_lo: u8,
+    pattern_hi: u8,
+    attribute: u8,
+    x: u8,
+}
+
+impl ScanlineSprite {
+    fn pixel(&self, x: usize) -> u8 {
+        let column = x.wrapping_sub(self.x as usize);
+        if column >= 8 {
+            return 0
+        }
+
+        let bit = 7 - column;
+        (((self.pattern_hi >> bit) & 1) << 1) | ((self.pattern_lo >> bit) & 1)
+    }
+}
+
+impl Ppu {
+    /// Scanline renderer replacement of background and sprite pipelines on visible and pre-render lines
+    pub(super) fn process_scanline(&mut self, ppu_bus: &mut PpuBus) {
+        let dot = self.cycles_per_scanline;
+
+        // Pre-render line fetches aren't drawn, mappers which count PPU A12 rises see them
+        if dot == RENDER_DOT && self.scanline == self.region.pre_render_scanline() {
+            self.scanline_tiles(ppu_bus);
+            self.fetch_empty_sprite_slots(0, ppu_bus);
+            ppu_bus.set_ppu_cycle(self.cycles);
+        }
+
+        if (SPRITE_FETCH_START..=SPRITE_FETCH_END).contains(&dot) {
+            self.registers[OAM_ADDR_REG] = 0;
+        }
+        if dot == NEXT_LINE_V_DOT {
+            self.scanline_start_v = self.v_register;
+        }
+    }
+
+    /// Draws the whole line from the current nametable, attribute, CHR and OAM state.
+    /// Mid-line register changes and exact timing of sprite flags are not emulated
+    pub(super) fn render_scanline(&mut self, ppu_bus: &mut PpuBus) {
+        let line_start = self.scanline as usize * SCREEN_WIDTH;
+        let emphasis = self.render_settings.emphasis_bits() << 6;
+
+        if !self.is_rendering_enabled() {
+            let vram_address = self.v_register.value() & PPU_ADDRESS_SPACE_MASK;
+            let color = if vram_address as usize >= PPU_PALETTES.start {
+                ppu_bus.read_8bit_ppu(vram_address)
+            } else {
+                self.palette_color(0, 0, ppu_bus)
+            };
+            self.frame_buffer[line_start..line_start + SCREEN_WIDTH].fill(color as u16 | emphasis);
+            return
+        }
+
+        let background = self.scanline_background(ppu_bus);
+        let (sprites, has_sprite_zero) = self.scanline_sprites(ppu_bus);
+        ppu_bus.set_ppu_cycle(self.cycles);
+
+        for (now_x, &(bg_pixel, bg_palette)) in background.iter().enumerate() {
+            let is_left_column = now_x < 8;
+            let bg_clipped = is_left_column && !self.render_settings.leftmost_bg_render;
+            let sprite_clipped = is_left_column && !self.render_settings.leftmost_sprite_render;
+
+            let mut bg_pixel = bg_pixel;
+            let mut sprite = sprites.iter().enumerate()
+                .map(|(sprite_slot, now_sprite)| (sprite_slot, now_sprite, now_sprite.pixel(now_x)))
+                .find(|(_, _, pixel)| *pixel != 0)
+                .filter(|_| self.render_settings.sprite_render);
+
+            let is_sprite_zero = matches!(sprite, Some((0, _, _))) && has_sprite_zero;
+            if is_sprite_zero && !bg_clipped && !sprite_clipped && bg_pixel != 0 && now_x != 255 {
+                self.ppu_status.set_sprite_zero_hit();
+            }
+
+            if !self.enhancements.disable_left_clip {
+                if bg_clipped {
+                    bg_pixel = 0;
+                }
+                if sprite_clipped {
+                    sprite = None;
+                }
+            }
+
+            let color = match sprite {
+                Some((_, now_sprite, sprite_pixel)) if bg_pixel == 0 || now_sprite.attribute & ATTRIBUTE_BEHIND_BG == 0 => {
+                    self.palette_color(sprite_pixel, (now_sprite.attribute & ATTRIBUTE_PALETTE) + 4, ppu_bus)
+                },
+                _ => self.palette_color(bg_pixel, bg_palette, ppu_bus),
+            };
+            self.frame_buffer[line_start + now_x] = color as u16 | emphasis;
+        }
+    }
+
+    /// (pixel, palette) of every background dot of the line
+    fn scanline_background(&self, ppu_bus: &mut PpuBus) -> [(u8, u8); SCREEN_WIDTH] {
+        let mut background = [(0u8, 0u8); SCREEN_WIDTH];
+        // Tiles are fetched with disabled background too while sprites are shown
+        let tiles = self.scanline_tiles(ppu_bus);
+        if !self.render_settings.bg_render {
+            return background
+        }
+
+        let fine_x = self.fine_x_scroll as usize;
+        for (now_tile, &(pattern_lo, pattern_hi, palette)) in tiles.iter().enumerate() {
+            for now_column in 0..8 {
+                let Some(x) = (now_tile * 8 + now_column).checked_sub(fine_x).filter(|x| *x < SCREEN_WIDTH) else {
+                    continue
+                };
+                let bit = 7 - now_column;
+                let pixel = (((pattern_hi >> bit) & 1) << 1) | ((pattern_lo >> bit) & 1);
+                background[x] = (pixel, palette);
+            }
+        }
+
+        background
+    }
+
+    /// (pattern_lo, pattern_hi, palette) of the line tiles, every fetch is made at its dot
+    /// of the dot renderer, so mappers see the same access timing
+    fn scanline_tiles(&self, ppu_bus: &mut PpuBus) -> [(u8, u8, u8); LINE_TILES] {
+        let mut tiles = [(0u8, 0u8, 0u8); LINE_TILES];
+        let mut v = self.scanline_start_v;
+        for (now_tile, tile) in tiles.iter_mut().enumerate() {
+            *tile = self.fetch_scanline_tile(v, now_tile, ppu_bus);
+            v.increment_coarse_x();
+        }
+
+        tiles
+    }
+
+    fn fetch_scanline_tile(&self, v: LoopyRegister, now_tile: usize, ppu_bus: &mut PpuBus) -> (u8, u8, u8) {
+        let fetch_cycle = if now_tile < PREFETCH_TILES {
+            self.line_dot_cycle(PREFETCH_DOT + now_tile as u16 * 8).saturating_sub(LAST_DOT as usize + 1)
+        } else {
+            self.line_dot_cycle(1 + (now_tile - PREFETCH_TILES) as u16 * 8)
+        };
+
+        ppu_bus.set_ppu_cycle(fetch_cycle);
+        let tile_id = nametable_byte_at(v, ppu_bus);
+        ppu_bus.set_ppu_cycle(fetch_cycle + 2);
+        let palette = attribute_bits_at(v, ppu_bus);
+        let pattern_address = self.ctrl_settings.bg_addr + ((tile_id as u16) << 4) + v.fine_y();
+        ppu_bus.set_ppu_cycle(fetch_cycle + 4);
+        let pattern_lo = ppu_bus.read_8bit_ppu(pattern_address);
+        ppu_bus.set_ppu_cycle(fetch_cycle + 6);
+        let pattern_hi = ppu_bus.read_8bit_ppu(pattern_address + 8);
+
+        (pattern_lo, pattern_hi, palette)
+    }
+
+    /// PPU cycle of the given dot of the current line
+    fn line_dot_cycle(&self, dot: u16) -> usize {
+        self.cycles - self.cycles_per_scanline as usize + dot as usize
+    }
+
+    /// Evaluates OAM for the line the same way as the dot renderer (including overflow bug) and fetches
+    /// sprite patterns. Returns sprites and whether sprite 0 is among them. Evaluation starts from
+    /// the sprite at OAMADDR, misaligned OAMADDR is not emulated
+    fn scanline_sprites(&mut self, ppu_bus: &mut PpuBus) -> (Vec<ScanlineSprite>, bool) {
+        // Sprites are evaluated one line earlier, none are on the first line
+        let Some(evaluated_line) = self.scanline.checked_sub(1) else {
+            self.fetch_empty_sprite_slots(0, ppu_bus);
+            return (Vec::new(), false)
+        };
+        let sprite_size = self.ctrl_settings.sprite_size as u16;
+        let is_on_line = |y: u8| evaluated_line.wrapping_sub(y as u16) < sprite_size;
+
+        let first_sprite = self.registers[OAM_ADDR_REG] as usize >> 2;
+        let mut found: Vec<usize> = Vec::with_capacity(SPRITES_PER_LINE);
+        let mut now_sprite = first_sprite;
+        while now_sprite < OAM_SPRITES && found.len() < SPRITES_PER_LINE {
+            if is_on_line(self.oam_data[now_sprite * 4]) {
+                found.push(now_sprite);
+            }
+            now_sprite += 1;
+        }
+
+        let mut byte_index = 0;
+        while now_sprite < OAM_SPRITES {
+            if is_on_line(self.oam_data[now_sprite * 4 + byte_index]) {
+                self.ppu_status.set_sprite_overflow();
+                break
+            }
+            now_sprite += 1;
+            byte_index = (byte_index + 1) % 4;
+        }
+
+        let has_sprite_zero = found.first() == Some(&first_sprite);
+        let mut sprites: Vec<ScanlineSprite> = found.iter().enumerate()
+            .map(|(sprite_slot, &now_sprite)| self.scanline_sprite(now_sprite, evaluated_line, ppu_bus, Some(sprite_slot)))
+            .collect();
+        self.fetch_empty_sprite_slots(sprites.len(), ppu_bus);
+
+        if self.enhancements.unlimited_sprites && found.len() == SPRITES_PER_LINE {
+            let extra_sprites: Vec<usize> = (found[SPRITES_PER_LINE - 1] + 1..OAM_SPRITES)
+                .filter(|&now_sprite| is_on_line(self.oam_data[now_sprite * 4]))
+                .collect();
+            for now_sprite in extra_sprites {
+                sprites.push(self.scanline_sprite(now_sprite, evaluated_line, ppu_bus, None));
+            }
+        }
+
+        (sprites, has_sprite_zero)
+    }
+
+    /// Extra sprites of unlimited sprites enhancement have no fetch slot and are read hidden from mapper
+    fn scanline_sprite(&self, oam_sprite: usize, evaluated_line: u16, ppu_bus: &mut PpuBus, sprite_slot: Option<usize>) -> ScanlineSprite {
+        let sprite_data = &self.oam_data[oam_sprite * 4..oam_sprite * 4 + 4];
+        let (y, tile, attribute, x) = (sprite_data[0], sprite_data[1], sprite_data[2], sprite_data[3]);
+        let pattern_address = self.sprite_row_address(tile, attribute, evaluated_line.wrapping_sub(y as u16));
+
+        let (mut pattern_lo, mut pattern_hi) = match sprite_slot {
+            Some(sprite_slot) => self.fetch_sprite_row(sprite_slot, pattern_address, ppu_bus),
+            None => (ppu_bus.peek_8bit_ppu(pattern_address), ppu_bus.peek_8bit_ppu(pattern_address + 8)),
+        };
+        if attribute & ATTRIBUTE_FLIP_HORIZONTAL != 0 {
+            pattern_lo = pattern_lo.reverse_bits();
+            pattern_hi = pattern_hi.reverse_bits();
+        }
+
+        ScanlineSprite { pattern_lo, pattern_hi, attribute, x }
+    }
+
+    /// Pattern row fetch of sprite slot at its dots within 257-320
+    fn fetch_sprite_row(&self, sprite_slot: usize, pattern_address: u16, ppu_bus: &mut PpuBus) -> (u8, u8) {
+        let fetch_cycle = self.line_dot_cycle(SPRITE_FETCH_START + sprite_slot as u16 * 8 + 4);
+        ppu_bus.set_ppu_cycle(fetch_cycle);
+        let pattern_lo = ppu_bus.read_8bit_ppu(pattern_address);
+        ppu_bus.set_ppu_cycle(fetch_cycle + 2);
+        let pattern_hi = ppu_bus.read_8bit_ppu(pattern_address + 8);
+
+        (pattern_lo, pattern_hi)
+    }
+
+    /// Empty slots fetch tile $FF, mappers which count PPU A12 rises see them
+    fn fetch_empty_sprite_slots(&self, first_slot: usize, ppu_bus: &mut PpuBus) {
+        let pattern_address = self.sprite_row_address(0xFF, 0xFF, 0);
+        for sprite_slot in first_slot..SPRITES_PER_LINE {
+            self.fetch_sprite_row(sprite_slot, pattern_address, ppu_bus);
+        }
+    }
+}
+
+/// Program which fills CHR-RAM, nametables, palettes and OAM with varied data, then enables
+/// rendering with given PPUCTRL and PPUMASK and scroll (13, 21)
+#[cfg(test)]
+fn renderer_test_image(ctrl: u8, mask: u8) -> Vec<u8> {
+    crate::headless::nrom_test_image(&[
+        0x78, 0xD8, 0xA2, 0xFF, 0x9A,       // $8000: SEI; CLD; LDX #$FF; TXS
+        0xA9, 0x00, 0x8D, 0x00, 0x20,       // $8005: LDA #0; STA $2000
+        0x8D, 0x01, 0x20,                   // $800A: STA $2001
+        0x2C, 0x02, 0x20, 0x10, 0xFB,       // $800D: BIT $2002; BPL $800D
+        0x2C, 0x02, 0x20, 0x10, 0xFB,       // $8012: BIT $2002; BPL $8012
+        0xA9, 0x00, 0x8D, 0x06, 0x20,       // $8017: LDA #0; STA $2006
+        0x8D, 0x06, 0x20,                   // $801C: STA $2006
+        0xA0, 0x10, 0xA2, 0x00,             // $801F: LDY #16; LDX #0
+        0x8A, 0x8D, 0x07, 0x20, 0xE8,       // $8023: TXA; STA $2007; INX
+        0xD0, 0xF9, 0x88, 0xD0, 0xF6,       // $8028: BNE $8023; DEY; BNE $8023
+        0xA9, 0x20, 0x8D, 0x06, 0x20,       // $802D: LDA #$20; STA $2006
+        0xA9, 0x00, 0x8D, 0x06, 0x20,       // $8032: LDA #0; STA $2006
+        0xA0, 0x08,                         // $8037: LDY #8
+        0x8A, 0x8D, 0x07, 0x20, 0xE8,       // $8039: TXA; STA $2007; INX
+        0xD0, 0xF9, 0x88, 0xD0, 0xF6,       // $803E: BNE $8039; DEY; BNE $8039
+        0xA9, 0x3F, 0x8D, 0x06, 0x20,       // $8043: LDA #$3F; STA $2006
+        0xA9, 0x00, 0x8D, 0x06, 0x20,       // $8048: LDA #0; STA $2006
+        0xA2, 0x00,                         // $804D: LDX #0
+        0x8A, 0x69, 0x05, 0x8D, 0x07, 0x20, // $804F: TXA; ADC #5; STA $2007
+        0xE8, 0xE0, 0x20, 0xD0, 0xF5,       // $8055: INX; CPX #32; BNE $804F
+        0xA9, 0x00, 0xA2, 0x00,             // $805A: LDA #0; LDX #0
+        0x18, 0x69, 0x07,                   // $805E: CLC; ADC #7
+        0x9D, 0x00, 0x02, 0xE8, 0xD0, 0xF7, // $8061: STA $0200,X; INX; BNE $805E
+        0xA9, 0x02, 0x8D, 0x14, 0x40,       // $8067: LDA #2; STA $4014
+        0xAD, 0x02, 0x20,                   // $806C: LDA $2002
+        0xA9, ctrl, 0x8D, 0x00, 0x20,       // $806F: LDA #ctrl; STA $2000
+        0xA9, 0x0D, 0x8D, 0x05, 0x20,       // $8074: LDA #13; STA $2005
+        0xA9, 0x15, 0x8D, 0x05, 0x20,       // $8079: LDA #21; STA $2005
+        0xA9, mask, 0x8D, 0x01, 0x20,       // $807E: LDA #mask; STA $2001
+        0x4C, 0x83, 0x80,                   // $8083: JMP $8083
+    ])
+}
+
+#[test]
+fn test_scanline_renderer() {
+    use crate::cartridges;
+    use crate::headless::run_headless;
+    use crate::ppu::{RenderBackend, PpuEnhancements};
+
+    let no_enhancements = PpuEnhancements::default();
+    let all_enhancements = PpuEnhancements { unlimited_sprites: true, disable_left_clip: true, crop_overscan: false };
+    let run = |nes_data: &[u8], backend, enhancements| {
+        let (mut cpu, mut bus) = cartridges::load_nes_data(nes_data).unwrap();
+        bus.set_ppu_backend(backend);
+        bus.set_ppu_enhancements(enhancements);
+        let report = run_headless(&mut cpu, &mut bus, 10, &[]).unwrap();
+        (report, bus.ppu().frame_buffer().to_vec())
+    };
+
+    // Setup takes about 5 frames, the picture is static after it.
+    // 8x8 and 8x16 sprites, both pattern tables, left clipping, emphasis
+    for (ctrl, mask, enhancements) in [
+        (0b0000_0000, 0b0001_1110, no_enhancements),
+        (0b0011_1001, 0b0001_1000, no_enhancements),
+        (0b0010_0010, 0b1111_1110, no_enhancements),
+        (0b0000_1011, 0b0001_1000, all_enhancements),
+    ] {
+        let nes_data = renderer_test_image(ctrl, mask);
+        let (dot_report, dot_frame) = run(&nes_data, RenderBackend::Dot, enhancements);
+        let (scanline_report, scanline_frame) = run(&nes_data, RenderBackend::Scanline, enhancements);
+
+        assert!(dot_frame.iter().any(|&now_entry| now_entry != dot_frame[0]));
+        let first_difference = dot_frame.iter().zip(&scanline_frame).position(|(dot, scanline)| dot != scanline);
+        assert_eq!(first_difference, None, "ctrl {ctrl:#04X}, mask {mask:#04X}");
+        assert_eq!(dot_report, scanline_report);
+    }
+}
@@ -0,0 +1,546 @@
+use crate::ppu::{Ppu, OAM_ADDR_REG};
+use crate::bus::PpuBus;
+
+pub const SPRITES_PER_LINE: usize = 8;
+pub(super) const OAM_SPRITES: usize = 64;
+const EVALUATION_START: u16 = 65;
+const EVALUATION_END: u16 = 256;
+pub(super) const SPRITE_FETCH_START: u16 = 257;
+pub(super) const SPRITE_FETCH_END: u16 = 320;
+
+pub(super) const ATTRIBUTE_PALETTE: u8 = 0b0000_0011;
+pub(super) const ATTRIBUTE_BEHIND_BG: u8 = 0b0010_0000;
+pub(super) const ATTRIBUTE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const ATTRIBUTE_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+/// Sprite fetched for the scanline, pattern bytes are already flipped horizontally if needed
+#[derive(Debug, Clone, Copy, Default)]
+struct LineSprite {
+    pattern_lo: u8,
+    pattern_hi: u8,
+    attribute: u8,
+    x: u8,
+}
+
+/// Secondary OAM and sprite output units
+#[derive(Debug, Clone, Copy)]
+pub struct SpritePipeline {
+    secondary_oam: [u8; SPRITES_PER_LINE * 4],
+    sprites_found: usize,
+    sprite_zero_found: bool,
+    /// Evaluation position in OAM, byte index is also moved by overflow search bug
+    eval_sprite_n: usize,
+    eval_byte_m: usize,
+    /// Sprite where evaluation starts, it takes the role of sprite 0 for sprite 0 hit
+    eval_first_sprite: usize,
+    /// Sprite after the 8th found one, unlimited sprites enhancement continues from it
+    eval_extra_start: usize,
+    /// OAM byte read on the last odd dot of evaluation
+    eval_latch: u8,
+    eval_done: bool,
+    /// Sprites after the first 8 are here only with unlimited sprites enhancement
+    line_sprites: [LineSprite; OAM_SPRITES],
+    line_sprites_count: usize,
+    line_has_sprite_zero: bool,
+}
+
+impl Default for SpritePipeline {
+    fn default() -> Self {
+        Self {
+            secondary_oam: [0xFF; SPRITES_PER_LINE * 4],
+            sprites_found: 0,
+            sprite_zero_found: false,
+            eval_sprite_n: 0,
+            eval_byte_m: 0,
+            eval_first_sprite: 0,
+            eval_extra_start: OAM_SPRITES,
+            eval_latch: 0xFF,
+            eval_done: false,
+            line_sprites: [LineSprite::default(); OAM_SPRITES],
+            line_sprites_count: 0,
+            line_has_sprite_zero: false,
+        }
+    }
+}
+
+impl Ppu {
+    /// Sprite evaluation for the next scanline at dots 1-256 and sprite pattern fetches at dots 257-320
+    pub(super) fn process_sprites(&mut self, ppu_bus: &mut PpuBus) {
+        let dot = self.cycles_per_scanline;
+        let is_pre_render = self.scanline == self.region.pre_render_scanline();
+
+        if !is_pre_render {
+            if dot == 1 {
+                self.start_sprite_evaluation();
+            } else if (EVALUATION_START..=EVALUATION_END).contains(&dot) {
+                self.sprite_evaluation_step(dot);
+            }
+        }
+
+        if dot == SPRITE_FETCH_START {
+            if is_pre_render {
+                // No evaluation on pre-render line, so no sprites on the first visible line
+                self.start_sprite_evaluation();
+            }
+            self.sprites.line_sprites_count = self.sprites.sprites_found;
+            self.sprites.line_has_sprite_zero = self.sprites.sprite_zero_found;
+            if self.enhancements.unlimited_sprites && self.sprites.sprites_found == SPRITES_PER_LINE {
+                self.load_extra_sprites(ppu_bus);
+            }
+        }
+
+        if (SPRITE_FETCH_START..=SPRITE_FETCH_END).contains(&dot) {
+            self.registers[OAM_ADDR_REG] = 0;
+
+            let sprite_slot = ((dot - SPRITE_FETCH_START) / 8) as usize;
+            match (dot - SPRITE_FETCH_START) % 8 {
+                // Unused nametable fetches, seen only by mapper
+                0 | 2 => {
+                    self.fetch_nametable_byte(ppu_bus);
+                },
+                4 => {
+                    let pattern_address = self.sprite_pattern_address(sprite_slot);
+                    self.sprites.line_sprites[sprite_slot].pattern_lo = ppu_bus.read_8bit_ppu(pattern_address);
+                },
+                6 => {
+                    let pattern_address = self.sprite_pattern_address(sprite_slot) + 8;
+                    self.sprites.line_sprites[sprite_slot].pattern_hi = ppu_bus.read_8bit_ppu(pattern_address);
+                },
+                7 => self.load_line_sprite(sprite_slot),
+                _ => {},
+            }
+        }
+    }
+
+    /// Secondary OAM is cleared at dots 1-64, it's done at once as nothing reads it meanwhile.
+    /// Evaluation starts from OAMADDR, which is normally 0 after sprite fetches of the previous line
+    fn start_sprite_evaluation(&mut self) {
+        let oam_address = self.registers[OAM_ADDR_REG] as usize;
+        self.sprites.secondary_oam = [0xFF; SPRITES_PER_LINE * 4];
+        self.sprites.sprites_found = 0;
+        self.sprites.sprite_zero_found = false;
+        self.sprites.eval_sprite_n = oam_address >> 2;
+        self.sprites.eval_byte_m = oam_address & 0b11;
+        self.sprites.eval_first_sprite = self.sprites.eval_sprite_n;
+        self.sprites.eval_done = false;
+    }
+
+    /// Finds up to 8 sprites on the next scanline, copying them to secondary OAM. OAM is read on odd dots,
+    /// secondary OAM is written on even dots. After 8 sprites overflow search has hardware bug:
+    /// both sprite index and byte index are incremented
+    fn sprite_evaluation_step(&mut self, dot: u16) {
+        let sprites = &mut self.sprites;
+        if dot % 2 == 1 {
+            let oam_index = (sprites.eval_sprite_n % OAM_SPRITES) * 4 + sprites.eval_byte_m;
+            sprites.eval_latch = self.oam_data[oam_index];
+            return
+        }
+        if sprites.eval_done {
+            return
+        }
+
+        let row = self.scanline as i16 - sprites.eval_latch as i16;
+        let is_on_line = (0..self.ctrl_settings.sprite_size as i16).contains(&row);
+
+        if sprites.sprites_found < SPRITES_PER_LINE {
+            sprites.secondary_oam[sprites.sprites_found * 4 + sprites.eval_byte_m] = sprites.eval_latch;
+
+            if sprites.eval_byte_m == 0 && !is_on_line {
+                sprites.eval_sprite_n += 1;
+            } else {
+                if sprites.eval_sprite_n == sprites.eval_first_sprite {
+                    sprites.sprite_zero_found = true;
+                }
+                sprites.eval_byte_m += 1;
+                if sprites.eval_byte_m == 4 {
+                    sprites.eval_byte_m = 0;
+                    sprites.sprites_found += 1;
+                    sprites.eval_sprite_n += 1;
+                    sprites.eval_extra_start = sprites.eval_sprite_n;
+                }
+            }
+        } else if is_on_line {
+            self.ppu_status.set_sprite_overflow();
+            sprites.eval_done = true;
+        } else {
+            sprites.eval_sprite_n += 1;
+            sprites.eval_byte_m = (sprites.eval_byte_m + 1) % 4;
+        }
+
+        if sprites.eval_sprite_n == OAM_SPRITES {
+            sprites.eval_done = true;
+        }
+    }
+
+    /// $2004 read while rendering returns value on the internal OAM bus
+    pub(super) fn rendering_oam_read(&self) -> u8 {
+        match self.cycles_per_scanline {
+            1..=64 => 0xFF,
+            EVALUATION_START..=EVALUATION_END => self.sprites.eval_latch,
+            SPRITE_FETCH_START..=SPRITE_FETCH_END => {
+                let fetch_offset = (self.cycles_per_scanline - SPRITE_FETCH_START) as usize;
+                self.sprites.secondary_oam[(fetch_offset / 8) * 4 + (fetch_offset % 8).min(3)]
+            },
+            _ => self.sprites.secondary_oam[0],
+        }
+    }
+
+    fn sprite_pattern_address(&self, sprite_slot: usize) -> u16 {
+        let sprite_data = &self.sprites.secondary_oam[sprite_slot * 4..sprite_slot * 4 + 4];
+        let (y, tile, attribute) = (sprite_data[0], sprite_data[1], sprite_data[2]);
+
+        // Empty slots still fetch tile $FF
+        let row = if sprite_slot < self.sprites.sprites_found {
+            self.scanline.wrapping_sub(y as u16)
+        } else {
+            0
+        };
+        self.sprite_row_address(tile, attribute, row)
+    }
+
+    pub(super) fn sprite_row_address(&self, tile: u8, attribute: u8, mut row: u16) -> u16 {
+        if self.ctrl_settings.sprite_size == 16 {
+            if attribute & ATTRIBUTE_FLIP_VERTICAL != 0 {
+                row = 15 - row;
+            }
+            let table_address = ((tile & 0b0000_0001) as u16) << 12;
+            let tile_index = (tile & 0b1111_1110) as u16 + (row >> 3);
+            table_address + (tile_index << 4) + (row & 0b0111)
+        } else {
+            if attribute & ATTRIBUTE_FLIP_VERTICAL != 0 {
+                row = 7 - row;
+            }
+            self.ctrl_settings.sprite_pt_address + ((tile as u16) << 4) + (row & 0b0111)
+        }
+    }
+
+    /// Unlimited sprites enhancement: in range sprites which didn't fit into secondary OAM are
+    /// fetched at once. Reads are hidden from mapper, so CPU visible state is the same as without it
+    fn load_extra_sprites(&mut self, ppu_bus: &mut PpuBus) {
+        let sprite_size = self.ctrl_settings.sprite_size as u16;
+        for now_sprite in self.sprites.eval_extra_start..OAM_SPRITES {
+            let sprite_data = &self.oam_data[now_sprite * 4..now_sprite * 4 + 4];
+            let (y, tile, attribute, x) = (sprite_data[0], sprite_data[1], sprite_data[2], sprite_data[3]);
+            if self.scanline.wrapping_sub(y as u16) >= sprite_size {
+                continue
+            }
+
+            let pattern_address = self.sprite_row_address(tile, attribute, self.scanline.wrapping_sub(y as u16));
+            let mut line_sprite = LineSprite {
+                pattern_lo: ppu_bus.peek_8bit_ppu(pattern_address),
+                pattern_hi: ppu_bus.peek_8bit_ppu(pattern_address + 8),
+                attribute,
+                x,
+            };
+            if attribute & ATTRIBUTE_FLIP_HORIZONTAL != 0 {
+                line_sprite.pattern_lo = line_sprite.pattern_lo.reverse_bits();
+                line_sprite.pattern_hi = line_sprite.pattern_hi.reverse_bits();
+            }
+
+            self.sprites.line_sprites[self.sprites.line_sprites_count] = line_sprite;
+            self.sprites.line_sprites_count += 1;
+        }
+    }
+
+    fn load_line_sprite(&mut self, sprite_slot: usize) {
+        let line_sprite = &mut self.sprites.line_sprites[sprite_slot];
+        if sprite_slot >= self.sprites.line_sprites_count {
+            *line_sprite = LineSprite::default();
+            return
+        }
+
+        line_sprite.attribute = self.sprites.secondary_oam[sprite_slot * 4 + 2];
+        line_sprite.x = self.sprites.secondary_oam[sprite_slot * 4 + 3];
+        if line_sprite.attribute & ATTRIBUTE_FLIP_HORIZONTAL != 0 {
+            line_sprite.pattern_lo = line_sprite.pattern_lo.reverse_bits();
+            line_sprite.pattern_hi = line_sprite.pattern_hi.reverse_bits();
+        }
+    }
+
+    /// Returns (pixel, palette, behind background, is sprite zero) of the first opaque sprite
+    /// at current dot, pixel 0 is transparent
+    pub(super) fn sprite_pixel(&self, left_clip: bool) -> (u8, u8, bool, bool) {
+        let x = self.cycles_per_scanline - 1;
+
+        if !self.render_settings.sprite_render || (left_clip && x < 8 && !self.render_settings.leftmost_sprite_render) {
+            return (0, 0, false, false)
+        }
+
+        for (sprite_slot, line_sprite) in self.sprites.line_sprites[..self.sprites.line_sprites_count].iter().enumerate() {
+            let column = x.wrapping_sub(line_sprite.x as u16);
+            if column >= 8 {
+                continue
+            }
+
+            let bit = 7 - column;
+            let pixel = (((line_sprite.pattern_hi >> bit) & 1) << 1) | ((line_sprite.pattern_lo >> bit) & 1);
+            if pixel != 0 {
+                let palette = (line_sprite.attribute & ATTRIBUTE_PALETTE) + 4;
+                let behind_bg = line_sprite.attribute & ATTRIBUTE_BEHIND_BG != 0;
+                let is_sprite_zero = sprite_slot == 0 && self.sprites.line_has_sprite_zero;
+                return (pixel, palette, behind_bg, is_sprite_zero)
+            }
+        }
+
+        (0, 0, false, false)
+    }
+}
+
+#[test]
+fn test_sprite_rendering() {
+    use crate::bus::nrom_test_bus;
+    use crate::ppu::MirroringType;
+    use crate::ppu::{PPU_CTRL_REG, PPU_MASK_REG, SCREEN_WIDTH};
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+
+    // Tile 1: only top-left pixel is set (pixel 1), tile 2: solid pixel 3, tile 3: solid pixel 2
+    ppu_bus.write_8bit_ppu(0x0010u16, 0b1000_0000);
+    for now_row in 0..8u16 {
+        ppu_bus.write_8bit_ppu(0x0020 + now_row, 0xFF);
+        ppu_bus.write_8bit_ppu(0x0028 + now_row, 0xFF);
+        ppu_bus.write_8bit_ppu(0x0038 + now_row, 0xFF);
+    }
+
+    for (now_address, now_color) in [(0x3F00u16, 0x0F), (0x3F11, 0x16), (0x3F12, 0x21), (0x3F13, 0x27), (0x3F1A, 0x2A)] {
+        ppu_bus.write_8bit_ppu(now_address, now_color);
+    }
+
+    let render_frame = |ppu: &mut Ppu, ppu_bus: &mut PpuBus| {
+        ppu.scanline = 261;
+        ppu.cycles_per_scanline = 0;
+        ppu.execute_cycles(341 * 241, ppu_bus);
+    };
+
+    let mut ppu = Ppu::default();
+    ppu.write_to_registers(PPU_MASK_REG, 0b0001_0110);
+    ppu.oam_data.fill(0xF0);
+
+    // Sprite 0 at (10, 21) with tile 1, sprite 1 with both flips at (100, 51)
+    ppu.oam_data[0..4].copy_from_slice(&[20, 1, 0b0000_0000, 10]);
+    ppu.oam_data[4..8].copy_from_slice(&[50, 1, 0b1100_0000, 100]);
+    render_frame(&mut ppu, &mut ppu_bus);
+
+    let frame = ppu.frame_buffer();
+    assert_eq!(frame[21 * SCREEN_WIDTH + 10], 0x16);
+    assert_eq!(frame[21 * SCREEN_WIDTH + 11], 0x0F);
+    assert_eq!(frame[20 * SCREEN_WIDTH + 10], 0x0F);
+    assert_eq!(frame[58 * SCREEN_WIDTH + 107], 0x16);
+    assert_eq!(frame[51 * SCREEN_WIDTH + 100], 0x0F);
+    assert!(!ppu.ppu_status.is_sprite_overflow());
+
+    // Lower OAM index wins, sprite palette 2 selected by attributes
+    ppu.oam_data[0..4].copy_from_slice(&[30, 3, 0b0000_0010, 40]);
+    ppu.oam_data[4..8].copy_from_slice(&[30, 2, 0b0000_0000, 44]);
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[31 * SCREEN_WIDTH + 40..31 * SCREEN_WIDTH + 52], [
+        0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x27, 0x27, 0x27, 0x27
+    ]);
+
+    // 8x16 sprites: tile 2 ($0020) on top, tile 3 ($0030) on the bottom
+    ppu.write_to_registers(PPU_CTRL_REG, 0b0010_0000);
+    ppu.oam_data[0..4].copy_from_slice(&[100, 2, 0b0000_0000, 0]);
+    ppu.oam_data[4..8].fill(0xFF);
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH], 0x27);
+    assert_eq!(ppu.frame_buffer()[109 * SCREEN_WIDTH], 0x21);
+    assert_eq!(ppu.frame_buffer()[117 * SCREEN_WIDTH], 0x0F);
+
+    // Vertical flip of 8x16 swaps top and bottom tiles
+    ppu.oam_data[2] = 0b1000_0000;
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH], 0x21);
+    assert_eq!(ppu.frame_buffer()[109 * SCREEN_WIDTH], 0x27);
+
+    // Leftmost 8 pixels are clipped without leftmost sprites bit
+    ppu.write_to_registers(PPU_MASK_REG, 0b0001_0010);
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH + 7], 0x0F);
+}
+
+#[test]
+fn test_sprite_overflow_and_sprite_zero_hit() {
+    use crate::bus::nrom_test_bus;
+    use crate::ppu::MirroringType;
+    use crate::ppu::{PPU_MASK_REG, SCREEN_WIDTH};
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+
+    // Tile 1 is solid pixel 1, nametable is filled with tile 1 only in the first tile row
+    for now_row in 0..8u16 {
+        ppu_bus.write_8bit_ppu(0x0010 + now_row, 0xFF);
+    }
+    for now_tile in 0..32u16 {
+        ppu_bus.write_8bit_ppu(0x2000 + now_tile, 0x01);
+    }
+
+    let mut ppu = Ppu::default();
+    ppu.write_to_registers(PPU_MASK_REG, 0b0001_1110);
+
+    // 9 sprites on the same line set overflow, only 8 are rendered
+    for now_sprite in 0..9 {
+        ppu.oam_data[now_sprite * 4..now_sprite * 4 + 4].copy_from_slice(&[100, 1, 0, (now_sprite * 16) as u8]);
+    }
+    for now_sprite in 9..64 {
+        ppu.oam_data[now_sprite * 4..now_sprite * 4 + 4].copy_from_slice(&[0xF0, 0, 0, 0]);
+    }
+    ppu_bus.write_8bit_ppu(0x3F11u16, 0x16);
+
+    ppu.scanline = 261;
+    ppu.cycles_per_scanline = 0;
+    ppu.execute_cycles(341 * 241, &mut ppu_bus);
+    assert!(ppu.ppu_status.is_sprite_overflow());
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH + 7 * 16], 0x16);
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH + 8 * 16], 0x00);
+    assert!(!ppu.ppu_status.is_sprite_zero_hit());
+
+    // Hardware bug: with 8 sprites found, Y check goes to tile byte of the next sprite (byte m = 1)
+    ppu.oam_data[8 * 4..8 * 4 + 4].copy_from_slice(&[0xF0, 0xF0, 0, 0]);
+    ppu.oam_data[9 * 4..9 * 4 + 4].copy_from_slice(&[0xF0, 99, 0, 0]);
+    ppu.scanline = 261;
+    ppu.cycles_per_scanline = 0;
+    ppu.execute_cycles(2, &mut ppu_bus);
+    assert!(!ppu.ppu_status.is_sprite_overflow());
+    ppu.execute_cycles(341 * 241 - 2, &mut ppu_bus);
+    assert!(ppu.ppu_status.is_sprite_overflow());
+
+    // Sprite 0 hit happens exactly at the dot where opaque pixels overlap
+    for now_sprite in 0..64 {
+        ppu.oam_data[now_sprite * 4..now_sprite * 4 + 4].copy_from_slice(&[0xF0, 0, 0, 0]);
+    }
+    ppu.oam_data[0..4].copy_from_slice(&[4, 1, 0, 50]);
+    ppu.scanline = 261;
+    ppu.cycles_per_scanline = 0;
+    ppu.execute_cycles(341 + 341 * 5 + 51, &mut ppu_bus);
+    assert!(!ppu.ppu_status.is_sprite_zero_hit());
+    ppu.execute_cycles(1, &mut ppu_bus);
+    assert!(ppu.ppu_status.is_sprite_zero_hit());
+
+    // No hit with transparent background under sprite 0
+    ppu.oam_data[0..4].copy_from_slice(&[20, 1, 0, 50]);
+    ppu.scanline = 261;
+    ppu.cycles_per_scanline = 0;
+    ppu.execute_cycles(341 * 241, &mut ppu_bus);
+    assert!(!ppu.ppu_status.is_sprite_zero_hit());
+
+    // No hit at x = 255
+    ppu.oam_data[0..4].copy_from_slice(&[4, 1, 0, 255]);
+    ppu.scanline = 261;
+    ppu.cycles_per_scanline = 0;
+    ppu.execute_cycles(341 * 241, &mut ppu_bus);
+    assert!(!ppu.ppu_status.is_sprite_zero_hit());
+}
+
+#[test]
+fn test_oam_access() {
+    use crate::bus::nrom_test_bus;
+    use crate::ppu::MirroringType;
+    use crate::ppu::{PPU_MASK_REG, OAM_DATA_REG};
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+    let mut ppu = Ppu::default();
+
+    // Outside of rendering writes increment OAMADDR, reads don't. Unused attribute bits read as 0
+    ppu.write_to_registers(OAM_ADDR_REG, 1);
+    for now_byte in [0x12, 0xFF, 0x34] {
+        ppu.write_to_registers(OAM_DATA_REG, now_byte);
+    }
+    assert_eq!(ppu.oam_data[1..4], [0x12, 0xE3, 0x34]);
+    ppu.write_to_registers(OAM_ADDR_REG, 2);
+    assert_eq!(ppu.read_from_registers(OAM_DATA_REG), 0xE3);
+    assert_eq!(ppu.read_from_registers(OAM_DATA_REG), 0xE3);
+
+    ppu.oam_data.fill(0xF0);
+    ppu.oam_data[0..4].copy_from_slice(&[10, 0x01, 0x03, 20]);
+    ppu.oam_data[0x20..0x28].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    ppu.write_to_registers(PPU_MASK_REG, 0b0001_1000);
+
+    // Evaluation starts from the sprite at OAMADDR, sprite fetches clear OAMADDR for the next line
+    ppu.write_to_registers(OAM_ADDR_REG, 4);
+    ppu.scanline = 9;
+    ppu.cycles_per_scanline = 0;
+    ppu.execute_cycles(66, &mut ppu_bus);
+    assert_eq!(ppu.read_from_registers(OAM_DATA_REG), 0xF0);
+
+    // While rendering, $2004 shows secondary OAM clear, evaluation reads and sprite fetches
+    ppu.execute_cycles(341 - 66, &mut ppu_bus);
+    assert_eq!((ppu.scanline, ppu.cycles_per_scanline), (10, 0));
+    ppu.execute_cycles(30, &mut ppu_bus);
+    assert_eq!(ppu.read_from_registers(OAM_DATA_REG), 0xFF);
+    ppu.execute_cycles(66 - 30, &mut ppu_bus);
+    assert_eq!(ppu.read_from_registers(OAM_DATA_REG), 10);
+    ppu.execute_cycles(258 - 66, &mut ppu_bus);
+    assert_eq!(ppu.read_from_registers(OAM_DATA_REG), 0x01);
+    ppu.execute_cycles(2, &mut ppu_bus);
+    assert_eq!(ppu.read_from_registers(OAM_DATA_REG), 20);
+    assert_eq!(ppu.registers[OAM_ADDR_REG], 0);
+
+    // Writes during rendering are ignored, OAMADDR is bumped by 4
+    ppu.write_to_registers(OAM_ADDR_REG, 0x21);
+    ppu.write_to_registers(OAM_DATA_REG, 0xAA);
+    assert_eq!(ppu.registers[OAM_ADDR_REG], 0x25);
+    assert_eq!(ppu.oam_data[0x21], 2);
+
+    // Rendering start with OAMADDR >= 8 copies its 8 bytes row to the start of OAM
+    ppu.scanline = 261;
+    ppu.cycles_per_scanline = 0;
+    ppu.execute_cycles(2, &mut ppu_bus);
+    assert_eq!(ppu.oam_data[0..8], [1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn test_sprite_enhancements() {
+    use crate::bus::nrom_test_bus;
+    use crate::ppu::{MirroringType, PpuEnhancements};
+    use crate::ppu::{PPU_MASK_REG, SCREEN_WIDTH};
+
+    let mut bus = nrom_test_bus(&[], MirroringType::Horizontal);
+    let mut ppu_bus = bus.ppu_bus();
+
+    // Tile 1 is solid pixel 1, background has it only in the first tile row
+    for now_row in 0..8u16 {
+        ppu_bus.write_8bit_ppu(0x0010 + now_row, 0xFF);
+    }
+    for now_tile in 0..32u16 {
+        ppu_bus.write_8bit_ppu(0x2000 + now_tile, 0x01);
+    }
+    ppu_bus.write_8bit_ppu(0x3F01u16, 0x21);
+    ppu_bus.write_8bit_ppu(0x3F11u16, 0x16);
+
+    let render_frame = |ppu: &mut Ppu, ppu_bus: &mut PpuBus| {
+        ppu.scanline = 261;
+        ppu.cycles_per_scanline = 0;
+        ppu.ppu_status.clear_sprite_zero_hit();
+        ppu.execute_cycles(341 * 241, ppu_bus);
+    };
+
+    // 10 sprites on line 101, sprite 0 is at x = 0 on the background row
+    let mut ppu = Ppu::default();
+    ppu.write_to_registers(PPU_MASK_REG, 0b0001_1000);
+    ppu.oam_data.fill(0xF0);
+    ppu.oam_data[0..4].copy_from_slice(&[2, 1, 0, 0]);
+    for now_sprite in 1..11 {
+        ppu.oam_data[now_sprite * 4..now_sprite * 4 + 4].copy_from_slice(&[100, 1, 0, (now_sprite * 16) as u8]);
+    }
+
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert!(ppu.ppu_status.is_sprite_overflow());
+    assert!(!ppu.ppu_status.is_sprite_zero_hit());
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH + 8 * 16], 0x16);
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH + 9 * 16], 0x00);
+    assert_eq!(ppu.frame_buffer()[3 * SCREEN_WIDTH + 2], 0x00);
+    let accurate_frame = ppu.frame_buffer().to_vec();
+
+    // Enhancements draw extra sprites and leftmost pixels, flags stay the same
+    ppu.set_enhancements(PpuEnhancements { unlimited_sprites: true, disable_left_clip: true, crop_overscan: false });
+    render_frame(&mut ppu, &mut ppu_bus);
+    assert!(ppu.ppu_status.is_sprite_overflow());
+    assert!(!ppu.ppu_status.is_sprite_zero_hit());
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH + 9 * 16], 0x16);
+    assert_eq!(ppu.frame_buffer()[101 * SCREEN_WIDTH + 10 * 16 + 7], 0x16);
+    assert_eq!(ppu.frame_buffer()[3 * SCREEN_WIDTH + 2], 0x16);
+    assert_eq!(ppu.frame_buffer()[3 * SCREEN_WIDTH + 8], accurate_frame[3 * SCREEN_WIDTH + 8]);
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+
+/// Console timing variant. PAL and Dendy PPUs have 312 scanlines per frame,
+/// Dendy keeps NTSC-like CPU divider and starts vblank 50 lines later
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    /// PPU dots per CPU cycle as (numerator, denominator), 3.2 for PAL
+    pub const fn ppu_dots_per_cpu_cycle(&self) -> (usize, usize) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+
+    pub const fn cpu_clock_hz(&self) -> u32 {
+        match self {
+            Region::Ntsc => 1_789_773,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    pub const fn frames_per_second(&self) -> u32 {
+        match self {
+            Region::Ntsc => 60,
+            Region::Pal | Region::Dendy => 50,
+        }
+    }
+
+    pub const fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// Scanline with vblank flag set (and NMI raised) at dot 1
+    pub const fn v_blank_scanline(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    pub const fn pre_render_scanline(&self) -> u16 {
+        self.scanlines_per_frame() - 1
+    }
+
+    /// Only NTSC PPU shortens odd frames by one dot
+    pub const fn has_odd_frame_skip(&self) -> bool {
+        matches!(self, Region::Ntsc)
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Region::Ntsc => write!(f, "NTSC"),
+            Region::Pal => write!(f, "PAL"),
+            Region::Dendy => write!(f, "Dendy"),
+        }
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(region_name: &str) -> Result<Self, Self::Err> {
+        match region_name.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!("Unknown region '{region_name}', expected ntsc, pal or dendy")),
+        }
+    }
+}